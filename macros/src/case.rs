@@ -0,0 +1,50 @@
+/// Converts `snake_case` names to `PascalCase`.
+pub(crate) fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Converts `snake_case` names to `camelCase`, keeping leading underscores.
+pub(crate) fn camel_case(name: &str) -> String {
+    let trimmed = name.trim_start_matches('_');
+    let prefix = &name[..name.len() - trimmed.len()];
+    let mut parts = trimmed.split('_').filter(|part| !part.is_empty());
+    match parts.next() {
+        Some(first) => format!(
+            "{}{}{}",
+            prefix,
+            first,
+            pascal_case(&parts.collect::<Vec<_>>().join("_"))
+        ),
+        None => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{camel_case, pascal_case};
+
+    #[test]
+    fn it_converts_names_to_pascal_case() {
+        assert_eq!(pascal_case("concat"), "Concat");
+        assert_eq!(pascal_case("current_user"), "CurrentUser");
+        assert_eq!(pascal_case("__private__name"), "PrivateName");
+    }
+
+    #[test]
+    fn it_converts_names_to_camel_case() {
+        assert_eq!(camel_case("concat"), "concat");
+        assert_eq!(camel_case("front_window_name"), "frontWindowName");
+        assert_eq!(camel_case("_private_name"), "_privateName");
+        assert_eq!(camel_case("alreadyCamel"), "alreadyCamel");
+        assert_eq!(camel_case("__"), "__");
+    }
+}
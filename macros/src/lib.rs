@@ -0,0 +1,51 @@
+//! Procedural macros for the [osakit](https://docs.rs/osakit/) crate.
+//!
+//! Use the macros through their re-exports in `osakit` instead of depending on this crate directly.
+
+use proc_macro::TokenStream;
+
+mod case;
+mod mock;
+mod script;
+mod test_fn;
+
+/// Registers a test to be run on the main thread by `osakit::harness!()`.
+///
+/// Supports `#[ignore]`, `#[ignore = "reason"]`, `#[should_panic]` and
+/// `#[should_panic(expected = "message")]` attributes. The test function may return `()` or
+/// `Result<(), E>` where `E: Debug`.
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, input: TokenStream) -> TokenStream {
+    test_fn::expand(args.into(), input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generates a struct implementing the trait by calling script functions.
+///
+/// Accepts `language` (`"AppleScript"` or `"JavaScript"`), `source` (a string or an expression
+/// like `include_str!("finder.js")`) and optional struct `name` (trait name suffixed with `Script`
+/// by default). Functions without default implementations must take `&self`, their results are
+/// wrapped into `Result<T, osakit::ScriptFunctionRunError>`. Generated code refers to `::osakit`,
+/// `crate = "path"` sets another path, i.e. when the dependency is renamed.
+///
+/// Function names are converted to `camelCase` for `JavaScript`, `#[handler = "name"]` sets
+/// the handler name explicitly, `#[retry(attempts = N)]` retries transient failures.
+/// `None` values of `Option<T>` arguments are passed as `missing value` in `AppleScript` and
+/// omitted when trailing in `JavaScript`. Slice arguments (`&[T]`) are passed as separate
+/// arguments, so they have to be the last argument.
+#[proc_macro_attribute]
+pub fn script(args: TokenStream, input: TokenStream) -> TokenStream {
+    script::expand(args.into(), input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[doc(hidden)]
+#[proc_macro]
+pub fn __declare_script_mock(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as mock::MockInput);
+    mock::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,345 @@
+use crate::case::pascal_case;
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{
+    braced, parenthesized, parse_quote, Attribute, Expr, Ident, PathArguments, Result, ReturnType,
+    Token, Type, Visibility,
+};
+
+/// Input of `__declare_script_mock!` passed by `declare_script!`:
+/// `($crate) $vis $struct_name: $trait_name { $(fn ...;)* }`.
+pub(crate) struct MockInput {
+    krate: TokenStream,
+    vis: Visibility,
+    struct_name: Ident,
+    trait_name: Ident,
+    functions: Vec<ScriptFn>,
+}
+
+impl Parse for MockInput {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let krate;
+        parenthesized!(krate in input);
+        let krate = krate.parse()?;
+        let vis = input.parse()?;
+        let struct_name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let trait_name = input.parse()?;
+        let content;
+        braced!(content in input);
+        let mut functions = Vec::new();
+        while !content.is_empty() {
+            functions.push(content.parse()?);
+        }
+        Ok(Self {
+            krate,
+            vis,
+            struct_name,
+            trait_name,
+            functions,
+        })
+    }
+}
+
+/// Function declared in `declare_script!`: `fn name(arg: Type, arg: Type = default) -> Type;`.
+struct ScriptFn {
+    attrs: Vec<Attribute>,
+    ident: Ident,
+    args: Punctuated<ScriptArg, Token![,]>,
+    output: ReturnType,
+}
+
+impl Parse for ScriptFn {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        input.parse::<Visibility>()?;
+        input.parse::<Token![fn]>()?;
+        let ident = input.parse()?;
+        let content;
+        parenthesized!(content in input);
+        let args = content.parse_terminated(ScriptArg::parse, Token![,])?;
+        let output = input.parse()?;
+        input.parse::<Token![;]>()?;
+        Ok(Self {
+            attrs,
+            ident,
+            args,
+            output,
+        })
+    }
+}
+
+struct ScriptArg {
+    name: Ident,
+    ty: Type,
+}
+
+impl Parse for ScriptArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty: Type = input.parse()?;
+        // Arguments with default values are optional in the generated functions.
+        if input.parse::<Option<Token![=]>>()?.is_some() {
+            input.parse::<Expr>()?;
+            return Ok(Self {
+                name,
+                ty: parse_quote!(::core::option::Option<#ty>),
+            });
+        }
+        Ok(Self { name, ty })
+    }
+}
+
+struct Function<'a> {
+    docs: Vec<&'a Attribute>,
+    name: &'a Ident,
+    expectation: Ident,
+    arg_names: Vec<&'a Ident>,
+    arg_types: Vec<&'a Type>,
+    result: TokenStream,
+}
+
+impl<'a> Function<'a> {
+    fn new(function: &'a ScriptFn, krate: &TokenStream) -> Self {
+        let result = match &function.output {
+            ReturnType::Default => {
+                quote!(::core::result::Result<(), #krate::ScriptFunctionRunError>)
+            }
+            ReturnType::Type(_, ty) if is_result(ty) => quote!(#ty),
+            ReturnType::Type(_, ty) => {
+                quote!(::core::result::Result<#ty, #krate::ScriptFunctionRunError>)
+            }
+        };
+        Self {
+            docs: function
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("doc"))
+                .collect(),
+            name: &function.ident,
+            expectation: Ident::new(
+                &pascal_case(&function.ident.to_string()),
+                function.ident.span(),
+            ),
+            arg_names: function.args.iter().map(|arg| &arg.name).collect(),
+            arg_types: function.args.iter().map(|arg| &arg.ty).collect(),
+            result,
+        }
+    }
+}
+
+/// Functions declared with `-> Result<T, E>` return errors converted from `ScriptFunctionRunError`.
+fn is_result(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) if path.qself.is_none() && path.path.segments.len() == 1 => {
+            let segment = &path.path.segments[0];
+            segment.ident == "Result"
+                && matches!(
+                    &segment.arguments,
+                    PathArguments::AngleBracketed(arguments) if arguments.args.len() == 2
+                )
+        }
+        _ => false,
+    }
+}
+
+pub(crate) fn expand(input: MockInput) -> Result<TokenStream> {
+    let MockInput {
+        krate,
+        vis,
+        struct_name,
+        trait_name,
+        functions,
+    } = &input;
+    let functions: Vec<Function> = functions
+        .iter()
+        .map(|function| Function::new(function, krate))
+        .collect();
+    let mock_name = format_ident!("Mock{}", struct_name);
+    let module = Ident::new(&format!("__osakit_mock_{}", struct_name), Span::call_site());
+    let trait_doc = format!(
+        "Functions of [`{}`], implemented by [`{}`] for testing.",
+        struct_name, mock_name
+    );
+    let mock_doc = format!(
+        "Mock of [`{}`] serving results set using `expect_*()` methods.",
+        struct_name
+    );
+
+    let trait_fns = functions.iter().map(|function| {
+        let Function {
+            docs,
+            name,
+            arg_names,
+            arg_types,
+            result,
+            ..
+        } = function;
+        quote! {
+            #(#docs)*
+            fn #name(&self #(, #arg_names: #arg_types)*) -> #result;
+        }
+    });
+    let struct_fns = functions.iter().map(|function| {
+        let Function {
+            name,
+            arg_names,
+            arg_types,
+            result,
+            ..
+        } = function;
+        quote! {
+            fn #name(&self #(, #arg_names: #arg_types)*) -> #result {
+                #struct_name::#name(self #(, #arg_names)*)
+            }
+        }
+    });
+    let mock_fields = functions.iter().map(
+        |Function {
+             name, expectation, ..
+         }| { quote!(#name: #module::#expectation) },
+    );
+    let expect_fns = functions.iter().map(
+        |Function {
+             name, expectation, ..
+         }| {
+            let expect_name = format_ident!("expect_{}", name);
+            let doc = format!("Sets the expectation of [`{}::{}`].", trait_name, name);
+            quote! {
+                #[doc = #doc]
+                pub fn #expect_name(&mut self) -> &mut #module::#expectation {
+                    &mut self.#name
+                }
+            }
+        },
+    );
+    let mock_fns = functions.iter().map(|function| {
+        let Function {
+            name,
+            arg_names,
+            arg_types,
+            result,
+            ..
+        } = function;
+        // Mixed site hygiene keeps the closure argument from shadowing function arguments.
+        let function = Ident::new("function", Span::mixed_site());
+        quote! {
+            fn #name(&self #(, #arg_names: #arg_types)*) -> #result {
+                self.#name.0.call(|#function| #function(#(#arg_names),*))
+            }
+        }
+    });
+    let expectations = functions.iter().map(|function| {
+        let Function {
+            name,
+            expectation,
+            arg_types,
+            result,
+            ..
+        } = function;
+        let full_name = format!("{}::{}", mock_name, name);
+        quote! {
+            pub struct #expectation(pub(super) #krate::macros::MockFunction<dyn FnMut(#(#arg_types),*) -> #result>);
+
+            impl ::core::default::Default for #expectation {
+                fn default() -> Self {
+                    Self(#krate::macros::MockFunction::new(#full_name))
+                }
+            }
+
+            impl #expectation {
+                /// Sets the function computing results of the calls.
+                pub fn returning<F: FnMut(#(#arg_types),*) -> #result + 'static>(
+                    &mut self,
+                    function: F,
+                ) -> &mut Self {
+                    self.0.set_returning(Box::new(function));
+                    self
+                }
+
+                /// Sets the number of calls expected before the mock is dropped.
+                pub fn times(&mut self, times: usize) -> &mut Self {
+                    self.0.set_times(times);
+                    self
+                }
+
+                /// Expects no calls.
+                pub fn never(&mut self) -> &mut Self {
+                    self.times(0)
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        #[doc = #trait_doc]
+        #vis trait #trait_name {
+            #(#trait_fns)*
+        }
+
+        impl #trait_name for #struct_name {
+            #(#struct_fns)*
+        }
+
+        #[doc = #mock_doc]
+        #[derive(Default)]
+        #vis struct #mock_name {
+            #(#mock_fields,)*
+        }
+
+        impl #mock_name {
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            #(#expect_fns)*
+        }
+
+        impl #trait_name for #mock_name {
+            #(#mock_fns)*
+        }
+
+        #[doc(hidden)]
+        #[allow(non_snake_case)]
+        #vis mod #module {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #(#expectations)*
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{expand, MockInput};
+    use quote::quote;
+
+    #[test]
+    fn it_generates_mocks() {
+        let input: MockInput = syn::parse2(quote! {
+            (::osakit) pub MyJsScript: MyJsScriptApi {
+                /// Concatenates strings.
+                #[retry(attempts = 2)]
+                pub fn concat(x: &str, y: &str) -> String;
+                pub fn no_result(values: &[u8], flag: bool = true);
+                pub fn fallible() -> Result<u8, MyError>;
+                fn __current_user_name() -> String;
+            }
+        })
+        .unwrap();
+        let output = expand(input).unwrap().to_string();
+        assert!(output.contains("pub trait MyJsScriptApi"));
+        assert!(output.contains("pub struct MockMyJsScript"));
+        assert!(output.contains("pub fn expect_concat"));
+        assert!(output.contains("pub struct NoResult"));
+        assert!(output.contains("pub struct CurrentUserName"));
+        assert!(output.contains("# [doc = r\" Concatenates strings.\"]"));
+        assert!(!output.contains("retry"));
+        assert!(output.contains("FnMut (& [u8] , :: core :: option :: Option < bool >)"));
+        assert!(output.contains("fn fallible (& self) -> Result < u8 , MyError >"));
+    }
+}
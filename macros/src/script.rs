@@ -0,0 +1,470 @@
+use crate::case::camel_case;
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{
+    Attribute, Error, Expr, ExprLit, FnArg, Ident, ItemTrait, Lit, LitInt, LitStr, Meta, Pat, Path,
+    Result, ReturnType, TraitItem, TraitItemFn, Type,
+};
+
+const LANGUAGES: [&str; 2] = ["AppleScript", "JavaScript"];
+
+/// Arguments of `#[osakit::script(...)]`.
+struct ScriptArgs {
+    language: LitStr,
+    source: Expr,
+    name: Option<Ident>,
+    krate: Path,
+}
+
+impl ScriptArgs {
+    fn parse(args: TokenStream) -> Result<Self> {
+        let mut language: Option<LitStr> = None;
+        let mut source: Option<Expr> = None;
+        let mut name: Option<Ident> = None;
+        let mut krate: Option<Path> = None;
+        let parser = syn::meta::parser(|meta| {
+            if meta.path.is_ident("language") {
+                let value: LitStr = meta.value()?.parse()?;
+                if !LANGUAGES.contains(&value.value().as_str()) {
+                    return Err(Error::new(
+                        value.span(),
+                        format!(
+                            "unknown language, expected one of: {}",
+                            LANGUAGES.join(", ")
+                        ),
+                    ));
+                }
+                language = Some(value);
+                Ok(())
+            } else if meta.path.is_ident("source") {
+                source = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("name") {
+                let value: LitStr = meta.value()?.parse()?;
+                name = Some(value.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("crate") {
+                let value: LitStr = meta.value()?.parse()?;
+                krate = Some(value.parse()?);
+                Ok(())
+            } else {
+                Err(meta
+                    .error("unknown argument, expected `language`, `source`, `name` or `crate`"))
+            }
+        });
+        syn::parse::Parser::parse2(parser, args)?;
+        Ok(Self {
+            language: language
+                .ok_or_else(|| Error::new(Span::call_site(), "missing `language = \"...\"`"))?,
+            source: source
+                .ok_or_else(|| Error::new(Span::call_site(), "missing `source = \"...\"`"))?,
+            name,
+            krate: krate.unwrap_or_else(|| syn::parse_quote!(::osakit)),
+        })
+    }
+}
+
+/// Script function declared in the trait.
+struct Function {
+    item: TraitItemFn,
+    handler: String,
+    retry: Option<LitInt>,
+}
+
+impl Function {
+    fn new(mut item: TraitItemFn, language: &str) -> Result<Self> {
+        let sig = &item.sig;
+        if let Some(asyncness) = &sig.asyncness {
+            return Err(Error::new(
+                asyncness.span(),
+                "async functions are not supported in scripts",
+            ));
+        }
+        match sig.inputs.first() {
+            Some(FnArg::Receiver(receiver))
+                if receiver.reference.is_some() && receiver.mutability.is_none() => {}
+            Some(input) => {
+                return Err(Error::new_spanned(
+                    input,
+                    "expected `&self` as the first argument",
+                ))
+            }
+            None => {
+                return Err(Error::new(
+                    sig.paren_token.span.join(),
+                    "expected `&self` as the first argument",
+                ))
+            }
+        }
+        let last = sig.inputs.len() - 1;
+        for (index, input) in sig.inputs.iter().enumerate().skip(1) {
+            let arg = match input {
+                FnArg::Typed(arg) if matches!(&*arg.pat, Pat::Ident(_)) => arg,
+                _ => return Err(Error::new_spanned(input, "expected `name: Type`")),
+            };
+            if index != last && is_slice(&arg.ty) {
+                return Err(Error::new_spanned(
+                    &arg.ty,
+                    "slice arguments are passed as separate arguments and have to be the last \
+                    argument",
+                ));
+            }
+        }
+
+        let mut handler = None;
+        let mut retry = None;
+        let mut attrs: Vec<Attribute> = Vec::new();
+        for attr in item.attrs.drain(..) {
+            if attr.path().is_ident("handler") {
+                match &attr.meta {
+                    Meta::NameValue(name_value) => match &name_value.value {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Str(value),
+                            ..
+                        }) => handler = Some(value.value()),
+                        value => {
+                            return Err(Error::new_spanned(value, "expected a string literal"))
+                        }
+                    },
+                    meta => {
+                        return Err(Error::new_spanned(meta, "expected #[handler = \"name\"]"));
+                    }
+                }
+            } else if attr.path().is_ident("retry") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("attempts") {
+                        retry = Some(meta.value()?.parse::<LitInt>()?);
+                        Ok(())
+                    } else {
+                        Err(meta.error("expected `attempts = N`"))
+                    }
+                })?;
+            } else {
+                attrs.push(attr);
+            }
+        }
+        item.attrs = attrs;
+
+        let name = item.sig.ident.to_string();
+        let handler = handler.unwrap_or_else(|| match language {
+            "JavaScript" => camel_case(&name),
+            _ => name,
+        });
+        Ok(Self {
+            item,
+            handler,
+            retry,
+        })
+    }
+
+    /// Arguments together with the `ScriptArguments` methods pushing them: `None` values of
+    /// `Option<T>` arguments are passed as `missing value` or omitted when trailing in
+    /// `JavaScript`, slices are passed as separate arguments.
+    fn arguments(&self) -> Vec<(&Ident, Ident)> {
+        self.item
+            .sig
+            .inputs
+            .iter()
+            .filter_map(|input| match input {
+                FnArg::Typed(arg) => match &*arg.pat {
+                    Pat::Ident(pat) => Some((&pat.ident, push_method(&arg.ty))),
+                    _ => None,
+                },
+                FnArg::Receiver(_) => None,
+            })
+            .collect()
+    }
+}
+
+fn is_slice(ty: &Type) -> bool {
+    matches!(ty, Type::Reference(reference) if matches!(&*reference.elem, Type::Slice(_)))
+}
+
+fn push_method(ty: &Type) -> Ident {
+    let method = match ty {
+        Type::Path(path)
+            if path.qself.is_none()
+                && path.path.segments.len() == 1
+                && path.path.segments[0].ident == "Option" =>
+        {
+            "push_optional"
+        }
+        _ if is_slice(ty) => "push_variadic",
+        _ => "push_required",
+    };
+    Ident::new(method, Span::call_site())
+}
+
+pub(crate) fn expand(args: TokenStream, input: TokenStream) -> Result<TokenStream> {
+    let ScriptArgs {
+        language,
+        source,
+        name,
+        krate,
+    } = ScriptArgs::parse(args)?;
+    let mut item: ItemTrait = syn::parse2(input)?;
+    let struct_name = name.unwrap_or_else(|| format_ident!("{}Script", item.ident));
+    let language_variant = Ident::new(&language.value(), language.span());
+
+    let mut functions = Vec::new();
+    for trait_item in &mut item.items {
+        if let TraitItem::Fn(function) = trait_item {
+            // Functions with default implementations are left untouched.
+            if function.default.is_some() {
+                continue;
+            }
+            let mut parsed = Function::new(function.clone(), &language.value())?;
+            let output = match &parsed.item.sig.output {
+                ReturnType::Default => quote!(()),
+                ReturnType::Type(_, ty) => quote!(#ty),
+            };
+            let span = parsed.item.sig.output.span();
+            parsed.item.sig.output = syn::parse2(quote_spanned! {span=>
+                -> ::core::result::Result<#output, #krate::ScriptFunctionRunError>
+            })?;
+            let mut errors_doc = vec![
+                " ## Errors",
+                "",
+                " Returns [`osakit::ScriptFunctionRunError`] if arguments can not be serialized, the",
+                " script function fails or its result can not be deserialized.",
+            ];
+            if parsed
+                .item
+                .attrs
+                .iter()
+                .any(|attr| attr.path().is_ident("doc"))
+            {
+                errors_doc.insert(0, "");
+            }
+            for line in errors_doc {
+                parsed.item.attrs.push(syn::parse_quote!(#[doc = #line]));
+            }
+            *function = parsed.item.clone();
+            functions.push(parsed);
+        }
+    }
+
+    let trait_name = &item.ident;
+    let vis = &item.vis;
+    let (impl_generics, ty_generics, where_clause) = item.generics.split_for_impl();
+    let struct_doc = format!("Script implementing [`{}`].", trait_name);
+    let impl_fns = functions.iter().map(|function| {
+        let sig = &function.item.sig;
+        let handler = &function.handler;
+        let (arg_names, push_methods): (Vec<_>, Vec<_>) = function.arguments().into_iter().unzip();
+        let arg_name_strings = arg_names.iter().map(|name| name.to_string());
+        let retry = match &function.retry {
+            Some(attempts) => {
+                quote!(::core::option::Option::Some(#krate::RetryPolicy::new(#attempts)))
+            }
+            None => quote!(::core::option::Option::None),
+        };
+        quote! {
+            #sig {
+                #[allow(unused_mut)]
+                let mut arguments = #krate::macros::ScriptArguments::new();
+                #(arguments.#push_methods(#arg_name_strings, #arg_names)?;)*
+                #krate::macros::__exec_and_deserialize(
+                    &self.script,
+                    #handler,
+                    arguments.finish(self.script.language()),
+                    #retry,
+                )
+            }
+        }
+    });
+
+    Ok(quote! {
+        #item
+
+        #[doc = #struct_doc]
+        #vis struct #struct_name {
+            script: #krate::Script,
+        }
+
+        impl #struct_name {
+            /// Compiles the script.
+            #vis fn new() -> ::core::result::Result<Self, #krate::ScriptCompilationError> {
+                let mut script = #krate::Script::new_from_source(
+                    #krate::Language::#language_variant,
+                    #source,
+                );
+                script.compile()?;
+                ::core::result::Result::Ok(Self { script })
+            }
+        }
+
+        impl #impl_generics #trait_name #ty_generics for #struct_name #where_clause {
+            #(#impl_fns)*
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::expand;
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    fn error(args: TokenStream, input: TokenStream) -> String {
+        expand(args, input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn it_generates_script_struct() {
+        let output = expand(
+            quote!(
+                language = "JavaScript",
+                source = "function frontWindowName() {}"
+            ),
+            quote! {
+                pub trait Finder {
+                    /// Name of the front window.
+                    #[retry(attempts = 2)]
+                    fn front_window_name(&self) -> String;
+                    #[handler = "list_windows"]
+                    fn windows<'a>(&self, prefix: &'a str, limit: Option<usize>, names: &[&str]) -> Vec<String>;
+                    fn window_count(&self) -> usize {
+                        self.windows("").map(|windows| windows.len()).unwrap_or(0)
+                    }
+                }
+            },
+        )
+        .unwrap()
+        .to_string();
+        assert!(output.contains("pub struct FinderScript"));
+        assert!(output.contains("impl Finder for FinderScript"));
+        assert!(output.contains("\"frontWindowName\""));
+        assert!(output.contains("\"list_windows\""));
+        assert!(output.contains("RetryPolicy :: new (2)"));
+        assert!(output.contains("arguments . push_required (\"prefix\" , prefix)"));
+        assert!(output.contains("arguments . push_optional (\"limit\" , limit)"));
+        assert!(output.contains("arguments . push_variadic (\"names\" , names)"));
+        assert!(output.contains("ScriptFunctionRunError > ;"));
+        assert!(!output.contains("# [handler"));
+        assert!(!output.contains("\"windowCount\""));
+    }
+
+    #[test]
+    fn it_keeps_applescript_names() {
+        let output = expand(
+            quote!(language = "AppleScript", source = "", name = "Finder"),
+            quote!(
+                trait FinderApi {
+                    fn front_window_name(&self) -> String;
+                }
+            ),
+        )
+        .unwrap()
+        .to_string();
+        assert!(output.contains("struct Finder"));
+        assert!(output.contains("\"front_window_name\""));
+    }
+
+    #[test]
+    fn it_uses_custom_crate_paths() {
+        let output = expand(
+            quote!(language = "AppleScript", source = "", crate = "::osa"),
+            quote!(
+                trait Finder {
+                    fn name(&self) -> String;
+                }
+            ),
+        )
+        .unwrap()
+        .to_string();
+        assert!(output.contains(":: osa :: Script :: new_from_source"));
+        assert!(output.contains(":: osa :: macros :: ScriptArguments"));
+        assert!(!output.contains("osakit ::"));
+    }
+
+    #[test]
+    fn it_reports_errors() {
+        let item = quote!(
+            trait Finder {
+                fn name(&self) -> String;
+            }
+        );
+        assert_eq!(
+            error(quote!(language = "Python", source = ""), item.clone()),
+            "unknown language, expected one of: AppleScript, JavaScript"
+        );
+        assert_eq!(
+            error(quote!(language = "JavaScript"), item.clone()),
+            "missing `source = \"...\"`"
+        );
+        assert_eq!(
+            error(quote!(language = "JavaScript", src = ""), item),
+            "unknown argument, expected `language`, `source`, `name` or `crate`"
+        );
+        let args = quote!(language = "JavaScript", source = "");
+        assert_eq!(
+            error(
+                args.clone(),
+                quote!(
+                    trait Finder {
+                        fn name(&mut self);
+                    }
+                )
+            ),
+            "expected `&self` as the first argument"
+        );
+        assert_eq!(
+            error(
+                args.clone(),
+                quote!(
+                    trait Finder {
+                        fn name();
+                    }
+                )
+            ),
+            "expected `&self` as the first argument"
+        );
+        assert_eq!(
+            error(
+                args.clone(),
+                quote!(
+                    trait Finder {
+                        async fn name(&self);
+                    }
+                )
+            ),
+            "async functions are not supported in scripts"
+        );
+        assert_eq!(
+            error(
+                args.clone(),
+                quote!(
+                    trait Finder {
+                        fn name(&self, (a, b): (u8, u8));
+                    }
+                )
+            ),
+            "expected `name: Type`"
+        );
+        assert_eq!(
+            error(
+                args.clone(),
+                quote!(
+                    trait Finder {
+                        fn open(&self, paths: &[&str], new_window: bool);
+                    }
+                )
+            ),
+            "slice arguments are passed as separate arguments and have to be the last argument"
+        );
+        assert_eq!(
+            error(
+                args,
+                quote!(
+                    trait Finder {
+                        #[handler(name)]
+                        fn name(&self);
+                    }
+                )
+            ),
+            "expected #[handler = \"name\"]"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{Attribute, Error, Expr, ExprLit, ItemFn, Lit, LitStr, Meta, Result, ReturnType};
+
+pub(crate) fn expand(args: TokenStream, input: TokenStream) -> Result<TokenStream> {
+    if !args.is_empty() {
+        return Err(Error::new(
+            args.span(),
+            "#[osakit::test] does not accept arguments",
+        ));
+    }
+
+    let mut function: ItemFn = syn::parse2(input)?;
+    validate_signature(&function)?;
+
+    let mut ignore = quote!(::core::option::Option::None);
+    let mut should_panic: Option<Option<LitStr>> = None;
+    let mut attrs: Vec<Attribute> = Vec::new();
+    for attr in function.attrs.drain(..) {
+        if attr.path().is_ident("ignore") {
+            let reason = match &attr.meta {
+                Meta::Path(_) => String::new(),
+                Meta::NameValue(name_value) => string_literal(&name_value.value)?.value(),
+                Meta::List(list) => {
+                    return Err(Error::new_spanned(list, "expected #[ignore = \"reason\"]"));
+                }
+            };
+            ignore = quote!(::core::option::Option::Some(#reason));
+        } else if attr.path().is_ident("should_panic") {
+            should_panic = Some(match &attr.meta {
+                Meta::Path(_) => None,
+                Meta::NameValue(name_value) => Some(string_literal(&name_value.value)?),
+                Meta::List(list) => {
+                    let mut expected = None;
+                    list.parse_nested_meta(|meta| {
+                        if meta.path.is_ident("expected") {
+                            expected = Some(meta.value()?.parse::<LitStr>()?);
+                            Ok(())
+                        } else {
+                            Err(meta.error("expected `expected = \"message\"`"))
+                        }
+                    })?;
+                    expected
+                }
+            });
+        } else {
+            attrs.push(attr);
+        }
+    }
+    function.attrs = attrs;
+
+    if should_panic.is_some() && matches!(function.sig.output, ReturnType::Type(..)) {
+        return Err(Error::new_spanned(
+            &function.sig.output,
+            "functions using #[should_panic] must return `()`",
+        ));
+    }
+
+    let should_panic = match should_panic {
+        None => quote!(::osakit::testing::ShouldPanic::No),
+        Some(None) => quote!(::osakit::testing::ShouldPanic::Yes),
+        Some(Some(expected)) => quote!(::osakit::testing::ShouldPanic::WithMessage(#expected)),
+    };
+    let ident = &function.sig.ident;
+    let name = ident.to_string();
+    let register = format_ident!("__osakit_register_{}", ident);
+
+    Ok(quote! {
+        #function
+
+        #[doc(hidden)]
+        #[::osakit::testing::__ctor]
+        fn #register() {
+            ::osakit::testing::__register(::osakit::testing::TestDefinition {
+                module_path: ::core::module_path!(),
+                name: #name,
+                function: || ::osakit::testing::TestResult::into_result(#ident()),
+                ignore: #ignore,
+                should_panic: #should_panic,
+            });
+        }
+    })
+}
+
+fn validate_signature(function: &ItemFn) -> Result<()> {
+    let sig = &function.sig;
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(Error::new(
+            asyncness.span(),
+            "async functions are not supported by #[osakit::test]",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            "functions used as tests can not have generic parameters",
+        ));
+    }
+    if !sig.inputs.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.inputs,
+            "functions used as tests can not have any arguments",
+        ));
+    }
+    Ok(())
+}
+
+fn string_literal(expr: &Expr) -> Result<LitStr> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(literal),
+            ..
+        }) => Ok(literal.clone()),
+        _ => Err(Error::new_spanned(expr, "expected a string literal")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::expand;
+    use quote::quote;
+
+    #[test]
+    fn it_registers_tests() {
+        let output = expand(
+            quote!(),
+            quote! {
+                #[ignore = "slow"]
+                #[should_panic(expected = "boom")]
+                fn it_works() {}
+            },
+        )
+        .unwrap()
+        .to_string();
+        assert!(output.contains("fn __osakit_register_it_works"));
+        assert!(output.contains("name : \"it_works\""));
+        assert!(output.contains("Some (\"slow\")"));
+        assert!(output.contains("ShouldPanic :: WithMessage (\"boom\")"));
+        assert!(!output.contains("# [ignore"));
+    }
+
+    #[test]
+    fn it_rejects_unsupported_functions() {
+        let error = |input| expand(quote!(), input).unwrap_err().to_string();
+        assert_eq!(
+            error(quote!(
+                fn it_works(x: u32) {}
+            )),
+            "functions used as tests can not have any arguments"
+        );
+        assert_eq!(
+            error(quote!(
+                async fn it_works() {}
+            )),
+            "async functions are not supported by #[osakit::test]"
+        );
+        assert_eq!(
+            error(quote!(
+                #[should_panic]
+                fn it_works() -> Result<(), String> {
+                    Ok(())
+                }
+            )),
+            "functions using #[should_panic] must return `()`"
+        );
+        assert_eq!(
+            expand(
+                quote!(arg),
+                quote!(
+                    fn it_works() {}
+                )
+            )
+            .unwrap_err()
+            .to_string(),
+            "#[osakit::test] does not accept arguments"
+        );
+    }
+
+    #[test]
+    fn it_points_errors_at_offending_tokens() {
+        // `compile_error!` starts at the first offending token and ends at the last one, since
+        // spans cannot be joined on stable compilers.
+        let columns = |input: &str| {
+            let error = expand(quote!(), input.parse().unwrap()).unwrap_err();
+            let tokens: Vec<_> = error.to_compile_error().into_iter().collect();
+            let first = tokens.first().unwrap().span();
+            let last = tokens.last().unwrap().span();
+            (
+                first.start().column,
+                first.end().column,
+                last.start().column,
+                last.end().column,
+            )
+        };
+        assert_eq!(columns("fn it_works(x: u32, y: u32) {}"), (12, 13, 23, 26));
+        assert_eq!(columns("fn it_works<T>() {}"), (11, 12, 13, 14));
+        assert_eq!(
+            columns("#[should_panic] fn it_works() -> u32 { 1 }"),
+            (30, 31, 33, 36)
+        );
+        assert_eq!(columns("#[ignore(slow)] fn it_works() {}"), (2, 8, 8, 14));
+    }
+}
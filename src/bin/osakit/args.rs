@@ -0,0 +1,391 @@
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub(crate) const USAGE: &str = "Usage:
+    osakit run <FILE> [OPTIONS]
+    osakit run --eval <SOURCE> [OPTIONS]
+    osakit repl [--language <NAME>]
+    osakit serve [--socket <PATH>] [--timeout-ms <MS>]
+    osakit --help
+    osakit --version
+
+Options:
+    -e, --eval <SOURCE>      Runs inline source instead of a file
+    -l, --language <NAME>    Script language: applescript (default) or javascript,
+                             detected from the file extension if not specified
+        --handler <NAME>     Calls the handler instead of running the script
+        --args <JSON>        Handler arguments as a JSON array, defaults to []
+    -f, --format <FORMAT>    Output format: json (default), pretty or aeprint
+        --check              Only compiles the script
+        --socket <PATH>      Serves JSON-RPC on a Unix domain socket instead of stdio
+        --timeout-ms <MS>    Default request timeout, requests may override it
+                             using the `timeoutMs` parameter
+";
+
+/// Language of the executed script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ScriptLanguage {
+    AppleScript,
+    JavaScript,
+}
+
+impl ScriptLanguage {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "applescript" | "as" => Some(ScriptLanguage::AppleScript),
+            "javascript" | "js" | "jxa" => Some(ScriptLanguage::JavaScript),
+            _ => None,
+        }
+    }
+
+    fn from_extension(path: &std::path::Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "applescript" | "scpt" => Some(ScriptLanguage::AppleScript),
+            "js" => Some(ScriptLanguage::JavaScript),
+            _ => None,
+        }
+    }
+}
+
+/// Format used to print results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum OutputFormat {
+    #[default]
+    Json,
+    Pretty,
+    AePrint,
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Some(OutputFormat::Json),
+            "pretty" => Some(OutputFormat::Pretty),
+            "aeprint" => Some(OutputFormat::AePrint),
+            _ => None,
+        }
+    }
+}
+
+/// Source of the executed script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ScriptSource {
+    File(PathBuf),
+    Inline(String),
+}
+
+/// Options of the `run` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RunOptions {
+    pub(crate) source: ScriptSource,
+    pub(crate) language: ScriptLanguage,
+    pub(crate) handler: Option<String>,
+    pub(crate) arguments: Vec<Value>,
+    pub(crate) format: OutputFormat,
+    pub(crate) check: bool,
+}
+
+/// Options of the `serve` command.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct ServeOptions {
+    pub(crate) socket: Option<PathBuf>,
+    pub(crate) timeout: Option<Duration>,
+}
+
+/// Parsed command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Command {
+    Run(RunOptions),
+    Repl(ScriptLanguage),
+    Serve(ServeOptions),
+    Help,
+    Version,
+}
+
+/// Invalid command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ArgsError(pub(crate) String);
+
+impl Display for ArgsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parses command line arguments, excluding the program name.
+pub(crate) fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, ArgsError> {
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        None | Some("-h" | "--help" | "help") => Ok(Command::Help),
+        Some("-V" | "--version") => Ok(Command::Version),
+        Some("run") => parse_run(args).map(Command::Run),
+        Some("repl") => parse_repl(args).map(Command::Repl),
+        Some("serve") => parse_serve(args).map(Command::Serve),
+        Some(command) => Err(ArgsError(format!("unknown command `{}`", command))),
+    }
+}
+
+fn parse_language(name: &str) -> Result<ScriptLanguage, ArgsError> {
+    ScriptLanguage::from_name(name).ok_or_else(|| ArgsError(format!("unknown language `{}`", name)))
+}
+
+fn parse_repl<I: Iterator<Item = String>>(mut args: I) -> Result<ScriptLanguage, ArgsError> {
+    let mut language = ScriptLanguage::AppleScript;
+    while let Some(arg) = args.next() {
+        let name = match arg.split_once('=') {
+            Some(("--language", name)) => name.to_string(),
+            _ if arg == "-l" || arg == "--language" => args
+                .next()
+                .ok_or_else(|| ArgsError(format!("missing value for `{}`", arg)))?,
+            _ => return Err(ArgsError(format!("unexpected argument `{}`", arg))),
+        };
+        language = parse_language(&name)?;
+    }
+    Ok(language)
+}
+
+fn parse_serve<I: Iterator<Item = String>>(mut args: I) -> Result<ServeOptions, ArgsError> {
+    let mut options = ServeOptions::default();
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_string(), value.to_string()),
+            _ if arg == "--socket" || arg == "--timeout-ms" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| ArgsError(format!("missing value for `{}`", arg)))?;
+                (arg, value)
+            }
+            _ => return Err(ArgsError(format!("unexpected argument `{}`", arg))),
+        };
+        match name.as_str() {
+            "--socket" => options.socket = Some(PathBuf::from(value)),
+            "--timeout-ms" => {
+                let milliseconds = value
+                    .parse()
+                    .map_err(|_| ArgsError(format!("invalid timeout `{}`", value)))?;
+                options.timeout = Some(Duration::from_millis(milliseconds));
+            }
+            _ => return Err(ArgsError(format!("unknown option `{}`", name))),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_run<I: Iterator<Item = String>>(mut args: I) -> Result<RunOptions, ArgsError> {
+    let mut file = None;
+    let mut inline = None;
+    let mut language = None;
+    let mut handler = None;
+    let mut arguments = None;
+    let mut format = OutputFormat::default();
+    let mut check = false;
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| {
+            inline_value
+                .map(|value| value.to_string())
+                .or_else(|| args.next())
+                .ok_or_else(|| ArgsError(format!("missing value for `{}`", name)))
+        };
+        match name.as_str() {
+            "-e" | "--eval" => inline = Some(value(&name)?),
+            "-l" | "--language" => language = Some(parse_language(&value(&name)?)?),
+            "--handler" => handler = Some(value(&name)?),
+            "--args" => {
+                let json = value(&name)?;
+                arguments = match serde_json::from_str(&json) {
+                    Ok(Value::Array(values)) => Some(values),
+                    Ok(_) => return Err(ArgsError(String::from("`--args` must be a JSON array"))),
+                    Err(error) => {
+                        return Err(ArgsError(format!("invalid JSON in `--args`: {}", error)))
+                    }
+                };
+            }
+            "-f" | "--format" => {
+                let name = value(&name)?;
+                format = OutputFormat::from_name(&name)
+                    .ok_or_else(|| ArgsError(format!("unknown format `{}`", name)))?;
+            }
+            "--check" => check = true,
+            _ if name.starts_with('-') && name != "-" => {
+                return Err(ArgsError(format!("unknown option `{}`", name)));
+            }
+            _ if file.is_none() => file = Some(PathBuf::from(arg)),
+            _ => return Err(ArgsError(format!("unexpected argument `{}`", arg))),
+        }
+    }
+    let source = match (file, inline) {
+        (Some(file), None) => ScriptSource::File(file),
+        (None, Some(source)) => ScriptSource::Inline(source),
+        (None, None) => return Err(ArgsError(String::from("missing script file or `--eval`"))),
+        (Some(_), Some(_)) => {
+            return Err(ArgsError(String::from(
+                "script file and `--eval` cannot be used together",
+            )))
+        }
+    };
+    let language = language
+        .or(match &source {
+            ScriptSource::File(path) => ScriptLanguage::from_extension(path),
+            ScriptSource::Inline(_) => None,
+        })
+        .unwrap_or(ScriptLanguage::AppleScript);
+    if arguments.is_some() && handler.is_none() {
+        return Err(ArgsError(String::from("`--args` requires `--handler`")));
+    }
+    Ok(RunOptions {
+        source,
+        language,
+        handler,
+        arguments: arguments.unwrap_or_default(),
+        format,
+        check,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn parse_args(args: &[&str]) -> Result<Command, ArgsError> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn it_parses_handler_calls() {
+        assert_eq!(
+            parse_args(&[
+                "run",
+                "foo.applescript",
+                "--handler",
+                "concat",
+                "--args",
+                "[\"a\",\"b\"]",
+                "--format=pretty"
+            ]),
+            Ok(Command::Run(RunOptions {
+                source: ScriptSource::File(PathBuf::from("foo.applescript")),
+                language: ScriptLanguage::AppleScript,
+                handler: Some(String::from("concat")),
+                arguments: vec![json!("a"), json!("b")],
+                format: OutputFormat::Pretty,
+                check: false,
+            }))
+        );
+    }
+
+    #[test]
+    fn it_parses_inline_sources() {
+        assert_eq!(
+            parse_args(&["run", "-e", "output = 1;", "-l", "js", "--check"]),
+            Ok(Command::Run(RunOptions {
+                source: ScriptSource::Inline(String::from("output = 1;")),
+                language: ScriptLanguage::JavaScript,
+                handler: None,
+                arguments: vec![],
+                format: OutputFormat::Json,
+                check: true,
+            }))
+        );
+    }
+
+    #[test]
+    fn it_detects_language_from_extension() {
+        match parse_args(&["run", "scripts/tabs.js"]) {
+            Ok(Command::Run(options)) => assert_eq!(options.language, ScriptLanguage::JavaScript),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match parse_args(&["run", "tabs.js", "--language", "applescript"]) {
+            Ok(Command::Run(options)) => assert_eq!(options.language, ScriptLanguage::AppleScript),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_parses_repl() {
+        assert_eq!(
+            parse_args(&["repl"]),
+            Ok(Command::Repl(ScriptLanguage::AppleScript))
+        );
+        assert_eq!(
+            parse_args(&["repl", "--language", "js"]),
+            Ok(Command::Repl(ScriptLanguage::JavaScript))
+        );
+        assert_eq!(
+            parse_args(&["repl", "--language=jxa"]),
+            Ok(Command::Repl(ScriptLanguage::JavaScript))
+        );
+        assert_eq!(
+            parse_args(&["repl", "-l", "python"]),
+            Err(ArgsError(String::from("unknown language `python`")))
+        );
+    }
+
+    #[test]
+    fn it_parses_serve() {
+        assert_eq!(
+            parse_args(&["serve"]),
+            Ok(Command::Serve(ServeOptions::default()))
+        );
+        assert_eq!(
+            parse_args(&["serve", "--socket", "/tmp/osakit.sock", "--timeout-ms=1500"]),
+            Ok(Command::Serve(ServeOptions {
+                socket: Some(PathBuf::from("/tmp/osakit.sock")),
+                timeout: Some(Duration::from_millis(1500)),
+            }))
+        );
+        assert_eq!(
+            parse_args(&["serve", "--timeout-ms", "soon"]),
+            Err(ArgsError(String::from("invalid timeout `soon`")))
+        );
+        assert_eq!(
+            parse_args(&["serve", "--verbose=1"]),
+            Err(ArgsError(String::from("unknown option `--verbose`")))
+        );
+    }
+
+    #[test]
+    fn it_parses_help_and_version() {
+        assert_eq!(parse_args(&[]), Ok(Command::Help));
+        assert_eq!(parse_args(&["--help"]), Ok(Command::Help));
+        assert_eq!(parse_args(&["--version"]), Ok(Command::Version));
+    }
+
+    #[test]
+    fn it_rejects_invalid_arguments() {
+        for (args, message) in [
+            (&["build"][..], "unknown command `build`"),
+            (&["run"][..], "missing script file or `--eval`"),
+            (
+                &["run", "a.js", "--handler"][..],
+                "missing value for `--handler`",
+            ),
+            (
+                &["run", "a.js", "--handler", "f", "--args", "{}"][..],
+                "`--args` must be a JSON array",
+            ),
+            (
+                &["run", "a.js", "--args", "[]"][..],
+                "`--args` requires `--handler`",
+            ),
+            (&["run", "a.js", "-f", "xml"][..], "unknown format `xml`"),
+            (
+                &["run", "a.js", "--verbose"][..],
+                "unknown option `--verbose`",
+            ),
+            (&["run", "a.js", "b.js"][..], "unexpected argument `b.js`"),
+            (
+                &["run", "a.js", "-e", "1"][..],
+                "script file and `--eval` cannot be used together",
+            ),
+        ] {
+            assert_eq!(parse_args(args), Err(ArgsError(String::from(message))));
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use crate::args::ScriptLanguage;
+
+/// Returns `true` if the input forms a complete block and can be executed, `false` if more
+/// lines are expected: unclosed `tell ... end tell` and similar blocks, strings, comments and
+/// line continuations in `AppleScript`, unclosed brackets, strings and comments in `JavaScript`.
+pub(crate) fn is_complete(language: ScriptLanguage, input: &str) -> bool {
+    match language {
+        ScriptLanguage::AppleScript => is_apple_script_complete(input),
+        ScriptLanguage::JavaScript => is_java_script_complete(input),
+    }
+}
+
+fn is_apple_script_complete(input: &str) -> bool {
+    let mut depth = 0usize;
+    let mut comment_depth = 0usize;
+    let mut in_string = false;
+    let mut continued = false;
+    for line in input.lines() {
+        let mut code = String::new();
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if comment_depth > 0 {
+                match (c, chars.peek()) {
+                    ('(', Some('*')) => {
+                        chars.next();
+                        comment_depth += 1;
+                    }
+                    ('*', Some(')')) => {
+                        chars.next();
+                        comment_depth -= 1;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if in_string {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                // Strings are replaced with a placeholder, so keywords inside are ignored.
+                continue;
+            }
+            match (c, chars.peek()) {
+                ('"', _) => {
+                    in_string = true;
+                    code.push_str("\"\"");
+                }
+                ('(', Some('*')) => {
+                    chars.next();
+                    comment_depth += 1;
+                }
+                ('-', Some('-')) | ('#', _) => break,
+                _ => code.push(c),
+            }
+        }
+        let code = code.trim();
+        continued = code.ends_with('¬');
+        if in_string || comment_depth > 0 || code.is_empty() {
+            continue;
+        }
+        let words: Vec<String> = code
+            .split_whitespace()
+            .map(|word| word.to_lowercase())
+            .collect();
+        let first = words[0].as_str();
+        let second = words.get(1).map(|word| word.as_str());
+        let opens_block = match first {
+            "tell" => !words.iter().any(|word| word == "to"),
+            "if" => words.last().is_some_and(|word| word == "then"),
+            "repeat" | "try" | "considering" | "ignoring" | "script" => true,
+            "on" => second != Some("error"),
+            "to" => second.is_some(),
+            "using" => second == Some("terms"),
+            "with" => matches!(second, Some("timeout" | "transaction")),
+            _ => false,
+        };
+        if opens_block {
+            depth += 1;
+        } else if first == "end" {
+            depth = depth.saturating_sub(1);
+        }
+    }
+    depth == 0 && comment_depth == 0 && !in_string && !continued
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JsContext {
+    Bracket(char),
+    Template,
+    Substitution,
+}
+
+fn is_java_script_complete(input: &str) -> bool {
+    let mut stack: Vec<JsContext> = Vec::new();
+    let mut string_quote: Option<char> = None;
+    let mut block_comment = false;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if block_comment {
+            if c == '*' && chars.peek() == Some(&'/') {
+                chars.next();
+                block_comment = false;
+            }
+            continue;
+        }
+        if let Some(quote) = string_quote {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                // Unterminated strings cannot continue on the next line, so the input is
+                // treated as complete and the syntax error is reported on execution.
+                '\n' => string_quote = None,
+                _ if c == quote => string_quote = None,
+                _ => {}
+            }
+            continue;
+        }
+        if stack.last() == Some(&JsContext::Template) {
+            match (c, chars.peek()) {
+                ('\\', _) => {
+                    chars.next();
+                }
+                ('`', _) => {
+                    stack.pop();
+                }
+                ('$', Some('{')) => {
+                    chars.next();
+                    stack.push(JsContext::Substitution);
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                block_comment = true;
+            }
+            ('"' | '\'', _) => string_quote = Some(c),
+            ('`', _) => stack.push(JsContext::Template),
+            ('(' | '[' | '{', _) => stack.push(JsContext::Bracket(c)),
+            ('}', _) if stack.last() == Some(&JsContext::Substitution) => {
+                stack.pop();
+            }
+            (')' | ']' | '}', _) => match stack.pop() {
+                Some(JsContext::Bracket(_)) => {}
+                // Unbalanced closing brackets are syntax errors, reported on execution.
+                _ => return true,
+            },
+            _ => {}
+        }
+    }
+    stack.is_empty() && !block_comment
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn as_complete(input: &str) -> bool {
+        is_complete(ScriptLanguage::AppleScript, input)
+    }
+
+    fn js_complete(input: &str) -> bool {
+        is_complete(ScriptLanguage::JavaScript, input)
+    }
+
+    #[test]
+    fn it_detects_apple_script_blocks() {
+        assert!(as_complete("return 1"));
+        assert!(!as_complete("tell application \"Finder\""));
+        assert!(as_complete(
+            "tell application \"Finder\"\nget name of window 1\nend tell"
+        ));
+        assert!(as_complete("tell application \"Finder\" to activate"));
+        assert!(!as_complete(
+            "repeat with i from 1 to 3\nif i > 1 then\nlog i"
+        ));
+        assert!(as_complete(
+            "repeat with i from 1 to 3\nif i > 1 then\nlog i\nelse\nlog 0\nend if\nend repeat"
+        ));
+        assert!(as_complete("if x then return 1"));
+        assert!(!as_complete("try\nerror \"x\"\non error message"));
+        assert!(as_complete("try\nerror \"x\"\non error message\nend try"));
+        assert!(!as_complete("on concat(x, y)\nreturn x & y"));
+        assert!(as_complete("on concat(x, y)\nreturn x & y\nend concat"));
+        assert!(!as_complete("to greet(name)"));
+        assert!(!as_complete("with timeout of 10 seconds"));
+        assert!(!as_complete("using terms from application \"Mail\""));
+    }
+
+    #[test]
+    fn it_ignores_apple_script_keywords_in_strings_and_comments() {
+        assert!(as_complete("return \"tell repeat\""));
+        assert!(as_complete("-- tell application \"Finder\""));
+        assert!(as_complete("(* repeat *) return 1"));
+        assert!(!as_complete("(* tell\n"));
+        assert!(!as_complete("return \"multi\nline"));
+        assert!(as_complete("return \"multi\nline\""));
+        assert!(as_complete("return \"escaped \\\" tell\""));
+    }
+
+    #[test]
+    fn it_detects_apple_script_line_continuations() {
+        assert!(!as_complete("set x to 1 + ¬"));
+        assert!(as_complete("set x to 1 + ¬\n2"));
+    }
+
+    #[test]
+    fn it_detects_java_script_brackets() {
+        assert!(js_complete("1 + 2"));
+        assert!(!js_complete("function concat(x, y) {"));
+        assert!(js_complete("function concat(x, y) {\nreturn x + y;\n}"));
+        assert!(!js_complete("[1,\n2"));
+        assert!(!js_complete("concat(\n'a'"));
+        assert!(js_complete("}"));
+    }
+
+    #[test]
+    fn it_ignores_java_script_brackets_in_strings_and_comments() {
+        assert!(js_complete("'{' + \"(\" + 'it\\'s ['"));
+        assert!(js_complete("1 // {"));
+        assert!(!js_complete("1 /* {"));
+        assert!(js_complete("1 /* { */"));
+        assert!(js_complete("'unterminated {\n"));
+    }
+
+    #[test]
+    fn it_detects_java_script_template_literals() {
+        assert!(!js_complete("`line\n"));
+        assert!(js_complete("`line\n{`"));
+        assert!(!js_complete("`${items.map(x => {`"));
+        assert!(js_complete("`${ {a: 1}.a } }`"));
+    }
+}
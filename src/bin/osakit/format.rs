@@ -0,0 +1,135 @@
+use crate::args::OutputFormat;
+use osakit::ScriptExecutionError;
+use serde_json::Value;
+
+pub(crate) const EXIT_SUCCESS: i32 = 0;
+pub(crate) const EXIT_USAGE: i32 = 2;
+pub(crate) const EXIT_IO: i32 = 3;
+pub(crate) const EXIT_COMPILATION: i32 = 4;
+/// Returned on platforms other than macOS.
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+pub(crate) const EXIT_UNSUPPORTED: i32 = 5;
+
+/// Exit code for the execution error, distinct for every kind of error.
+pub(crate) fn exit_code(error: &ScriptExecutionError) -> i32 {
+    match error {
+        ScriptExecutionError::Runtime { .. } => 10,
+        ScriptExecutionError::JsException(_) => 11,
+        ScriptExecutionError::OutputConversion(_) => 12,
+        ScriptExecutionError::InputConversion(_) => 13,
+        ScriptExecutionError::MainThread => 14,
+        ScriptExecutionError::Globals(_) => 15,
+        ScriptExecutionError::UnknownProperty(_) => 16,
+        ScriptExecutionError::Unknown => 19,
+    }
+}
+
+/// Formats a result value for printing.
+pub(crate) fn format_value(value: &Value, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Json => value.to_string(),
+        OutputFormat::Pretty => {
+            serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+        }
+        OutputFormat::AePrint => ae_print(value),
+    }
+}
+
+/// Formats a value the same way `AEPrintDescToHandle` prints the corresponding descriptor.
+fn ae_print(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("'msng'"),
+        Value::Bool(true) => String::from("'true'"),
+        Value::Bool(false) => String::from("'fals'"),
+        Value::Number(n)
+            if n.is_i64() && n.as_i64().and_then(|n| i32::try_from(n).ok()).is_some() =>
+        {
+            format!("'long'({})", n)
+        }
+        Value::Number(n) if n.is_f64() => format!("'doub'({})", n),
+        Value::Number(n) => format!("'comp'({})", n),
+        Value::String(s) => format!(
+            "'utxt'(\"{}\")",
+            s.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        Value::Array(items) => format!(
+            "[ {} ]",
+            items.iter().map(ae_print).collect::<Vec<_>>().join(", ")
+        ),
+        Value::Object(fields) => format!(
+            "{{ 'usrf':[ {} ] }}",
+            fields
+                .iter()
+                .map(|(key, value)| format!(
+                    "{}, {}",
+                    ae_print(&Value::from(key.as_str())),
+                    ae_print(value)
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_formats_json() {
+        let value = json!({"a": [1, "b"]});
+        assert_eq!(
+            format_value(&value, OutputFormat::Json),
+            "{\"a\":[1,\"b\"]}"
+        );
+        assert_eq!(
+            format_value(&value, OutputFormat::Pretty),
+            "{\n  \"a\": [\n    1,\n    \"b\"\n  ]\n}"
+        );
+    }
+
+    #[test]
+    fn it_formats_ae_print() {
+        assert_eq!(
+            format_value(
+                &json!([1, 2.5, 10000000000i64, "say \"hi\"", true, null, {"id": 21}]),
+                OutputFormat::AePrint
+            ),
+            "[ 'long'(1), 'doub'(2.5), 'comp'(10000000000), 'utxt'(\"say \\\"hi\\\"\"), \
+            'true', 'msng', { 'usrf':[ 'utxt'(\"id\"), 'long'(21) ] } ]"
+        );
+    }
+
+    #[test]
+    fn it_maps_errors_to_distinct_exit_codes() {
+        // Conversion and globals errors are not constructible outside of `osakit`.
+        let errors: Vec<ScriptExecutionError> = serde_json::from_value(json!([
+            {"Runtime": {
+                "message": "", "location": 0, "length": 0, "number": null,
+                "brief_message": null, "app_name": null, "partial_result": null,
+                "offending_object": null, "js_exception": null
+            }},
+            {"JsException": {"name": null, "message": "", "stack": null, "line": null, "value": null}},
+            {"OutputConversion": "StringExpectedButNoneFound"},
+            {"InputConversion": {"NumberConversionError": "NaN"}},
+            "MainThread",
+            {"Globals": {"InvalidName": "1"}},
+            {"UnknownProperty": "name"},
+            "Unknown"
+        ]))
+        .unwrap();
+        let mut codes: Vec<i32> = errors.iter().map(exit_code).collect();
+        codes.extend([
+            EXIT_SUCCESS,
+            EXIT_USAGE,
+            EXIT_IO,
+            EXIT_COMPILATION,
+            EXIT_UNSUPPORTED,
+        ]);
+        let count = codes.len();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), count);
+    }
+}
@@ -0,0 +1,63 @@
+//! Command line tool running `AppleScript` and `JavaScript` using `osakit`.
+
+mod args;
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+mod block;
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+mod format;
+#[cfg(target_os = "macos")]
+mod repl;
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+mod rpc;
+#[cfg(target_os = "macos")]
+mod run;
+#[cfg(target_os = "macos")]
+mod serve;
+
+use args::{Command, USAGE};
+use format::{EXIT_SUCCESS, EXIT_USAGE};
+
+fn main() {
+    let code = match args::parse(std::env::args().skip(1)) {
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            EXIT_SUCCESS
+        }
+        Ok(Command::Version) => {
+            println!("osakit {}", env!("CARGO_PKG_VERSION"));
+            EXIT_SUCCESS
+        }
+        Ok(Command::Run(options)) => run(options),
+        Ok(Command::Repl(language)) => repl(language),
+        Ok(Command::Serve(options)) => serve(options),
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+            EXIT_USAGE
+        }
+    };
+    std::process::exit(code);
+}
+
+#[cfg(target_os = "macos")]
+use {repl::repl, run::run, serve::serve};
+
+#[cfg(not(target_os = "macos"))]
+fn run(_options: args::RunOptions) -> i32 {
+    unsupported()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn repl(_language: args::ScriptLanguage) -> i32 {
+    unsupported()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn serve(_options: args::ServeOptions) -> i32 {
+    unsupported()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn unsupported() -> i32 {
+    eprintln!("error: osakit requires macOS");
+    format::EXIT_UNSUPPORTED
+}
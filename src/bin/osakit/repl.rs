@@ -0,0 +1,99 @@
+use crate::args::{OutputFormat, ScriptLanguage};
+use crate::block::is_complete;
+use crate::format::{format_value, EXIT_IO, EXIT_SUCCESS};
+use crate::run::osa_language;
+use osakit::Script;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::path::PathBuf;
+
+const HELP: &str = "Enter AppleScript or JavaScript code, blocks continue until closed.
+Commands:
+    :lang as|js    Switches language
+    :help          Prints this help
+    :quit          Exits, same as Ctrl-D
+";
+
+/// Runs the interactive session and returns the exit code.
+pub(crate) fn repl(mut language: ScriptLanguage) -> i32 {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            return EXIT_IO;
+        }
+    };
+    let history = history_path();
+    if let Some(path) = &history {
+        // History file does not exist on the first run.
+        let _ = editor.load_history(path);
+    }
+    let mut buffer = String::new();
+    loop {
+        let prompt = match (buffer.is_empty(), language) {
+            (false, _) => "..> ",
+            (true, ScriptLanguage::AppleScript) => "as> ",
+            (true, ScriptLanguage::JavaScript) => "js> ",
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                eprintln!("error: {}", error);
+                return EXIT_IO;
+            }
+        };
+        if buffer.is_empty() {
+            if let Some(command) = line.trim().strip_prefix(':') {
+                let _ = editor.add_history_entry(line.trim());
+                match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+                    ["q" | "quit"] => break,
+                    ["h" | "help"] => print!("{}", HELP),
+                    ["lang", name] => match ScriptLanguage::from_name(name) {
+                        Some(selected) => language = selected,
+                        None => eprintln!("error: unknown language `{}`", name),
+                    },
+                    _ => eprintln!("error: unknown command `:{}`, see `:help`", command),
+                }
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+        }
+        buffer.push_str(&line);
+        buffer.push('\n');
+        if !is_complete(language, &buffer) {
+            continue;
+        }
+        let _ = editor.add_history_entry(buffer.trim_end());
+        evaluate(language, &buffer);
+        buffer.clear();
+    }
+    if let Some(path) = &history {
+        if let Err(error) = editor.save_history(path) {
+            eprintln!("error: cannot save history: {}", error);
+        }
+    }
+    EXIT_SUCCESS
+}
+
+fn evaluate(language: ScriptLanguage, source: &str) {
+    let mut script = Script::new_from_source(osa_language(language), source);
+    if let Err(error) = script.compile() {
+        eprintln!("error: {}", error);
+        return;
+    }
+    match script.execute() {
+        Ok(value) => println!("{}", format_value(&value, OutputFormat::Pretty)),
+        Err(error) => eprintln!("error: {}", error),
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".osakit_history"))
+}
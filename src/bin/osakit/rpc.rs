@@ -0,0 +1,638 @@
+use crate::args::ScriptLanguage;
+use serde_json::{json, Map, Value};
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+pub(crate) const PARSE_ERROR: i64 = -32700;
+pub(crate) const INVALID_REQUEST: i64 = -32600;
+pub(crate) const METHOD_NOT_FOUND: i64 = -32601;
+pub(crate) const INVALID_PARAMS: i64 = -32602;
+pub(crate) const INTERNAL_ERROR: i64 = -32603;
+/// Script failed to compile, `data` describes the compilation error.
+pub(crate) const COMPILATION_ERROR: i64 = -32001;
+/// Script failed to execute, `data` describes the `ScriptExecutionError`.
+pub(crate) const EXECUTION_ERROR: i64 = -32002;
+pub(crate) const UNKNOWN_HANDLE: i64 = -32003;
+pub(crate) const TIMEOUT: i64 = -32004;
+
+/// JSON-RPC error object.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RpcError {
+    pub(crate) code: i64,
+    pub(crate) message: String,
+    pub(crate) data: Option<Value>,
+}
+
+impl RpcError {
+    pub(crate) fn new<S: Into<String>>(code: i64, message: S) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub(crate) fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    pub(crate) fn unknown_handle(handle: u64) -> Self {
+        Self::new(UNKNOWN_HANDLE, format!("unknown script handle: {}", handle))
+    }
+
+    fn to_json(&self) -> Value {
+        let mut error = json!({"code": self.code, "message": self.message});
+        if let Some(data) = &self.data {
+            error["data"] = data.clone();
+        }
+        error
+    }
+}
+
+/// Executes protocol methods. Scripts are referenced by handles returned from `compile`.
+pub(crate) trait Backend {
+    fn compile(&mut self, language: ScriptLanguage, source: &str) -> Result<u64, RpcError>;
+    fn execute(&mut self, handle: u64) -> Result<Value, RpcError>;
+    fn execute_function(
+        &mut self,
+        handle: u64,
+        name: &str,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RpcError>;
+    fn list_handlers(&mut self, handle: u64) -> Result<Vec<String>, RpcError>;
+    fn dispose(&mut self, handle: u64) -> Result<(), RpcError>;
+}
+
+/// Calls the backend method with named parameters.
+pub(crate) fn dispatch(
+    backend: &mut dyn Backend,
+    method: &str,
+    params: &Map<String, Value>,
+) -> Result<Value, RpcError> {
+    match method {
+        "compile" => {
+            let language = match params.get("language") {
+                None => ScriptLanguage::AppleScript,
+                Some(Value::String(name)) => ScriptLanguage::from_name(name).ok_or_else(|| {
+                    RpcError::new(INVALID_PARAMS, format!("unknown language `{}`", name))
+                })?,
+                Some(_) => return Err(invalid_param("language", "a string")),
+            };
+            let source = string_param(params, "source")?;
+            let handle = backend.compile(language, source)?;
+            Ok(json!({"handle": handle}))
+        }
+        "execute" => backend.execute(handle_param(params)?),
+        "executeFunction" => {
+            let handle = handle_param(params)?;
+            let name = string_param(params, "name")?;
+            let arguments = match params.get("arguments") {
+                None => Vec::new(),
+                Some(Value::Array(arguments)) => arguments.clone(),
+                Some(_) => return Err(invalid_param("arguments", "an array")),
+            };
+            backend.execute_function(handle, name, arguments)
+        }
+        "listHandlers" => Ok(json!(backend.list_handlers(handle_param(params)?)?)),
+        "dispose" => {
+            backend.dispose(handle_param(params)?)?;
+            Ok(Value::Null)
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method `{}`", method),
+        )),
+    }
+}
+
+fn invalid_param(name: &str, expected: &str) -> RpcError {
+    RpcError::new(INVALID_PARAMS, format!("`{}` must be {}", name, expected))
+}
+
+fn string_param<'a>(params: &'a Map<String, Value>, name: &str) -> Result<&'a str, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid_param(name, "a string"))
+}
+
+fn handle_param(params: &Map<String, Value>) -> Result<u64, RpcError> {
+    params
+        .get("handle")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid_param("handle", "a non-negative integer"))
+}
+
+/// Request forwarded from a connection to the thread owning the backend.
+pub(crate) struct Job {
+    method: String,
+    params: Map<String, Value>,
+    reply: Sender<Result<Value, RpcError>>,
+}
+
+/// Executes jobs until all senders are dropped.
+/// Scripts can only be used from the main thread, so the backend is owned by it while
+/// connections are served from other threads.
+pub(crate) fn run_backend(backend: &mut dyn Backend, jobs: Receiver<Job>) {
+    for job in jobs {
+        let result = dispatch(backend, &job.method, &job.params);
+        // The connection may have stopped waiting because of a timeout.
+        let _ = job.reply.send(result);
+    }
+}
+
+/// Serves newline-delimited JSON-RPC 2.0 messages until the input is closed.
+///
+/// Each request may override the default timeout using the `timeoutMs` parameter. Running
+/// scripts cannot be interrupted, so on timeout the error is returned while the backend keeps
+/// executing the request and following requests wait for it to finish.
+pub(crate) fn serve_connection<R: BufRead, W: Write>(
+    reader: R,
+    mut writer: W,
+    jobs: &Sender<Job>,
+    default_timeout: Option<Duration>,
+) -> std::io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(Value::Array(requests)) if requests.is_empty() => Some(error_response(
+                Value::Null,
+                RpcError::new(INVALID_REQUEST, "empty batch"),
+            )),
+            Ok(Value::Array(requests)) => {
+                let responses: Vec<Value> = requests
+                    .into_iter()
+                    .filter_map(|request| handle_request(request, jobs, default_timeout))
+                    .collect();
+                (!responses.is_empty()).then_some(Value::Array(responses))
+            }
+            Ok(request) => handle_request(request, jobs, default_timeout),
+            Err(error) => Some(error_response(
+                Value::Null,
+                RpcError::new(PARSE_ERROR, format!("parse error: {}", error)),
+            )),
+        };
+        if let Some(response) = response {
+            writeln!(writer, "{}", response)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles a single request, returns `None` for notifications.
+fn handle_request(
+    request: Value,
+    jobs: &Sender<Job>,
+    default_timeout: Option<Duration>,
+) -> Option<Value> {
+    let Value::Object(mut request) = request else {
+        return Some(error_response(
+            Value::Null,
+            RpcError::new(INVALID_REQUEST, "request must be an object"),
+        ));
+    };
+    let id = request.remove("id");
+    let response_id = id.clone().unwrap_or(Value::Null);
+    if !matches!(
+        id,
+        None | Some(Value::Null | Value::Number(_) | Value::String(_))
+    ) {
+        return Some(error_response(
+            Value::Null,
+            RpcError::new(INVALID_REQUEST, "`id` must be a number, string or null"),
+        ));
+    }
+    if request.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Some(error_response(
+            response_id,
+            RpcError::new(INVALID_REQUEST, "`jsonrpc` must be \"2.0\""),
+        ));
+    }
+    let Some(Value::String(method)) = request.remove("method") else {
+        return Some(error_response(
+            response_id,
+            RpcError::new(INVALID_REQUEST, "`method` must be a string"),
+        ));
+    };
+    let params = match request.remove("params") {
+        None => Map::new(),
+        Some(Value::Object(params)) => params,
+        Some(_) => {
+            return Some(error_response(
+                response_id,
+                RpcError::new(INVALID_PARAMS, "`params` must be an object"),
+            ))
+        }
+    };
+    let timeout = match params.get("timeoutMs") {
+        None => default_timeout,
+        Some(value) => match value.as_u64() {
+            Some(milliseconds) => Some(Duration::from_millis(milliseconds)),
+            None => {
+                return Some(error_response(
+                    response_id,
+                    invalid_param("timeoutMs", "a non-negative integer"),
+                ))
+            }
+        },
+    };
+
+    let (reply, result) = channel();
+    let job = Job {
+        method,
+        params,
+        reply,
+    };
+    if jobs.send(job).is_err() {
+        return id.map(|id| error_response(id, backend_stopped()));
+    }
+    // Notifications are executed without waiting for the result.
+    let id = id?;
+    let result = match timeout {
+        Some(timeout) => result.recv_timeout(timeout).unwrap_or_else(|error| {
+            Err(match error {
+                RecvTimeoutError::Timeout => RpcError::new(
+                    TIMEOUT,
+                    format!("request timed out after {} ms", timeout.as_millis()),
+                ),
+                RecvTimeoutError::Disconnected => backend_stopped(),
+            })
+        }),
+        None => result.recv().unwrap_or_else(|_| Err(backend_stopped())),
+    };
+    Some(match result {
+        Ok(value) => json!({"jsonrpc": "2.0", "id": id, "result": value}),
+        Err(error) => error_response(id, error),
+    })
+}
+
+fn backend_stopped() -> RpcError {
+    RpcError::new(INTERNAL_ERROR, "backend stopped")
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": error.to_json()})
+}
+
+/// Binds a Unix domain socket, replacing a stale socket file left by a previous run.
+#[cfg(unix)]
+pub(crate) fn bind_unix_socket(
+    path: &std::path::Path,
+) -> std::io::Result<std::os::unix::net::UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    std::os::unix::net::UnixListener::bind(path)
+}
+
+/// Accepts connections forever, serving each one from a separate thread.
+#[cfg(unix)]
+pub(crate) fn serve_listener(
+    listener: std::os::unix::net::UnixListener,
+    jobs: Sender<Job>,
+    default_timeout: Option<Duration>,
+) -> std::io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let jobs = jobs.clone();
+        std::thread::spawn(move || {
+            let reader = match stream.try_clone() {
+                Ok(reader) => std::io::BufReader::new(reader),
+                Err(error) => return eprintln!("error: {}", error),
+            };
+            if let Err(error) = serve_connection(reader, stream, &jobs, default_timeout) {
+                eprintln!("error: {}", error);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufReader, Cursor};
+
+    /// Scripts are lists of handler names: `echo` returns its arguments, `fail` returns an
+    /// execution error and `sleep` waits for the specified number of milliseconds.
+    #[derive(Default)]
+    struct MemoryBackend {
+        scripts: HashMap<u64, String>,
+        next_handle: u64,
+    }
+
+    impl MemoryBackend {
+        fn source(&self, handle: u64) -> Result<&String, RpcError> {
+            self.scripts
+                .get(&handle)
+                .ok_or_else(|| RpcError::unknown_handle(handle))
+        }
+    }
+
+    impl Backend for MemoryBackend {
+        fn compile(&mut self, _language: ScriptLanguage, source: &str) -> Result<u64, RpcError> {
+            if source.contains('(') {
+                return Err(RpcError::new(COMPILATION_ERROR, "compilation error")
+                    .with_data(json!({"location": source.find('(')})));
+            }
+            self.next_handle += 1;
+            self.scripts.insert(self.next_handle, source.to_string());
+            Ok(self.next_handle)
+        }
+
+        fn execute(&mut self, handle: u64) -> Result<Value, RpcError> {
+            Ok(Value::from(self.source(handle)?.as_str()))
+        }
+
+        fn execute_function(
+            &mut self,
+            handle: u64,
+            name: &str,
+            arguments: Vec<Value>,
+        ) -> Result<Value, RpcError> {
+            if !self
+                .list_handlers(handle)?
+                .iter()
+                .any(|handler| handler == name)
+            {
+                return Err(RpcError::new(EXECUTION_ERROR, "handler not found")
+                    .with_data(json!({"kind": "runtime", "number": -1708})));
+            }
+            match name {
+                "echo" => Ok(Value::Array(arguments)),
+                "sleep" => {
+                    let milliseconds = arguments.first().and_then(Value::as_u64).unwrap_or(0);
+                    std::thread::sleep(Duration::from_millis(milliseconds));
+                    Ok(Value::Null)
+                }
+                _ => Err(RpcError::new(EXECUTION_ERROR, "execution error")
+                    .with_data(json!({"kind": "runtime", "number": -2700}))),
+            }
+        }
+
+        fn list_handlers(&mut self, handle: u64) -> Result<Vec<String>, RpcError> {
+            Ok(self
+                .source(handle)?
+                .split_whitespace()
+                .map(String::from)
+                .collect())
+        }
+
+        fn dispose(&mut self, handle: u64) -> Result<(), RpcError> {
+            self.scripts
+                .remove(&handle)
+                .map(|_| ())
+                .ok_or_else(|| RpcError::unknown_handle(handle))
+        }
+    }
+
+    fn params(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(params) => params,
+            _ => panic!("params must be an object"),
+        }
+    }
+
+    fn start_backend() -> Sender<Job> {
+        let (jobs, received) = channel();
+        std::thread::spawn(move || run_backend(&mut MemoryBackend::default(), received));
+        jobs
+    }
+
+    /// Serves the input lines and returns the parsed response lines.
+    fn serve(input: &[Value], default_timeout: Option<Duration>) -> Vec<Value> {
+        let input: String = input.iter().map(|line| format!("{}\n", line)).collect();
+        serve_text(&input, default_timeout)
+    }
+
+    fn serve_text(input: &str, default_timeout: Option<Duration>) -> Vec<Value> {
+        let mut output = Vec::new();
+        serve_connection(
+            Cursor::new(input.as_bytes()),
+            &mut output,
+            &start_backend(),
+            default_timeout,
+        )
+        .unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn it_dispatches_methods() {
+        let backend = &mut MemoryBackend::default();
+        assert_eq!(
+            dispatch(
+                backend,
+                "compile",
+                &params(json!({"language": "js", "source": "echo fail"}))
+            ),
+            Ok(json!({"handle": 1}))
+        );
+        assert_eq!(
+            dispatch(backend, "listHandlers", &params(json!({"handle": 1}))),
+            Ok(json!(["echo", "fail"]))
+        );
+        assert_eq!(
+            dispatch(
+                backend,
+                "executeFunction",
+                &params(json!({"handle": 1, "name": "echo", "arguments": [1, "a"]}))
+            ),
+            Ok(json!([1, "a"]))
+        );
+        assert_eq!(
+            dispatch(backend, "execute", &params(json!({"handle": 1}))),
+            Ok(json!("echo fail"))
+        );
+        assert_eq!(
+            dispatch(backend, "dispose", &params(json!({"handle": 1}))),
+            Ok(Value::Null)
+        );
+        assert_eq!(
+            dispatch(backend, "execute", &params(json!({"handle": 1}))),
+            Err(RpcError::unknown_handle(1))
+        );
+    }
+
+    #[test]
+    fn it_validates_params() {
+        let backend = &mut MemoryBackend::default();
+        for (method, value, message) in [
+            ("compile", json!({}), "`source` must be a string"),
+            (
+                "compile",
+                json!({"language": "python", "source": ""}),
+                "unknown language `python`",
+            ),
+            (
+                "execute",
+                json!({"handle": -1}),
+                "`handle` must be a non-negative integer",
+            ),
+            (
+                "executeFunction",
+                json!({"handle": 1, "name": "echo", "arguments": {}}),
+                "`arguments` must be an array",
+            ),
+        ] {
+            assert_eq!(
+                dispatch(backend, method, &params(value)),
+                Err(RpcError::new(INVALID_PARAMS, message))
+            );
+        }
+        assert_eq!(
+            dispatch(backend, "run", &Map::new()).map_err(|error| error.code),
+            Err(METHOD_NOT_FOUND)
+        );
+    }
+
+    #[test]
+    fn it_serves_requests() {
+        assert_eq!(
+            serve(
+                &[
+                    json!({"jsonrpc": "2.0", "id": 1, "method": "compile", "params": {"source": "echo fail"}}),
+                    json!({"jsonrpc": "2.0", "id": "a", "method": "executeFunction", "params": {"handle": 1, "name": "echo", "arguments": [true]}}),
+                    json!({"jsonrpc": "2.0", "id": 2, "method": "executeFunction", "params": {"handle": 1, "name": "fail"}}),
+                    json!({"jsonrpc": "2.0", "id": 3, "method": "compile", "params": {"source": "echo("}}),
+                ],
+                None
+            ),
+            vec![
+                json!({"jsonrpc": "2.0", "id": 1, "result": {"handle": 1}}),
+                json!({"jsonrpc": "2.0", "id": "a", "result": [true]}),
+                json!({"jsonrpc": "2.0", "id": 2, "error": {
+                    "code": EXECUTION_ERROR,
+                    "message": "execution error",
+                    "data": {"kind": "runtime", "number": -2700}
+                }}),
+                json!({"jsonrpc": "2.0", "id": 3, "error": {
+                    "code": COMPILATION_ERROR,
+                    "message": "compilation error",
+                    "data": {"location": 4}
+                }}),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_messages() {
+        let responses = serve_text(
+            "{\n\
+            []\n\
+            [1]\n\
+            {\"id\": 1, \"method\": \"execute\"}\n\
+            {\"jsonrpc\": \"2.0\", \"id\": 2}\n\
+            {\"jsonrpc\": \"2.0\", \"id\": {}, \"method\": \"execute\"}\n\
+            {\"jsonrpc\": \"2.0\", \"id\": 3, \"method\": \"execute\", \"params\": [1]}\n",
+            None,
+        );
+        let errors: Vec<(Value, Value)> = responses
+            .iter()
+            .map(|response| match response {
+                Value::Array(responses) => (
+                    responses[0]["id"].clone(),
+                    responses[0]["error"]["code"].clone(),
+                ),
+                _ => (response["id"].clone(), response["error"]["code"].clone()),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (Value::Null, json!(PARSE_ERROR)),
+                (Value::Null, json!(INVALID_REQUEST)),
+                (Value::Null, json!(INVALID_REQUEST)),
+                (json!(1), json!(INVALID_REQUEST)),
+                (json!(2), json!(INVALID_REQUEST)),
+                (Value::Null, json!(INVALID_REQUEST)),
+                (json!(3), json!(INVALID_PARAMS)),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_serves_batches_and_notifications() {
+        assert_eq!(
+            serve(
+                &[
+                    json!({"jsonrpc": "2.0", "method": "compile", "params": {"source": "echo"}}),
+                    json!([
+                        {"jsonrpc": "2.0", "id": 1, "method": "listHandlers", "params": {"handle": 1}},
+                        {"jsonrpc": "2.0", "method": "dispose", "params": {"handle": 1}},
+                        {"jsonrpc": "2.0", "id": 2, "method": "listHandlers", "params": {"handle": 1}},
+                    ]),
+                    json!([{"jsonrpc": "2.0", "method": "dispose", "params": {"handle": 1}}]),
+                ],
+                None
+            ),
+            vec![json!([
+                {"jsonrpc": "2.0", "id": 1, "result": ["echo"]},
+                {"jsonrpc": "2.0", "id": 2, "error": {
+                    "code": UNKNOWN_HANDLE,
+                    "message": "unknown script handle: 1"
+                }},
+            ])]
+        );
+    }
+
+    #[test]
+    fn it_times_out_requests() {
+        let responses = serve(
+            &[
+                json!({"jsonrpc": "2.0", "id": 1, "method": "compile", "params": {"source": "sleep"}}),
+                json!({"jsonrpc": "2.0", "id": 2, "method": "executeFunction", "params": {"handle": 1, "name": "sleep", "arguments": [500]}}),
+                json!({"jsonrpc": "2.0", "id": 3, "method": "executeFunction", "params": {"handle": 1, "name": "sleep", "arguments": [0], "timeoutMs": 5000}}),
+            ],
+            Some(Duration::from_millis(50)),
+        );
+        assert_eq!(responses[1]["error"]["code"], json!(TIMEOUT));
+        assert_eq!(
+            responses[2],
+            json!({"jsonrpc": "2.0", "id": 3, "result": null})
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_serves_unix_sockets() {
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join("osakit_it_serves_unix_sockets.sock");
+        // Binding twice replaces the stale socket file.
+        drop(bind_unix_socket(&path).unwrap());
+        let listener = bind_unix_socket(&path).unwrap();
+        let jobs = start_backend();
+        std::thread::spawn(move || serve_listener(listener, jobs, None));
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        writeln!(
+            stream,
+            "{}",
+            json!({"jsonrpc": "2.0", "id": 1, "method": "compile", "params": {"source": "echo"}})
+        )
+        .unwrap();
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&line).unwrap(),
+            json!({"jsonrpc": "2.0", "id": 1, "result": {"handle": 1}})
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+use crate::args::{RunOptions, ScriptLanguage, ScriptSource};
+use crate::format::{exit_code, format_value, EXIT_COMPILATION, EXIT_IO, EXIT_SUCCESS};
+use osakit::{Language, Script, ScriptCompilationError};
+
+pub(crate) fn osa_language(language: ScriptLanguage) -> Language {
+    match language {
+        ScriptLanguage::AppleScript => Language::AppleScript,
+        ScriptLanguage::JavaScript => Language::JavaScript,
+    }
+}
+
+/// Reads the script from the file or inline source. Compiled `.scpt` files are loaded
+/// as is, their language is stored in the file.
+fn load_script(options: &RunOptions) -> Result<Script, i32> {
+    let source = match &options.source {
+        ScriptSource::File(path)
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "scpt") =>
+        {
+            return Script::new_from_compiled(path).map_err(|error| {
+                eprintln!("error: cannot load `{}`: {}", path.display(), error);
+                EXIT_IO
+            });
+        }
+        ScriptSource::File(path) => std::fs::read_to_string(path).map_err(|error| {
+            eprintln!("error: cannot read `{}`: {}", path.display(), error);
+            EXIT_IO
+        })?,
+        ScriptSource::Inline(source) => source.clone(),
+    };
+    Ok(Script::new_from_source(
+        osa_language(options.language),
+        &source,
+    ))
+}
+
+/// Executes the `run` command and returns the exit code.
+pub(crate) fn run(options: RunOptions) -> i32 {
+    let mut script = match load_script(&options) {
+        Ok(script) => script,
+        Err(code) => return code,
+    };
+    if let Err(error) = script.compile() {
+        match &error {
+            ScriptCompilationError::Failure {
+                location, length, ..
+            } => eprintln!("error: {} (at {}..{})", error, location, location + length),
+            ScriptCompilationError::Unknown
+            | ScriptCompilationError::Function { .. }
+            | ScriptCompilationError::MainThread => {
+                eprintln!("error: {}", error)
+            }
+        }
+        return EXIT_COMPILATION;
+    }
+    if options.check {
+        return EXIT_SUCCESS;
+    }
+    let result = match &options.handler {
+        Some(handler) => script.execute_function(handler, options.arguments),
+        None => script.execute(),
+    };
+    match result {
+        Ok(value) => {
+            println!("{}", format_value(&value, options.format));
+            EXIT_SUCCESS
+        }
+        Err(error) => {
+            eprintln!("error: {}", error);
+            exit_code(&error)
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::args::{ScriptLanguage, ServeOptions};
+use crate::format::{EXIT_IO, EXIT_SUCCESS};
+use crate::rpc::{
+    bind_unix_socket, run_backend, serve_connection, serve_listener, Backend, RpcError,
+    COMPILATION_ERROR, EXECUTION_ERROR,
+};
+use crate::run::osa_language;
+use osakit::{Script, ScriptCompilationError, ScriptExecutionError, Value};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::mpsc::channel;
+
+/// Backend executing scripts using `osakit`, must be used from the main thread.
+#[derive(Default)]
+struct OsaBackend {
+    scripts: HashMap<u64, Script>,
+    next_handle: u64,
+}
+
+impl OsaBackend {
+    fn script(&self, handle: u64) -> Result<&Script, RpcError> {
+        self.scripts
+            .get(&handle)
+            .ok_or_else(|| RpcError::unknown_handle(handle))
+    }
+}
+
+impl Backend for OsaBackend {
+    fn compile(&mut self, language: ScriptLanguage, source: &str) -> Result<u64, RpcError> {
+        let mut script =
+            Script::new_from_source(osa_language(language), source).with_js_exceptions();
+        script
+            .compile()
+            .map_err(|error| compilation_error(&error))?;
+        self.next_handle += 1;
+        self.scripts.insert(self.next_handle, script);
+        Ok(self.next_handle)
+    }
+
+    fn execute(&mut self, handle: u64) -> Result<Value, RpcError> {
+        self.script(handle)?
+            .execute()
+            .map_err(|error| execution_error(&error))
+    }
+
+    fn execute_function(
+        &mut self,
+        handle: u64,
+        name: &str,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RpcError> {
+        self.script(handle)?
+            .execute_function(name, arguments)
+            .map_err(|error| execution_error(&error))
+    }
+
+    fn list_handlers(&mut self, handle: u64) -> Result<Vec<String>, RpcError> {
+        Ok(self.script(handle)?.handler_names())
+    }
+
+    fn dispose(&mut self, handle: u64) -> Result<(), RpcError> {
+        self.scripts
+            .remove(&handle)
+            .map(|_| ())
+            .ok_or_else(|| RpcError::unknown_handle(handle))
+    }
+}
+
+fn compilation_error(error: &ScriptCompilationError) -> RpcError {
+    let data = match error {
+        ScriptCompilationError::Failure {
+            location,
+            length,
+            number,
+            brief_message,
+            ..
+        } => json!({
+            "number": number,
+            "location": location,
+            "length": length,
+            "briefMessage": brief_message,
+        }),
+        ScriptCompilationError::Unknown
+        | ScriptCompilationError::Function { .. }
+        | ScriptCompilationError::MainThread => json!({}),
+    };
+    RpcError::new(COMPILATION_ERROR, error.to_string()).with_data(data)
+}
+
+fn execution_error(error: &ScriptExecutionError) -> RpcError {
+    let mut data = json!({"kind": error.kind(), "number": error.number()});
+    if let ScriptExecutionError::Runtime {
+        location,
+        length,
+        brief_message,
+        app_name,
+        partial_result,
+        offending_object,
+        ..
+    } = error
+    {
+        data["location"] = json!(location);
+        data["length"] = json!(length);
+        data["briefMessage"] = json!(brief_message);
+        data["appName"] = json!(app_name);
+        data["partialResult"] = json!(partial_result);
+        data["offendingObject"] = json!(offending_object);
+    }
+    if let Some(exception) = error.js_exception() {
+        data["jsException"] = json!({
+            "name": exception.name,
+            "message": exception.message,
+            "stack": exception.stack,
+            "line": exception.line,
+            "value": exception.value,
+        });
+    }
+    RpcError::new(EXECUTION_ERROR, error.to_string()).with_data(data)
+}
+
+/// Executes the `serve` command and returns the exit code.
+/// Requests are read on a separate thread and executed on the main thread.
+pub(crate) fn serve(options: ServeOptions) -> i32 {
+    let (jobs, received) = channel();
+    let timeout = options.timeout;
+    match &options.socket {
+        Some(path) => {
+            let listener = match bind_unix_socket(path) {
+                Ok(listener) => listener,
+                Err(error) => {
+                    eprintln!("error: cannot bind `{}`: {}", path.display(), error);
+                    return EXIT_IO;
+                }
+            };
+            std::thread::spawn(move || {
+                if let Err(error) = serve_listener(listener, jobs, timeout) {
+                    eprintln!("error: {}", error);
+                }
+            });
+        }
+        None => {
+            std::thread::spawn(move || {
+                let stdin = std::io::stdin().lock();
+                if let Err(error) = serve_connection(stdin, std::io::stdout(), &jobs, timeout) {
+                    eprintln!("error: {}", error);
+                }
+            });
+        }
+    }
+    run_backend(&mut OsaBackend::default(), received);
+    EXIT_SUCCESS
+}
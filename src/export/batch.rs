@@ -0,0 +1,265 @@
+use crate::js_exception::JsException;
+use crate::script::{Language, ScriptExecutionError};
+use crate::value::Value;
+
+/// Name of the handler dispatching batched calls.
+pub(crate) const BATCH_HANDLER: &str = "__osakit_batch";
+
+/// Appended to `JavaScript` source together with [`crate::js_exception::JS_EXCEPTION_HELPER`]
+/// to execute a batch of calls in a single `OSAKit` call.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) const JS_BATCH_EPILOGUE: &str = "
+;function __osakit_batch(calls, stopOnError) {
+    var results = [];
+    for (var i = 0; i < calls.length; i++) {
+        var result = __osakit_call(calls[i][0], calls[i][1]);
+        results.push(result);
+        if (!result.ok && stopOnError) {
+            break;
+        }
+    }
+    return results;
+}
+";
+
+/// Generates the dispatcher appended to `AppleScript` source to execute a batch of calls in a
+/// single `OSAKit` call. `AppleScript` cannot call handlers by name, so the dispatcher compares
+/// the requested name with the names of the positional handlers declared in the source and
+/// calls the matching one directly. Requested names are never evaluated as code.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn apple_script_batch_epilogue(handlers: &[(String, usize)]) -> String {
+    let mut dispatch = String::new();
+    // Backslashes are escape characters inside of `|...|`, such names are not dispatched.
+    for (name, parameters) in handlers.iter().filter(|(name, _)| !name.contains('\\')) {
+        let arguments = (1..=*parameters)
+            .map(|index| format!("item {} of __osakit_args", index))
+            .collect::<Vec<_>>()
+            .join(", ");
+        dispatch.push_str(&format!(
+            "        if __osakit_name is \"{}\" then
+            if (count of __osakit_args) is not {} then error \"Wrong number of parameters.\" number -1721
+            return |{}|({})
+        end if
+",
+            name.replace('"', "\\\""),
+            parameters,
+            name,
+            arguments
+        ));
+    }
+    format!(
+        "
+on __osakit_dispatch(__osakit_name, __osakit_args)
+    considering case
+{}    end considering
+    error \"«script» doesn’t understand the “\" & __osakit_name & \"” message.\" number -1708
+end __osakit_dispatch
+
+on __osakit_batch(__osakit_calls, __osakit_stop_on_error)
+    set __osakit_results to {{}}
+    repeat with __osakit_call in __osakit_calls
+        set {{__osakit_name, __osakit_args}} to contents of __osakit_call
+        try
+            set __osakit_value to __osakit_dispatch(__osakit_name, __osakit_args)
+            set end of __osakit_results to {{true, __osakit_value}}
+        on error __osakit_message number __osakit_number
+            if __osakit_number is -2763 then
+                set end of __osakit_results to {{true, missing value}}
+            else
+                set end of __osakit_results to {{false, __osakit_message, __osakit_number}}
+                if __osakit_stop_on_error then exit repeat
+            end if
+        end try
+    end repeat
+    return __osakit_results
+end __osakit_batch
+",
+        dispatch
+    )
+}
+
+/// Defines how [`crate::Script::execute_batch`] handles failing calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BatchMode {
+    /// Executes all calls regardless of failures.
+    #[default]
+    ContinueOnError,
+    /// Stops after the first failing call, results of the remaining calls are not returned.
+    StopOnFirstError,
+}
+
+/// Converts batch calls to the arguments of [`BATCH_HANDLER`].
+pub(crate) fn batch_arguments(calls: &[(&str, Vec<Value>)], mode: BatchMode) -> Vec<Value> {
+    vec![
+        Value::Array(
+            calls
+                .iter()
+                .map(|(handler, arguments)| {
+                    Value::Array(vec![
+                        Value::String(handler.to_string()),
+                        Value::Array(arguments.clone()),
+                    ])
+                })
+                .collect(),
+        ),
+        Value::Bool(mode == BatchMode::StopOnFirstError),
+    ]
+}
+
+/// Converts the result of [`BATCH_HANDLER`] call to per-call results.
+pub(crate) fn parse_batch_results(
+    language: Language,
+    js_exceptions: bool,
+    results: Value,
+) -> Result<Vec<Result<Value, ScriptExecutionError>>, ScriptExecutionError> {
+    let results = match results {
+        Value::Array(results) => results,
+        _ => return Err(ScriptExecutionError::Unknown),
+    };
+    results
+        .into_iter()
+        .map(|result| match language {
+            Language::JavaScript => Ok(JsException::from_helper_result(result).map_err(
+                |exception| match js_exceptions {
+                    true => ScriptExecutionError::JsException(exception),
+                    false => runtime_error(format!("Error: {}", exception), exception.number()),
+                },
+            )),
+            Language::AppleScript => parse_apple_script_result(result),
+        })
+        .collect()
+}
+
+fn parse_apple_script_result(
+    result: Value,
+) -> Result<Result<Value, ScriptExecutionError>, ScriptExecutionError> {
+    let mut fields = match result {
+        Value::Array(fields) if fields.len() >= 2 => fields.into_iter(),
+        _ => return Err(ScriptExecutionError::Unknown),
+    };
+    match (fields.next(), fields.next(), fields.next()) {
+        (Some(Value::Bool(true)), Some(value), _) => Ok(Ok(value)),
+        (Some(Value::Bool(false)), Some(message), number) => Ok(Err(runtime_error(
+            match message {
+                Value::String(message) => message,
+                message => message.to_string(),
+            },
+            number
+                .and_then(|number| number.as_i64())
+                .and_then(|number| i32::try_from(number).ok()),
+        ))),
+        _ => Err(ScriptExecutionError::Unknown),
+    }
+}
+
+fn runtime_error(message: String, number: Option<i32>) -> ScriptExecutionError {
+    ScriptExecutionError::Runtime {
+        message,
+        location: 0,
+        length: 0,
+        number,
+        brief_message: None,
+        app_name: None,
+        partial_result: None,
+        offending_object: None,
+        js_exception: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_converts_calls_to_arguments() {
+        assert_eq!(
+            batch_arguments(
+                &[("concat", vec![json!("a"), json!("b")]), ("now", vec![])],
+                BatchMode::StopOnFirstError
+            ),
+            vec![json!([["concat", ["a", "b"]], ["now", []]]), json!(true)]
+        );
+    }
+
+    #[test]
+    fn it_dispatches_apple_script_handlers_by_comparing_names() {
+        let epilogue = apple_script_batch_epilogue(&[
+            (String::from("concat"), 2),
+            (String::from("with \"quote\""), 0),
+            (String::from("back\\slash"), 0),
+        ]);
+        assert!(epilogue.contains(
+            "if __osakit_name is \"concat\" then
+            if (count of __osakit_args) is not 2 then"
+        ));
+        assert!(
+            epilogue.contains("return |concat|(item 1 of __osakit_args, item 2 of __osakit_args)")
+        );
+        assert!(epilogue.contains("if __osakit_name is \"with \\\"quote\\\"\" then"));
+        assert!(epilogue.contains("return |with \"quote\"|()"));
+        assert!(!epilogue.contains("slash"));
+        assert!(!epilogue.contains("run script"));
+    }
+
+    #[test]
+    fn it_parses_apple_script_results() {
+        assert_eq!(
+            parse_batch_results(
+                Language::AppleScript,
+                false,
+                json!([
+                    [true, "ab"],
+                    [false, "Can’t get window 1.", -1728],
+                    [true, null]
+                ])
+            ),
+            Ok(vec![
+                Ok(json!("ab")),
+                Err(runtime_error("Can’t get window 1.".into(), Some(-1728))),
+                Ok(Value::Null),
+            ])
+        );
+    }
+
+    #[test]
+    fn it_parses_java_script_results() {
+        let results = json!([
+            {"ok": true, "value": "ab"},
+            {"ok": false, "name": "TypeError", "message": "x is not a function", "value": {}},
+        ]);
+        assert_eq!(
+            parse_batch_results(Language::JavaScript, false, results.clone()),
+            Ok(vec![
+                Ok(json!("ab")),
+                Err(runtime_error(
+                    "Error: TypeError: x is not a function".into(),
+                    None
+                )),
+            ])
+        );
+        assert_eq!(
+            parse_batch_results(Language::JavaScript, true, results),
+            Ok(vec![
+                Ok(json!("ab")),
+                Err(ScriptExecutionError::JsException(JsException {
+                    name: Some("TypeError".into()),
+                    message: "x is not a function".into(),
+                    ..Default::default()
+                })),
+            ])
+        );
+    }
+
+    #[test]
+    fn it_fails_on_malformed_results() {
+        assert_eq!(
+            parse_batch_results(Language::AppleScript, false, json!({})),
+            Err(ScriptExecutionError::Unknown)
+        );
+        assert_eq!(
+            parse_batch_results(Language::AppleScript, false, json!([[1, 2]])),
+            Err(ScriptExecutionError::Unknown)
+        );
+    }
+}
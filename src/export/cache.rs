@@ -0,0 +1,429 @@
+use crate::script::{Language, Script, ScriptCompilationError};
+use crate::trace::hash_source;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// Identifies a compiled script: same language, source and enabled helpers produce
+/// the same compiled data. The full source is compared, so scripts with colliding hashes
+/// never share compiled data.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ScriptCacheKey {
+    pub(crate) language: Language,
+    pub(crate) source: String,
+    pub(crate) options: String,
+}
+
+impl ScriptCacheKey {
+    fn file_name(&self) -> String {
+        format!(
+            "{}-{:016x}-{:016x}.scpt",
+            self.language.name().to_lowercase(),
+            hash_source(&self.source),
+            hash_source(&self.options)
+        )
+    }
+
+    /// Contents of the cache file: length of the identity, the identity and compiled data.
+    fn file_contents(&self, data: &[u8]) -> Vec<u8> {
+        let identity = self.identity();
+        let mut contents = Vec::with_capacity(8 + identity.len() + data.len());
+        contents.extend_from_slice(&(identity.len() as u64).to_le_bytes());
+        contents.extend_from_slice(&identity);
+        contents.extend_from_slice(data);
+        contents
+    }
+
+    /// Returns compiled data from the cache file contents, `None` if the file belongs to
+    /// a different script with the same file name.
+    fn compiled_data<'a>(&self, contents: &'a [u8]) -> Option<&'a [u8]> {
+        let (length, rest) = contents.split_first_chunk::<8>()?;
+        let length = usize::try_from(u64::from_le_bytes(*length)).ok()?;
+        let identity = rest.get(..length)?;
+        (identity == self.identity().as_slice()).then(|| &rest[length..])
+    }
+
+    fn identity(&self) -> Vec<u8> {
+        [
+            self.language.name().as_bytes(),
+            self.options.as_bytes(),
+            self.source.as_bytes(),
+        ]
+        .join(&0)
+    }
+}
+
+/// Removes the key from the compilations in progress and wakes up waiting threads when
+/// dropped, including when the compilation panics.
+struct Compiling<'a> {
+    cache: &'a ScriptCache,
+    key: &'a ScriptCacheKey,
+}
+
+impl Drop for Compiling<'_> {
+    fn drop(&mut self) {
+        self.cache.lock().compiling.remove(self.key);
+        self.cache.compiled.notify_all();
+    }
+}
+
+/// Counters of a [`ScriptCache`]. Returned by [`ScriptCache::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ScriptCacheStats {
+    /// Number of scripts loaded from memory.
+    pub hits: u64,
+    /// Number of scripts loaded from the cache directory.
+    pub disk_hits: u64,
+    /// Number of scripts compiled from source.
+    pub misses: u64,
+    /// Number of entries evicted from memory.
+    pub evictions: u64,
+    /// Number of entries currently kept in memory.
+    pub entries: usize,
+}
+
+#[derive(Debug)]
+struct Entry {
+    data: Arc<Vec<u8>>,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<ScriptCacheKey, Entry>,
+    compiling: HashSet<ScriptCacheKey>,
+    tick: u64,
+    stats: ScriptCacheStats,
+}
+
+impl State {
+    fn get(&mut self, key: &ScriptCacheKey) -> Option<Arc<Vec<u8>>> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.entries.get_mut(key).map(|entry| {
+            entry.last_used = tick;
+            entry.data.clone()
+        })
+    }
+
+    fn insert(&mut self, key: ScriptCacheKey, data: Arc<Vec<u8>>, capacity: usize) {
+        self.tick += 1;
+        let last_used = self.tick;
+        self.entries.insert(key, Entry { data, last_used });
+        while self.entries.len() > capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => {
+                    self.entries.remove(&oldest);
+                    self.stats.evictions += 1;
+                }
+                None => break,
+            }
+        }
+        self.stats.entries = self.entries.len();
+    }
+}
+
+/// Cache of compiled scripts, shared between threads.
+///
+/// Scripts are keyed by language, source and enabled helpers. Compiling a script which was
+/// already compiled loads the compiled data instead of compiling the source again. Least
+/// recently used entries are evicted when the capacity is exceeded. Compiled data can optionally
+/// be persisted to a directory, so it is reused across process restarts.
+///
+/// Scripts are compiled on the calling thread, the same way as [`Script::compile`], so like
+/// executions, compilations have to be made on the main thread. The cache only deduplicates
+/// them: compiling a script while the same script is being compiled waits for that compilation
+/// and loads its data.
+///
+/// ## Example
+///
+/// ```
+/// use osakit::{Language, Script, ScriptCache, Value};
+///
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// let cache = ScriptCache::new(32);
+/// for _ in 0..2 {
+///     let mut script = Script::new_from_source(Language::AppleScript, "return 1");
+///     cache.compile(&mut script)?;
+///     assert_eq!(script.execute()?, Value::from(1));
+/// }
+/// assert_eq!(cache.stats().hits, 1);
+/// #
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ScriptCache {
+    capacity: usize,
+    directory: Option<PathBuf>,
+    state: Mutex<State>,
+    compiled: Condvar,
+}
+
+impl ScriptCache {
+    /// Constructs a cache keeping at most `capacity` compiled scripts in memory.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            directory: None,
+            state: Mutex::new(State::default()),
+            compiled: Condvar::new(),
+        }
+    }
+
+    /// Persists compiled scripts to the directory and loads them from there on memory misses.
+    /// The directory is created when the first script is written.
+    pub fn with_directory<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    /// Compiles the script using cached compiled data if available.
+    /// Same as [`Script::compile`] otherwise.
+    pub fn compile(&self, script: &mut Script) -> Result<(), ScriptCompilationError> {
+        if script.is_compiled() {
+            return Ok(());
+        }
+        let key = script.cache_key();
+        let mut state = self.lock();
+        loop {
+            if let Some(data) = state.get(&key) {
+                drop(state);
+                if script.load_compiled_data(&data, Instant::now()) {
+                    self.lock().stats.hits += 1;
+                    return Ok(());
+                }
+                return script.compile();
+            }
+            if !state.compiling.contains(&key) {
+                break;
+            }
+            state = self
+                .compiled
+                .wait(state)
+                .unwrap_or_else(|error| error.into_inner());
+        }
+        state.compiling.insert(key.clone());
+        drop(state);
+
+        let compiling = Compiling {
+            cache: self,
+            key: &key,
+        };
+        let result = self.compile_uncached(script, &key);
+        if let Ok(Some(data)) = &result {
+            self.lock().insert(key.clone(), data.clone(), self.capacity);
+        }
+        drop(compiling);
+        result.map(|_| ())
+    }
+
+    fn compile_uncached(
+        &self,
+        script: &mut Script,
+        key: &ScriptCacheKey,
+    ) -> Result<Option<Arc<Vec<u8>>>, ScriptCompilationError> {
+        let path = self
+            .directory
+            .as_ref()
+            .map(|directory| directory.join(key.file_name()));
+        if let Some(path) = &path {
+            let started = Instant::now();
+            if let Ok(contents) = std::fs::read(path) {
+                if let Some(data) = key.compiled_data(&contents) {
+                    if script.load_compiled_data(data, started) {
+                        self.lock().stats.disk_hits += 1;
+                        return Ok(Some(Arc::new(data.to_vec())));
+                    }
+                }
+            }
+        }
+        self.lock().stats.misses += 1;
+        script.compile()?;
+        let data = match script.compiled_data() {
+            Some(data) => Arc::new(data),
+            None => return Ok(None),
+        };
+        if let (Some(directory), Some(path)) = (&self.directory, &path) {
+            // Persisting is an optimization, the script is already compiled at this point.
+            let _ = std::fs::create_dir_all(directory)
+                .and_then(|_| std::fs::write(path, key.file_contents(&data)));
+        }
+        Ok(Some(data))
+    }
+
+    /// Returns a copy of the cache counters.
+    pub fn stats(&self) -> ScriptCacheStats {
+        self.lock().stats
+    }
+
+    /// Removes all entries from memory. Files in the cache directory are kept.
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.entries.clear();
+        state.stats.entries = 0;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+impl Default for ScriptCache {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Value;
+
+    fn key(source: &str) -> ScriptCacheKey {
+        ScriptCacheKey {
+            language: Language::AppleScript,
+            source: source.to_string(),
+            options: String::new(),
+        }
+    }
+
+    #[test]
+    fn it_evicts_least_recently_used_entries() {
+        let mut state = State::default();
+        state.insert(key("1"), Arc::new(vec![1]), 2);
+        state.insert(key("2"), Arc::new(vec![2]), 2);
+        assert!(state.get(&key("1")).is_some());
+        state.insert(key("3"), Arc::new(vec![3]), 2);
+        assert!(state.get(&key("2")).is_none());
+        assert!(state.get(&key("1")).is_some());
+        assert!(state.get(&key("3")).is_some());
+        assert_eq!(state.stats.evictions, 1);
+        assert_eq!(state.stats.entries, 2);
+    }
+
+    #[test]
+    fn it_names_cache_files() {
+        assert_eq!(
+            key("").file_name(),
+            "applescript-cbf29ce484222325-cbf29ce484222325.scpt"
+        );
+    }
+
+    #[test]
+    fn it_checks_identity_of_cache_files() {
+        let contents = key("return 1").file_contents(&[1, 2, 3]);
+        assert_eq!(
+            key("return 1").compiled_data(&contents),
+            Some([1, 2, 3].as_slice())
+        );
+        assert_eq!(key("return 2").compiled_data(&contents), None);
+        assert_eq!(key("return 1").compiled_data(&contents[..4]), None);
+        assert_eq!(key("return 1").compiled_data(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn it_finishes_compilations_on_panics() {
+        let cache = ScriptCache::new(8);
+        let key = key("return 1");
+        cache.lock().compiling.insert(key.clone());
+        let result = std::panic::catch_unwind(|| {
+            let _compiling = Compiling {
+                cache: &cache,
+                key: &key,
+            };
+            panic!("compilation failed");
+        });
+        assert!(result.is_err());
+        assert!(cache.lock().compiling.is_empty());
+    }
+
+    #[test]
+    fn it_reuses_compiled_scripts() {
+        let cache = ScriptCache::new(8);
+        for _ in 0..3 {
+            let mut script = Script::new_from_source(
+                Language::AppleScript,
+                "on concat(x, y)
+                    return x & y
+                end concat",
+            );
+            cache.compile(&mut script).unwrap();
+            assert_eq!(
+                script.execute_function("concat", vec![Value::from("a"), Value::from("b")]),
+                Ok(Value::from("ab"))
+            );
+        }
+        let mut script =
+            Script::new_from_source(Language::AppleScript, "return 1").with_log_capture();
+        cache.compile(&mut script).unwrap();
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.entries, 2);
+    }
+
+    #[test]
+    fn it_waits_for_compilations_in_progress() {
+        let mut compiled = Script::new_from_source(Language::JavaScript, "function f() {}");
+        compiled.compile().unwrap();
+        let data = Arc::new(compiled.compiled_data().unwrap());
+        let key = compiled.cache_key();
+        let cache = ScriptCache::new(8);
+        cache.lock().compiling.insert(key.clone());
+        std::thread::scope(|scope| {
+            // Finishes the compilation in progress, without using OSAKit off the main thread.
+            scope.spawn(|| {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                cache
+                    .lock()
+                    .insert(key.clone(), data.clone(), cache.capacity);
+                drop(Compiling {
+                    cache: &cache,
+                    key: &key,
+                });
+            });
+            let mut script = Script::new_from_source(Language::JavaScript, "function f() {}");
+            cache.compile(&mut script).unwrap();
+            assert!(script.is_compiled());
+        });
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 0);
+        assert_eq!(stats.hits, 1);
+    }
+
+    #[test]
+    fn it_persists_compiled_scripts() {
+        let directory = std::env::temp_dir().join("osakit_it_persists_compiled_scripts");
+        let _ = std::fs::remove_dir_all(&directory);
+        let source = "return \"persisted\"";
+
+        let cache = ScriptCache::new(8).with_directory(&directory);
+        let mut script = Script::new_from_source(Language::AppleScript, source);
+        cache.compile(&mut script).unwrap();
+        assert_eq!(cache.stats().misses, 1);
+
+        let cache = ScriptCache::new(8).with_directory(&directory);
+        let mut script = Script::new_from_source(Language::AppleScript, source);
+        cache.compile(&mut script).unwrap();
+        assert_eq!(cache.stats().disk_hits, 1);
+        assert_eq!(script.execute(), Ok(Value::from("persisted")));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn it_does_not_cache_compilation_errors() {
+        let cache = ScriptCache::new(8);
+        let mut script = Script::new_from_source(Language::AppleScript, "return (");
+        assert!(cache.compile(&mut script).is_err());
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
@@ -0,0 +1,592 @@
+use crate::script::{Language, ScriptCompilationError, ScriptExecutionError};
+use crate::value::Value;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Environment variable switching [`Cassette::from_env`] to recording when set to `record`.
+pub const CASSETTE_MODE_VARIABLE: &str = "OSAKIT_CASSETTE";
+
+/// Mode of a [`Cassette`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Calls are executed and their results are recorded.
+    Record,
+    /// Results are served from the cassette without executing scripts.
+    Replay,
+}
+
+/// Error happening when loading or saving a [`Cassette`].
+#[derive(Error, Debug)]
+pub enum CassetteError {
+    #[error("cassette file error")]
+    Io(#[from] std::io::Error),
+    #[error("cassette format error")]
+    Format(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CallKind {
+    Compile,
+    Execute,
+    ExecuteFunction,
+}
+
+/// Recorded `compile`, `execute` or `execute_function` call together with its result.
+/// Scripts are identified by language, source hash, enabled helpers and declared globals.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Interaction {
+    call: CallKind,
+    language: Language,
+    source_hash: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    handler: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    arguments: Option<Vec<Value>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    options: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    globals: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compilation_error: Option<ScriptCompilationError>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    execution_error: Option<ScriptExecutionError>,
+}
+
+impl Interaction {
+    fn new(call: CallKind, language: Language, source_hash: u64) -> Self {
+        Self {
+            call,
+            language,
+            source_hash,
+            handler: None,
+            arguments: None,
+            options: Vec::new(),
+            globals: Vec::new(),
+            result: None,
+            compilation_error: None,
+            execution_error: None,
+        }
+    }
+
+    pub(crate) fn compile(language: Language, source_hash: u64) -> Self {
+        Self::new(CallKind::Compile, language, source_hash)
+    }
+
+    pub(crate) fn execute(language: Language, source_hash: u64) -> Self {
+        Self::new(CallKind::Execute, language, source_hash)
+    }
+
+    pub(crate) fn execute_function(
+        language: Language,
+        source_hash: u64,
+        handler: &str,
+        arguments: &[Value],
+    ) -> Self {
+        Self {
+            handler: Some(handler.to_string()),
+            arguments: Some(arguments.to_vec()),
+            ..Self::new(CallKind::ExecuteFunction, language, source_hash)
+        }
+    }
+
+    /// Sets names of the enabled helpers and declarations of the globals, so calls of scripts
+    /// with a different state do not match.
+    pub(crate) fn with_state<'a>(
+        mut self,
+        options: impl IntoIterator<Item = (&'a str, bool)>,
+        globals: impl IntoIterator<Item = &'a String>,
+    ) -> Self {
+        self.options = options
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| name.to_string())
+            .collect();
+        self.globals = globals.into_iter().cloned().collect();
+        self
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn with_compile_result(
+        mut self,
+        result: &Result<(), ScriptCompilationError>,
+    ) -> Self {
+        self.compilation_error = result.as_ref().err().cloned();
+        self
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn with_execute_result(
+        mut self,
+        result: &Result<Value, ScriptExecutionError>,
+    ) -> Self {
+        match result {
+            // `null` results are omitted, since they are deserialized as `None` anyway.
+            Ok(value) => self.result = Some(value.clone()).filter(|value| !value.is_null()),
+            Err(error) => self.execution_error = Some(error.clone()),
+        }
+        self
+    }
+
+    pub(crate) fn compile_result(self) -> Result<(), ScriptCompilationError> {
+        self.compilation_error.map_or(Ok(()), Err)
+    }
+
+    pub(crate) fn execute_result(self) -> Result<Value, ScriptExecutionError> {
+        match self.execution_error {
+            Some(error) => Err(error),
+            None => Ok(self.result.unwrap_or(Value::Null)),
+        }
+    }
+
+    fn matches(&self, call: &Interaction) -> bool {
+        self.call == call.call
+            && self.language == call.language
+            && self.source_hash == call.source_hash
+            && self.handler == call.handler
+            && self.arguments == call.arguments
+            && self.options == call.options
+            && self.globals == call.globals
+    }
+
+    fn describe(&self) -> String {
+        let call = match (&self.handler, &self.arguments) {
+            (Some(handler), Some(arguments)) => format!(
+                "execute_function `{}` with arguments {}",
+                handler,
+                Value::Array(arguments.clone())
+            ),
+            _ => format!("{:?}", self.call).to_lowercase(),
+        };
+        format!(
+            "{} of {} script with source hash {:016x}",
+            call,
+            self.language.name(),
+            self.source_hash
+        )
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+/// Recording of script calls allowing to run tests without executing scripts, i.e. on CI
+/// machines without automation permissions or on platforms other than macOS.
+///
+/// When recording, every `compile`, `execute` and `execute_function` call of [`crate::Script`]
+/// is executed and stored together with the result, including calls made by helpers, i.e.
+/// batches, captured logs and property access. When replaying, results are served from
+/// the cassette without executing scripts; calls are matched by language, source hash, enabled
+/// helpers, declared globals, handler name and arguments. Every recorded call is served once,
+/// identical calls in the recorded order, while calls to different handlers or scripts may be
+/// made in any order. Calls missing from the cassette panic.
+///
+/// Cassettes are inserted for the current thread, so scripts constructed by
+/// [`crate::declare_script!`] are recorded and replayed as well.
+///
+/// ## Example
+///
+/// ```no_run
+/// use osakit::{Cassette, Language, Script, Value};
+///
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// // Records with `OSAKIT_CASSETTE=record`, replays otherwise.
+/// let cassette = Cassette::from_env("tests/cassettes/concat.json")?.insert();
+/// let mut script = Script::new_from_source(
+///     Language::JavaScript,
+///     "function concat(x, y) { return x + y; }",
+/// );
+/// script.compile()?;
+/// assert_eq!(
+///     script.execute_function("concat", vec![Value::from("a"), Value::from("b")])?,
+///     Value::from("ab")
+/// );
+/// cassette.finish()?;
+/// #
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: CassetteMode,
+    interactions: Vec<Interaction>,
+    used: Vec<bool>,
+}
+
+impl Cassette {
+    /// Constructs an empty cassette, recorded calls are written to the path.
+    /// Recording requires macOS.
+    pub fn record<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            mode: CassetteMode::Record,
+            interactions: Vec::new(),
+            used: Vec::new(),
+        }
+    }
+
+    /// Loads a previously recorded cassette for replaying.
+    pub fn replay<P: Into<PathBuf>>(path: P) -> Result<Self, CassetteError> {
+        let path = path.into();
+        let file: CassetteFile = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+        Ok(Self {
+            path,
+            mode: CassetteMode::Replay,
+            used: vec![false; file.interactions.len()],
+            interactions: file.interactions,
+        })
+    }
+
+    /// Records if [`CASSETTE_MODE_VARIABLE`] is set to `record`, replays otherwise.
+    pub fn from_env<P: Into<PathBuf>>(path: P) -> Result<Self, CassetteError> {
+        match std::env::var(CASSETTE_MODE_VARIABLE).as_deref() {
+            Ok("record") => Ok(Self::record(path)),
+            _ => Self::replay(path),
+        }
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes recorded calls to the cassette file.
+    pub fn save(&self) -> Result<(), CassetteError> {
+        let file = CassetteFile {
+            interactions: self.interactions.clone(),
+        };
+        if let Some(directory) = self.path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+
+    /// Inserts the cassette for the current thread until the returned guard is dropped.
+    pub fn insert(self) -> CassetteGuard {
+        let previous = CASSETTE.with(|cassette| cassette.borrow_mut().replace(self));
+        CassetteGuard {
+            previous,
+            finished: false,
+            thread_bound: PhantomData,
+        }
+    }
+
+    fn take_match(&mut self, call: &Interaction) -> Option<Interaction> {
+        let index = self
+            .interactions
+            .iter()
+            .zip(&self.used)
+            .position(|(interaction, used)| !used && interaction.matches(call))?;
+        self.used[index] = true;
+        Some(self.interactions[index].clone())
+    }
+}
+
+/// Keeps a [`Cassette`] inserted for the current thread.
+///
+/// Dropping the guard removes the cassette and saves recorded calls ignoring errors, use
+/// [`CassetteGuard::finish`] to handle them.
+#[derive(Debug)]
+pub struct CassetteGuard {
+    previous: Option<Cassette>,
+    finished: bool,
+    thread_bound: PhantomData<*const ()>,
+}
+
+impl CassetteGuard {
+    /// Removes the cassette and saves recorded calls.
+    pub fn finish(mut self) -> Result<(), CassetteError> {
+        self.finished = true;
+        match self.remove() {
+            Some(cassette) if cassette.mode == CassetteMode::Record => cassette.save(),
+            _ => Ok(()),
+        }
+    }
+
+    fn remove(&mut self) -> Option<Cassette> {
+        let previous = self.previous.take();
+        CASSETTE.with(|cassette| std::mem::replace(&mut *cassette.borrow_mut(), previous))
+    }
+}
+
+impl Drop for CassetteGuard {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Some(cassette) = self.remove() {
+            if cassette.mode == CassetteMode::Record {
+                let _ = cassette.save();
+            }
+        }
+    }
+}
+
+thread_local! {
+    static CASSETTE: RefCell<Option<Cassette>> = const { RefCell::new(None) };
+}
+
+/// Returns the recorded interaction if a cassette is replaying, `None` otherwise.
+/// Panics if the call was not recorded.
+pub(crate) fn replay<F: FnOnce() -> Interaction>(call: F) -> Option<Interaction> {
+    CASSETTE.with(|cassette| {
+        let mut cassette = cassette.borrow_mut();
+        let cassette = cassette
+            .as_mut()
+            .filter(|cassette| cassette.mode == CassetteMode::Replay)?;
+        let call = call();
+        match cassette.take_match(&call) {
+            Some(interaction) => Some(interaction),
+            None => panic!(
+                "no recorded call in cassette `{}` matches {}",
+                cassette.path.display(),
+                call.describe()
+            ),
+        }
+    })
+}
+
+/// Stores the interaction if a cassette is recording.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn record<F: FnOnce() -> Interaction>(interaction: F) {
+    CASSETTE.with(|cassette| {
+        if let Some(cassette) = cassette.borrow_mut().as_mut() {
+            if cassette.mode == CassetteMode::Record {
+                cassette.interactions.push(interaction());
+                cassette.used.push(true);
+            }
+        }
+    });
+}
+
+/// Returns `true` if a cassette is replaying on the current thread.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn is_replaying() -> bool {
+    inserted_mode() == Some(CassetteMode::Replay)
+}
+
+/// Returns `true` if a cassette is recording on the current thread.
+/// Always `false` on platforms other than macOS, where scripts can only be replayed.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn is_recording() -> bool {
+    cfg!(target_os = "macos") && inserted_mode() == Some(CassetteMode::Record)
+}
+
+fn inserted_mode() -> Option<CassetteMode> {
+    CASSETTE.with(|cassette| cassette.borrow().as_ref().map(|cassette| cassette.mode))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::js_exception::JsException;
+    use crate::script::Script;
+    use crate::trace::hash_source;
+    use serde_json::json;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    const SOURCE: &str = "function concat(x, y) { return x + y; }";
+
+    fn cassette_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("osakit_{}.json", name))
+    }
+
+    fn concat_interactions() -> Vec<Interaction> {
+        let hash = hash_source(SOURCE);
+        let arguments = [Value::from("a"), Value::from("b")];
+        vec![
+            Interaction::compile(Language::JavaScript, hash).with_compile_result(&Ok(())),
+            Interaction::execute_function(Language::JavaScript, hash, "concat", &arguments)
+                .with_execute_result(&Ok(Value::from("ab"))),
+            Interaction::execute_function(Language::JavaScript, hash, "concat", &arguments)
+                .with_execute_result(&Err(ScriptExecutionError::JsException(JsException {
+                    name: Some(String::from("TypeError")),
+                    message: String::from("x is not a function"),
+                    ..JsException::default()
+                }))),
+            Interaction::execute(Language::JavaScript, hash).with_execute_result(&Ok(Value::Null)),
+        ]
+    }
+
+    fn write_cassette(name: &str, interactions: Vec<Interaction>) -> PathBuf {
+        let path = cassette_path(name);
+        let mut cassette = Cassette::record(&path);
+        cassette.interactions = interactions;
+        cassette.save().unwrap();
+        path
+    }
+
+    #[test]
+    fn it_serializes_interactions() {
+        let interactions = concat_interactions();
+        let json = serde_json::to_value(&interactions).unwrap();
+        assert_eq!(
+            json[1],
+            json!({
+                "call": "execute_function",
+                "language": "JavaScript",
+                "source_hash": hash_source(SOURCE),
+                "handler": "concat",
+                "arguments": ["a", "b"],
+                "result": "ab",
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<Vec<Interaction>>(json).unwrap(),
+            interactions
+        );
+    }
+
+    #[test]
+    fn it_replays_recorded_calls() {
+        let path = write_cassette("it_replays_recorded_calls", concat_interactions());
+        let cassette = Cassette::replay(&path).unwrap().insert();
+
+        let mut script = Script::new_from_source(Language::JavaScript, SOURCE);
+        script.compile().unwrap();
+        let arguments = || vec![Value::from("a"), Value::from("b")];
+        assert_eq!(
+            script.execute_function("concat", arguments()),
+            Ok(Value::from("ab"))
+        );
+        assert!(matches!(
+            script.execute_function("concat", arguments()),
+            Err(ScriptExecutionError::JsException(exception)) if exception.message == "x is not a function"
+        ));
+        assert_eq!(script.execute(), Ok(Value::Null));
+
+        cassette.finish().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_records_batches_and_helper_calls() {
+        use crate::batch::BatchMode;
+
+        let path = cassette_path("it_records_batches_and_helper_calls");
+        let calls = [("concat", vec![Value::from("a"), Value::from("b")])];
+        let script = || {
+            Script::new_from_source(Language::JavaScript, SOURCE)
+                .with_batching()
+                .with_log_capture()
+        };
+        let cassette = Cassette::record(&path).insert();
+        let mut recorded = script();
+        recorded.compile().unwrap();
+        let results = recorded.execute_batch(&calls, BatchMode::StopOnFirstError);
+        assert_eq!(results, vec![Ok(Value::from("ab"))]);
+        cassette.finish().unwrap();
+
+        let cassette = Cassette::replay(&path).unwrap().insert();
+        let mut replayed = script();
+        replayed.compile().unwrap();
+        assert_eq!(
+            replayed.execute_batch(&calls, BatchMode::StopOnFirstError),
+            results
+        );
+        cassette.finish().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_panics_on_unmatched_calls() {
+        let path = write_cassette("it_panics_on_unmatched_calls", concat_interactions());
+        let cassette = Cassette::replay(&path).unwrap().insert();
+
+        let mut script = Script::new_from_source(Language::JavaScript, SOURCE);
+        script.compile().unwrap();
+        let error = catch_unwind(AssertUnwindSafe(|| {
+            script.execute_function("concat", vec![Value::from("c")])
+        }))
+        .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<String>().map(String::as_str),
+            Some(
+                format!(
+                    "no recorded call in cassette `{}` matches execute_function `concat` with \
+                    arguments [\"c\"] of JavaScript script with source hash {:016x}",
+                    path.display(),
+                    hash_source(SOURCE)
+                )
+                .as_str()
+            )
+        );
+
+        drop(cassette);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_restores_previous_cassette() {
+        let outer = Cassette::record(cassette_path("it_restores_previous_cassette")).insert();
+        let inner = Cassette::replay(write_cassette(
+            "it_restores_previous_cassette_inner",
+            vec![],
+        ))
+        .unwrap()
+        .insert();
+        assert_eq!(inserted_mode(), Some(CassetteMode::Replay));
+        inner.finish().unwrap();
+        assert_eq!(inserted_mode(), Some(CassetteMode::Record));
+        drop(outer);
+        assert_eq!(inserted_mode(), None);
+        std::fs::remove_file(cassette_path("it_restores_previous_cassette")).unwrap();
+        std::fs::remove_file(cassette_path("it_restores_previous_cassette_inner")).unwrap();
+    }
+
+    #[cfg(feature = "declare-script")]
+    crate::declare_script! {
+        #[language(JavaScript)]
+        #[source("function concat(x, y) { return x + y; }")]
+        CassetteTestScript {
+            fn concat(x: &str, y: &str) -> String;
+        }
+    }
+
+    #[cfg(feature = "declare-script")]
+    #[test]
+    fn it_replays_declared_scripts() {
+        let path = write_cassette("it_replays_declared_scripts", concat_interactions());
+        let cassette = Cassette::replay(&path).unwrap().insert();
+
+        let script = CassetteTestScript::new().unwrap();
+        assert_eq!(script.concat("a", "b").unwrap(), "ab");
+
+        cassette.finish().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_records_calls() {
+        let path = cassette_path("it_records_calls");
+        let cassette = Cassette::record(&path).insert();
+        let mut script = Script::new_from_source(Language::JavaScript, SOURCE);
+        script.compile().unwrap();
+        let arguments = vec![Value::from("a"), Value::from("b")];
+        assert_eq!(
+            script.execute_function("concat", arguments.clone()),
+            Ok(Value::from("ab"))
+        );
+        cassette.finish().unwrap();
+
+        let recorded = Cassette::replay(&path).unwrap().interactions;
+        assert_eq!(recorded, concat_interactions()[..2].to_vec());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::{Display, Formatter};
+
+macro_rules! osa_error_codes {
+    ($($(#[$meta:meta])* $variant:ident = $number:literal: $description:literal),*$(,)?) => {
+        /// Well-known `Apple Event` / `AppleScript` error codes.
+        ///
+        /// Codes which are not listed are represented using [`OsaErrorCode::Other`].
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum OsaErrorCode {
+            $(
+                $(#[$meta])*
+                $variant,
+            )*
+            /// Any other error code.
+            Other(i32),
+        }
+
+        const OSA_ERROR_CODES: &[(OsaErrorCode, i32, &str)] = &[
+            $((OsaErrorCode::$variant, $number, $description),)*
+        ];
+    };
+}
+
+osa_error_codes! {
+    /// `-43`: file wasn't found.
+    FileNotFound = -43: "file not found",
+    /// `-128`: user cancelled the operation.
+    UserCancelled = -128: "user cancelled",
+    /// `-600`: application isn't running.
+    AppNotRunning = -600: "application isn't running",
+    /// `-609`: connection is invalid, i.e. the application quit while handling an event.
+    ConnectionInvalid = -609: "connection is invalid",
+    /// `-1700`: value can't be coerced to the requested type.
+    CantCoerce = -1700: "can't make value into type",
+    /// `-1701`: required parameter is missing.
+    ParameterMissing = -1701: "parameter is missing",
+    /// `-1703`: wrong data type.
+    WrongDataType = -1703: "wrong data type",
+    /// `-1708`: object doesn't understand the message.
+    DoesNotUnderstand = -1708: "doesn't understand the message",
+    /// `-1712`: Apple Event timed out.
+    Timeout = -1712: "event timed out",
+    /// `-1713`: user interaction isn't allowed.
+    NoUserInteraction = -1713: "no user interaction allowed",
+    /// `-1719`: invalid index.
+    InvalidIndex = -1719: "invalid index",
+    /// `-1728`: referenced object can't be found.
+    CantGet = -1728: "can't get object",
+    /// `-1743`: not authorized to send Apple Events to the application.
+    NotAuthorized = -1743: "not authorized to send Apple Events",
+    /// `-1744`: sending Apple Events would require user consent.
+    UserConsentRequired = -1744: "user consent required",
+    /// `-2700`: generic script error.
+    GeneralError = -2700: "script error",
+    /// `-2740`: syntax error.
+    SyntaxError = -2740: "syntax error",
+    /// `-2753`: variable isn't defined.
+    UndefinedVariable = -2753: "variable isn't defined",
+    /// `-10000`: Apple Event handler failed.
+    HandlerFailed = -10000: "Apple Event handler failed",
+    /// `-10004`: privilege violation.
+    PrivilegeViolation = -10004: "privilege violation",
+    /// `-10006`: property can't be set.
+    CantSet = -10006: "can't set property",
+}
+
+impl OsaErrorCode {
+    /// Constructs error code from the error number.
+    pub fn from_number(number: i32) -> Self {
+        OSA_ERROR_CODES
+            .iter()
+            .find(|(_, code_number, _)| *code_number == number)
+            .map(|(code, _, _)| *code)
+            .unwrap_or(OsaErrorCode::Other(number))
+    }
+
+    /// Parses error code from the `"... (-1728)"` suffix used in `osascript`-style error text.
+    ///
+    /// ```
+    /// use osakit::OsaErrorCode;
+    ///
+    /// assert_eq!(
+    ///     OsaErrorCode::from_error_text("execution error: Can’t get window 1. (-1728)"),
+    ///     Some(OsaErrorCode::CantGet)
+    /// );
+    /// assert_eq!(OsaErrorCode::from_error_text("Can’t get window 1."), None);
+    /// ```
+    pub fn from_error_text(text: &str) -> Option<Self> {
+        let text = text.trim_end();
+        let text = text.strip_suffix(')')?;
+        let start = text.rfind('(')?;
+        text[start + 1..]
+            .trim()
+            .parse::<i32>()
+            .ok()
+            .map(Self::from_number)
+    }
+
+    /// Error number corresponding to the code.
+    pub fn number(&self) -> i32 {
+        match self {
+            OsaErrorCode::Other(number) => *number,
+            code => OSA_ERROR_CODES
+                .iter()
+                .find(|(known_code, _, _)| known_code == code)
+                .map(|(_, number, _)| *number)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Short description of the error code, `None` for [`OsaErrorCode::Other`].
+    pub fn description(&self) -> Option<&'static str> {
+        OSA_ERROR_CODES
+            .iter()
+            .find(|(known_code, _, _)| known_code == self)
+            .map(|(_, _, description)| *description)
+    }
+
+    /// Returns `true` if the error is likely transient and the operation may succeed if retried,
+    /// i.e. when the application is launching or busy.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            OsaErrorCode::AppNotRunning | OsaErrorCode::ConnectionInvalid | OsaErrorCode::Timeout
+        )
+    }
+
+    /// Returns `true` if the error is caused by missing automation permissions.
+    pub fn is_permission_denied(&self) -> bool {
+        matches!(
+            self,
+            OsaErrorCode::NotAuthorized
+                | OsaErrorCode::UserConsentRequired
+                | OsaErrorCode::PrivilegeViolation
+        )
+    }
+}
+
+impl From<i32> for OsaErrorCode {
+    fn from(number: i32) -> Self {
+        Self::from_number(number)
+    }
+}
+
+impl Display for OsaErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.description() {
+            Some(description) => write!(f, "{} ({})", description, self.number()),
+            None => write!(f, "error {}", self.number()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_converts_known_numbers() {
+        assert_eq!(OsaErrorCode::from_number(-128), OsaErrorCode::UserCancelled);
+        assert_eq!(OsaErrorCode::from_number(-1728), OsaErrorCode::CantGet);
+        assert_eq!(
+            OsaErrorCode::from_number(-1743),
+            OsaErrorCode::NotAuthorized
+        );
+        assert_eq!(
+            OsaErrorCode::from_number(-2753),
+            OsaErrorCode::UndefinedVariable
+        );
+    }
+
+    #[test]
+    fn it_converts_unknown_numbers() {
+        assert_eq!(OsaErrorCode::from_number(42), OsaErrorCode::Other(42));
+        assert_eq!(OsaErrorCode::Other(42).number(), 42);
+    }
+
+    #[test]
+    fn it_converts_codes_back_to_numbers() {
+        for (code, number, _) in OSA_ERROR_CODES {
+            assert_eq!(code.number(), *number);
+            assert_eq!(OsaErrorCode::from_number(*number), *code);
+        }
+    }
+
+    #[test]
+    fn it_parses_error_text() {
+        assert_eq!(
+            OsaErrorCode::from_error_text("execution error: Can’t get window 1. (-1728)"),
+            Some(OsaErrorCode::CantGet)
+        );
+        assert_eq!(
+            OsaErrorCode::from_error_text("User canceled. (-128)\n"),
+            Some(OsaErrorCode::UserCancelled)
+        );
+        assert_eq!(
+            OsaErrorCode::from_error_text("Custom error (1234)"),
+            Some(OsaErrorCode::Other(1234))
+        );
+    }
+
+    #[test]
+    fn it_ignores_text_without_error_number() {
+        assert_eq!(OsaErrorCode::from_error_text("Can’t get window 1."), None);
+        assert_eq!(OsaErrorCode::from_error_text("Can’t get (window 1)"), None);
+        assert_eq!(OsaErrorCode::from_error_text(""), None);
+    }
+
+    #[test]
+    fn it_classifies_error_codes() {
+        assert!(OsaErrorCode::Timeout.is_retryable());
+        assert!(OsaErrorCode::AppNotRunning.is_retryable());
+        assert!(!OsaErrorCode::CantGet.is_retryable());
+        assert!(OsaErrorCode::NotAuthorized.is_permission_denied());
+        assert!(OsaErrorCode::PrivilegeViolation.is_permission_denied());
+        assert!(!OsaErrorCode::UserCancelled.is_permission_denied());
+    }
+
+    #[test]
+    fn it_supports_display() {
+        assert_eq!(
+            OsaErrorCode::CantGet.to_string(),
+            "can't get object (-1728)"
+        );
+        assert_eq!(OsaErrorCode::Other(42).to_string(), "error 42");
+    }
+}
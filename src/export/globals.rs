@@ -0,0 +1,285 @@
+use crate::script::{Language, ScriptCompilationError, ScriptExecutionError};
+use crate::value::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Error happening when setting a global. Returned by [`crate::Script::set_global`].
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScriptGlobalError {
+    /// Happens when the name is not a valid identifier.
+    #[error("invalid global name: `{0}`")]
+    InvalidName(String),
+    /// Happens when an object key cannot be used as an `AppleScript` record label.
+    #[error("object key cannot be used as a record label: `{0}`")]
+    InvalidKey(String),
+    /// Happens when the script cannot be recompiled after declaring the global.
+    #[error("recompilation error: {0}")]
+    Compilation(#[from] ScriptCompilationError),
+    /// Happens when the value of a declared global cannot be set on the compiled script.
+    #[error("execution error: {0}")]
+    Execution(Box<ScriptExecutionError>),
+}
+
+/// Name of the handler setting a value of a declared global on the compiled script.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) const SET_GLOBAL_HANDLER: &str = "__osakit_set_global";
+
+/// Source of the `JavaScript` helpers appended when globals are set. Values set using
+/// [`SET_GLOBAL_HANDLER`] are stored, so that declarations executed by the top-level code do
+/// not reset them to the values the script was compiled with.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+const JS_GLOBALS_EPILOGUE: &str = "
+;function __osakit_global(name, value) {
+    var store = Function('return this')().__osakit_globals;
+    return store && Object.prototype.hasOwnProperty.call(store, name) ? store[name] : value;
+}
+function __osakit_set_global(name, value) {
+    var global = Function('return this')();
+    (global.__osakit_globals = global.__osakit_globals || {})[name] = value;
+    global[name] = value;
+}
+";
+
+/// Converts a global to a declaration prepended to the source.
+/// Declarations are kept on a single line in case of `JavaScript`, so line numbers in error
+/// messages stay intact.
+pub(crate) fn global_declaration(
+    language: Language,
+    name: &str,
+    value: &Value,
+) -> Result<String, ScriptGlobalError> {
+    if !is_identifier(language, name) {
+        return Err(ScriptGlobalError::InvalidName(name.to_string()));
+    }
+    Ok(match language {
+        Language::AppleScript => {
+            format!("property |{}| : {}\n", name, apple_script_literal(value)?)
+        }
+        Language::JavaScript => format!(
+            "var {} = __osakit_global(\"{}\", {});",
+            name,
+            name,
+            java_script_literal(value)
+        ),
+    })
+}
+
+/// Generates helpers appended to the source, which set values of the declared globals without
+/// recompiling the script.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn globals_epilogue<'a, I: Iterator<Item = &'a str>>(
+    language: Language,
+    names: I,
+) -> String {
+    match language {
+        Language::AppleScript => format!(
+            "
+on __osakit_set_global(__osakit_name, __osakit_value)
+    considering case
+{}    end considering
+end __osakit_set_global
+",
+            names
+                .map(|name| format!(
+                    "        if __osakit_name is \"{}\" then set |{}| to __osakit_value\n",
+                    name, name
+                ))
+                .collect::<String>()
+        ),
+        Language::JavaScript => String::from(JS_GLOBALS_EPILOGUE),
+    }
+}
+
+/// Splits the prelude of a loaded script back into declarations of the named globals, which
+/// were generated in the order of names. Returns an empty map if the declarations cannot be
+/// found, i.e. when the decompiled `AppleScript` source was formatted differently.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn split_declarations(
+    language: Language,
+    prelude: &str,
+    names: &[String],
+) -> BTreeMap<String, String> {
+    let starts = names
+        .iter()
+        .map(|name| match language {
+            Language::AppleScript => prelude
+                .find(&format!("property |{}| :", name))
+                .or_else(|| prelude.find(&format!("property {} :", name))),
+            Language::JavaScript => {
+                prelude.find(&format!("var {} = __osakit_global(\"{}\"", name, name))
+            }
+        })
+        .collect::<Option<Vec<usize>>>();
+    let Some(mut starts) = starts.filter(|starts| starts.is_sorted()) else {
+        return BTreeMap::new();
+    };
+    // Declarations are followed by the log capturing prelude and the prelude end marker.
+    starts.push(
+        prelude
+            .find("var __osakit_logs")
+            .or_else(|| prelude.find("/*osakit:source*/"))
+            .or_else(|| prelude.find("(*osakit:source*)"))
+            .unwrap_or(prelude.len()),
+    );
+    names
+        .iter()
+        .zip(starts.windows(2))
+        .map(|(name, range)| (name.clone(), prelude[range[0]..range[1]].to_string()))
+        .collect()
+}
+
+fn is_identifier(language: Language, name: &str) -> bool {
+    let is_start = |c: char| c.is_ascii_alphabetic() || c == '_' || c == '$';
+    let is_part = |c: char| is_start(c) || c.is_ascii_digit();
+    name.starts_with(is_start)
+        && name.chars().all(is_part)
+        && (language == Language::JavaScript || !name.contains('$'))
+}
+
+fn apple_script_literal(value: &Value) -> Result<String, ScriptGlobalError> {
+    Ok(match value {
+        Value::Null => String::from("missing value"),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+        Value::Array(items) => format!(
+            "{{{}}}",
+            items
+                .iter()
+                .map(apple_script_literal)
+                .collect::<Result<Vec<_>, _>>()?
+                .join(", ")
+        ),
+        Value::Object(fields) => format!(
+            "{{{}}}",
+            fields
+                .iter()
+                .map(|(key, value)| {
+                    if key.is_empty() || key.contains(['|', '\\']) {
+                        return Err(ScriptGlobalError::InvalidKey(key.clone()));
+                    }
+                    Ok(format!("|{}|:{}", key, apple_script_literal(value)?))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .join(", ")
+        ),
+    })
+}
+
+fn java_script_literal(value: &Value) -> String {
+    // JSON is valid `JavaScript` except for line separators, which are not allowed in string
+    // literals by older engines.
+    value
+        .to_string()
+        .replace('\u{2028}', "\\u2028")
+        .replace('\u{2029}', "\\u2029")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_declares_apple_script_properties() {
+        assert_eq!(
+            global_declaration(
+                Language::AppleScript,
+                "config",
+                &json!({"name": "a \"quoted\" \\ value", "items": [1, 2.5, true, null]})
+            ),
+            Ok(String::from(
+                "property |config| : {|items|:{1, 2.5, true, missing value}, \
+                |name|:\"a \\\"quoted\\\" \\\\ value\"}\n"
+            ))
+        );
+    }
+
+    #[test]
+    fn it_declares_java_script_globals() {
+        assert_eq!(
+            global_declaration(
+                Language::JavaScript,
+                "$config",
+                &json!({"text": "line\nnext\u{2028}", "n": 1})
+            ),
+            Ok(String::from(
+                "var $config = __osakit_global(\"$config\", \
+                {\"n\":1,\"text\":\"line\\nnext\\u2028\"});"
+            ))
+        );
+    }
+
+    #[test]
+    fn it_generates_apple_script_setter() {
+        assert_eq!(
+            globals_epilogue(Language::AppleScript, ["config", "name"].into_iter()),
+            "
+on __osakit_set_global(__osakit_name, __osakit_value)
+    considering case
+        if __osakit_name is \"config\" then set |config| to __osakit_value
+        if __osakit_name is \"name\" then set |name| to __osakit_value
+    end considering
+end __osakit_set_global
+"
+        );
+    }
+
+    #[test]
+    fn it_splits_declarations() {
+        let names = [String::from("a"), String::from("b")];
+        assert_eq!(
+            split_declarations(
+                Language::JavaScript,
+                "var a = __osakit_global(\"a\", \"var b = 1\");\
+                var b = __osakit_global(\"b\", 2);/*osakit:source*/",
+                &names
+            ),
+            BTreeMap::from([
+                (
+                    String::from("a"),
+                    String::from("var a = __osakit_global(\"a\", \"var b = 1\");")
+                ),
+                (
+                    String::from("b"),
+                    String::from("var b = __osakit_global(\"b\", 2);")
+                ),
+            ])
+        );
+        assert_eq!(
+            split_declarations(
+                Language::AppleScript,
+                "property a : 1\rproperty |b| : 2\r(*osakit:source*)",
+                &names
+            ),
+            BTreeMap::from([
+                (String::from("a"), String::from("property a : 1\r")),
+                (String::from("b"), String::from("property |b| : 2\r")),
+            ])
+        );
+        assert!(split_declarations(Language::AppleScript, "", &names).is_empty());
+    }
+
+    #[test]
+    fn it_rejects_invalid_names() {
+        for name in ["", "1st", "a b", "a;b", "ä"] {
+            assert_eq!(
+                global_declaration(Language::JavaScript, name, &Value::Null),
+                Err(ScriptGlobalError::InvalidName(name.to_string()))
+            );
+        }
+        assert_eq!(
+            global_declaration(Language::AppleScript, "$a", &Value::Null),
+            Err(ScriptGlobalError::InvalidName(String::from("$a")))
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_record_labels() {
+        assert_eq!(
+            global_declaration(Language::AppleScript, "a", &json!([{"x|y": 1}])),
+            Err(ScriptGlobalError::InvalidKey(String::from("x|y")))
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use crate::properties::strip_comments;
+use crate::script::Language;
+
+/// Returns names of the top-level handlers declared in the source: `on name(...)` and
+/// `to name(...)` in `AppleScript`, `function name(...)` in `JavaScript`.
+/// Handlers of nested `script` objects, nested functions and helpers generated by `osakit`
+/// are skipped.
+pub(crate) fn handler_names(language: Language, source: &str) -> Vec<String> {
+    let names = match language {
+        Language::AppleScript => apple_script_handler_names(source),
+        Language::JavaScript => java_script_handler_names(source),
+    };
+    let mut unique: Vec<String> = Vec::new();
+    for name in names {
+        if !name.starts_with("__osakit") && !unique.contains(&name) {
+            unique.push(name);
+        }
+    }
+    unique
+}
+
+/// Returns names and parameter counts of the top-level positional `AppleScript` handlers,
+/// i.e. `on name(x, y)`. Handlers with labeled parameters and helpers generated by `osakit`
+/// are skipped.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn apple_script_positional_handlers(source: &str) -> Vec<(String, usize)> {
+    let mut handlers: Vec<(String, usize)> = Vec::new();
+    for (name, parameters) in apple_script_handlers(source) {
+        if let Some(parameters) = parameters {
+            if !name.starts_with("__osakit") && !handlers.iter().any(|(n, _)| *n == name) {
+                handlers.push((name, parameters));
+            }
+        }
+    }
+    handlers
+}
+
+fn apple_script_handler_names(source: &str) -> Vec<String> {
+    apple_script_handlers(source)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Returns names of the top-level handlers together with the number of positional parameters,
+/// `None` for handlers without a parenthesized parameter list.
+fn apple_script_handlers(source: &str) -> Vec<(String, Option<usize>)> {
+    let mut handlers = Vec::new();
+    let mut comment_depth = 0usize;
+    let mut script_depth = 0usize;
+    for line in source.lines() {
+        let line = strip_comments(line, &mut comment_depth);
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("script"), Some(_)) => script_depth += 1,
+            (Some("end"), Some("script")) => script_depth = script_depth.saturating_sub(1),
+            (Some("on" | "to"), Some(_)) if script_depth == 0 => {
+                let rest = line.trim_start()[2..].trim_start();
+                if let Some(name) = parse_identifier(rest) {
+                    if name != "error" {
+                        let name_length = match rest.starts_with('|') {
+                            true => name.len() + 2,
+                            false => name.len(),
+                        };
+                        let parameters = count_parameters(&rest[name_length..]);
+                        handlers.push((name, parameters));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    handlers
+}
+
+/// Counts parameters of a parenthesized parameter list, `None` if there is no such list.
+/// Commas of list and record patterns, i.e. `on f({x, y})`, are not counted.
+fn count_parameters(text: &str) -> Option<usize> {
+    let list = text.trim_start().strip_prefix('(')?;
+    let mut count = 0;
+    let mut depth = 0usize;
+    for c in list.chars() {
+        match c {
+            ')' if depth == 0 => return Some(count),
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => count += 1,
+            _ if count == 0 && !c.is_whitespace() => count = 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn java_script_handler_names(source: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut depth = 0usize;
+    let mut block_comment = false;
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        if depth == 0 && !block_comment {
+            if let Some(rest) = trimmed.strip_prefix("function") {
+                if rest.starts_with(char::is_whitespace) {
+                    names.extend(parse_identifier(rest.trim_start()));
+                }
+            }
+        }
+        let mut string_quote: Option<char> = None;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if block_comment {
+                if c == '*' && chars.peek() == Some(&'/') {
+                    chars.next();
+                    block_comment = false;
+                }
+                continue;
+            }
+            if let Some(quote) = string_quote {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    _ if c == quote => string_quote = None,
+                    _ => {}
+                }
+                continue;
+            }
+            match (c, chars.peek()) {
+                ('/', Some('/')) => break,
+                ('/', Some('*')) => {
+                    chars.next();
+                    block_comment = true;
+                }
+                ('"' | '\'' | '`', _) => string_quote = Some(c),
+                ('{', _) => depth += 1,
+                ('}', _) => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    names
+}
+
+fn parse_identifier(text: &str) -> Option<String> {
+    if let Some(rest) = text.strip_prefix('|') {
+        return rest.find('|').map(|end| rest[..end].to_string());
+    }
+    let end = text
+        .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '$')
+        .unwrap_or(text.len());
+    (end > 0).then(|| text[..end].to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_finds_apple_script_handlers() {
+        assert_eq!(
+            handler_names(
+                Language::AppleScript,
+                "on concat(x, y)
+                    try
+                        return x & y
+                    on error message
+                        return message
+                    end try
+                end concat
+                to greet given name:n
+                end greet
+                on |with space|()
+                end |with space|
+                -- on commented()
+                script Nested
+                    on nested()
+                    end nested
+                end script
+                on run
+                end run
+                on __osakit_take_logs()
+                end __osakit_take_logs"
+            ),
+            vec!["concat", "greet", "with space", "run"]
+        );
+    }
+
+    #[test]
+    fn it_finds_positional_apple_script_handlers() {
+        assert_eq!(
+            apple_script_positional_handlers(
+                "on concat(x, y)
+                end concat
+                on answer( )
+                end answer
+                on first_item({x, y}, z)
+                end first_item
+                to greet given name:n
+                end greet
+                on run
+                end run
+                on |with space|(x)
+                end |with space|"
+            ),
+            vec![
+                (String::from("concat"), 2),
+                (String::from("answer"), 0),
+                (String::from("first_item"), 2),
+                (String::from("with space"), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_finds_java_script_functions() {
+        assert_eq!(
+            handler_names(
+                Language::JavaScript,
+                "function concat(x, y) {
+                    function nested() {}
+                    return x + '}';
+                }
+                // function commented() {}
+                /* function alsoCommented() {} */
+                function $dollar() { return 1; }
+                var f = function () {};
+                function __osakit_call(name, args) {}"
+            ),
+            vec!["concat", "$dollar"]
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use crate::value::{Map, Value};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Name of the `JavaScript` function used to call handlers and capture thrown values.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) const JS_EXCEPTION_CALL_HANDLER: &str = "__osakit_call";
+
+/// Source of the helper appended to `JavaScript` scripts when exception capturing is enabled.
+/// Appended to the end of the script, so error locations in the original source stay intact.
+/// The global object is looked up using `Function('return this')()`, which works in strict mode
+/// scripts and does not rely on top-level code of the helper being executed before the call.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) const JS_EXCEPTION_HELPER: &str = "
+;function __osakit_call(name, args) {
+    var global = Function('return this')();
+    try {
+        return {ok: true, value: global[name].apply(global, args)};
+    } catch (e) {
+        var isError = e instanceof Error;
+        var props = {};
+        if (isError) {
+            Object.keys(e).forEach(function (key) { props[key] = e[key]; });
+        }
+        return {
+            ok: false,
+            name: isError ? String(e.name) : null,
+            message: isError ? String(e.message) : String(e),
+            stack: isError && e.stack ? String(e.stack) : null,
+            line: isError && typeof e.line === 'number' ? e.line : null,
+            value: isError ? props : e
+        };
+    }
+}
+";
+
+/// Structured data of a value thrown from `JavaScript` code.
+///
+/// Returned as [`crate::ScriptExecutionError::JsException`] when exception capturing is enabled
+/// using [`crate::Script::with_js_exceptions`].
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct JsException {
+    /// Error name, i.e. `TypeError`. `None` if a non-`Error` value was thrown.
+    pub name: Option<String>,
+    /// Error message or string representation of the thrown value.
+    pub message: String,
+    /// Stack trace, if available.
+    pub stack: Option<String>,
+    /// Line number, if available.
+    pub line: Option<u32>,
+    /// Thrown value in case of non-`Error` values, or custom properties of an `Error` object.
+    pub value: Option<Value>,
+}
+
+impl JsException {
+    /// Parses exception from an `OSAKit` error message.
+    ///
+    /// `OSAKit` reports `JavaScript` errors as `"Error: TypeError: message"` during execution
+    /// and as `"Error on line 1: SyntaxError: message"` during compilation.
+    ///
+    /// ```
+    /// use osakit::JsException;
+    ///
+    /// let exception = JsException::from_error_message("Error: TypeError: x is not a function").unwrap();
+    /// assert_eq!(exception.name.as_deref(), Some("TypeError"));
+    /// assert_eq!(exception.message, "x is not a function");
+    /// ```
+    pub fn from_error_message(error_message: &str) -> Option<Self> {
+        let (line, rest) = if let Some(rest) = error_message.strip_prefix("Error on line ") {
+            let (line, rest) = rest.split_once(": ")?;
+            (Some(line.trim().parse::<u32>().ok()?), rest)
+        } else {
+            (None, error_message.strip_prefix("Error: ")?)
+        };
+        let (name, message) = match rest.split_once(": ") {
+            Some((name, message)) if is_error_name(name) => (Some(name.to_string()), message),
+            _ => (None, rest),
+        };
+        Some(JsException {
+            name,
+            message: message.to_string(),
+            stack: None,
+            line,
+            value: None,
+        })
+    }
+
+    /// Constructs exception from the result of [`JS_EXCEPTION_HELPER`] call.
+    /// Returns `Ok(value)` if the called handler did not throw.
+    pub(crate) fn from_helper_result(result: Value) -> Result<Value, Self> {
+        let mut result = match result {
+            Value::Object(result) => result,
+            other => return Ok(other),
+        };
+        if result.get("ok") == Some(&Value::Bool(true)) {
+            return Ok(result.remove("value").unwrap_or(Value::Null));
+        }
+        Err(JsException {
+            name: take_string(&mut result, "name"),
+            message: take_string(&mut result, "message").unwrap_or_default(),
+            stack: take_string(&mut result, "stack"),
+            line: result
+                .get("line")
+                .and_then(|line| line.as_u64())
+                .and_then(|line| u32::try_from(line).ok()),
+            value: match result.remove("value") {
+                None | Some(Value::Null) => None,
+                Some(Value::Object(props)) if props.is_empty() => None,
+                Some(value) => Some(value),
+            },
+        })
+    }
+
+    /// Error number reported by an application, stored in the `errorNumber` property.
+    pub fn number(&self) -> Option<i32> {
+        self.value
+            .as_ref()
+            .and_then(|value| value.get("errorNumber"))
+            .and_then(|number| number.as_i64())
+            .and_then(|number| i32::try_from(number).ok())
+    }
+}
+
+impl Display for JsException {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}: {}", name, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+fn is_error_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+fn take_string(map: &mut Map<String, Value>, key: &str) -> Option<String> {
+    match map.remove(key) {
+        Some(Value::String(s)) => Some(s),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::value::Number;
+
+    #[test]
+    fn it_parses_runtime_error_message() {
+        assert_eq!(
+            JsException::from_error_message("Error: ReferenceError: Can't find variable: y"),
+            Some(JsException {
+                name: Some("ReferenceError".into()),
+                message: "Can't find variable: y".into(),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_thrown_error_message() {
+        assert_eq!(
+            JsException::from_error_message("Error: Error: Test Error"),
+            Some(JsException {
+                name: Some("Error".into()),
+                message: "Test Error".into(),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_compilation_error_message() {
+        assert_eq!(
+            JsException::from_error_message(
+                "Error on line 12: SyntaxError: Unexpected identifier 'world'"
+            ),
+            Some(JsException {
+                name: Some("SyntaxError".into()),
+                message: "Unexpected identifier 'world'".into(),
+                line: Some(12),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_message_without_error_name() {
+        assert_eq!(
+            JsException::from_error_message("Error: something went wrong: badly"),
+            Some(JsException {
+                message: "something went wrong: badly".into(),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn it_does_not_parse_unknown_messages() {
+        assert_eq!(JsException::from_error_message("Can’t get window 1."), None);
+        assert_eq!(JsException::from_error_message("Error on line x: y"), None);
+    }
+
+    #[test]
+    fn it_returns_value_from_successful_helper_result() {
+        assert_eq!(
+            JsException::from_helper_result(serde_json::json!({"ok": true, "value": [1, 2]})),
+            Ok(serde_json::json!([1, 2]))
+        );
+        assert_eq!(
+            JsException::from_helper_result(serde_json::json!({"ok": true})),
+            Ok(Value::Null)
+        );
+    }
+
+    #[test]
+    fn it_returns_exception_from_failed_helper_result() {
+        assert_eq!(
+            JsException::from_helper_result(serde_json::json!({
+                "ok": false,
+                "name": "Error",
+                "message": "Test Error",
+                "stack": "throws_an_error@",
+                "line": 3,
+                "value": {"errorNumber": -1728}
+            })),
+            Err(JsException {
+                name: Some("Error".into()),
+                message: "Test Error".into(),
+                stack: Some("throws_an_error@".into()),
+                line: Some(3),
+                value: Some(serde_json::json!({"errorNumber": -1728})),
+            })
+        );
+    }
+
+    #[test]
+    fn it_returns_thrown_non_error_values() {
+        let exception = JsException::from_helper_result(serde_json::json!({
+            "ok": false,
+            "name": null,
+            "message": "[object Object]",
+            "value": {"code": 5}
+        }))
+        .unwrap_err();
+        assert_eq!(exception.name, None);
+        assert_eq!(
+            exception.value.and_then(|v| v.get("code").cloned()),
+            Some(Value::Number(Number::from(5)))
+        );
+    }
+
+    #[test]
+    fn it_returns_error_number() {
+        let exception = JsException {
+            value: Some(serde_json::json!({"errorNumber": -1743})),
+            ..Default::default()
+        };
+        assert_eq!(exception.number(), Some(-1743));
+        assert_eq!(JsException::default().number(), None);
+    }
+
+    #[test]
+    fn it_supports_display() {
+        assert_eq!(
+            JsException {
+                name: Some("TypeError".into()),
+                message: "x is not a function".into(),
+                ..Default::default()
+            }
+            .to_string(),
+            "TypeError: x is not a function"
+        );
+    }
+}
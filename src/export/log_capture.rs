@@ -0,0 +1,139 @@
+use crate::script::Language;
+use crate::value::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the handler returning and clearing collected log lines.
+pub(crate) const TAKE_LOGS_HANDLER: &str = "__osakit_take_logs";
+
+/// Prepended to `JavaScript` source to intercept `console.log` before the script code runs.
+/// Kept on a single line, so line numbers in error messages stay intact.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) const JS_LOG_PRELUDE: &str = "var __osakit_logs = [];\
+(function () { console.log = function () { \
+__osakit_logs.push([Date.now() / 1000, Array.prototype.map.call(arguments, function (a) { \
+return typeof a === 'string' ? a : JSON.stringify(a); }).join(' ')]); }; })();";
+
+/// Appended to `JavaScript` source to retrieve collected log lines.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) const JS_LOG_EPILOGUE: &str = "
+;function __osakit_take_logs() {
+    if (typeof __osakit_logs === 'undefined') {
+        return [];
+    }
+    var logs = __osakit_logs;
+    __osakit_logs = [];
+    return logs;
+}
+";
+
+/// Appended to `AppleScript` source to intercept `log` commands and retrieve collected log lines.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) const APPLE_SCRIPT_LOG_EPILOGUE: &str = "
+property __osakit_logs : {}
+
+on log __osakit_message
+    try
+        set __osakit_message to __osakit_message as text
+    end try
+    set end of __osakit_logs to {current date, __osakit_message}
+end log
+
+on __osakit_take_logs()
+    set __osakit_result to __osakit_logs
+    set __osakit_logs to {}
+    return __osakit_result
+end __osakit_take_logs
+";
+
+/// Single line logged by a script using `log` in `AppleScript` or `console.log` in `JavaScript`.
+/// Collected when log capturing is enabled using [`crate::Script::with_log_capture`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    /// Time when the line was logged. `AppleScript` timestamps have a precision of one second.
+    pub timestamp: SystemTime,
+    /// Language of the script which logged the line.
+    pub language: Language,
+    /// Logged message. Non-string values are represented using JSON.
+    pub message: String,
+}
+
+impl LogRecord {
+    /// Forwards the record to the [`log`](https://crates.io/crates/log) crate using `osakit::script`
+    /// target. Can be used as a callback for [`crate::Script::with_log_callback`].
+    #[cfg(feature = "log")]
+    pub fn forward_to_log(&self) {
+        log::info!(target: "osakit::script", "[{}] {}", self.language.name(), self.message);
+    }
+}
+
+/// Converts the result of [`TAKE_LOGS_HANDLER`] call to log records.
+pub(crate) fn parse_log_records(language: Language, logs: Value) -> Vec<LogRecord> {
+    let records = match logs {
+        Value::Array(records) => records,
+        _ => return Vec::new(),
+    };
+    records
+        .into_iter()
+        .filter_map(|record| match record {
+            Value::Array(mut fields) if fields.len() == 2 => {
+                let message = match fields.pop() {
+                    Some(Value::String(message)) => message,
+                    Some(value) => value.to_string(),
+                    None => return None,
+                };
+                let timestamp = fields
+                    .pop()
+                    .and_then(|timestamp| timestamp.as_f64())
+                    .filter(|timestamp| timestamp.is_finite() && *timestamp >= 0.0)
+                    .map(|timestamp| UNIX_EPOCH + Duration::from_secs_f64(timestamp))
+                    .unwrap_or_else(SystemTime::now);
+                Some(LogRecord {
+                    timestamp,
+                    language,
+                    message,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_parses_log_records() {
+        assert_eq!(
+            parse_log_records(
+                Language::JavaScript,
+                json!([[1700000000.5, "Hello"], [1700000001, {"x": 1}]])
+            ),
+            vec![
+                LogRecord {
+                    timestamp: UNIX_EPOCH + Duration::from_millis(1700000000500),
+                    language: Language::JavaScript,
+                    message: String::from("Hello"),
+                },
+                LogRecord {
+                    timestamp: UNIX_EPOCH + Duration::from_secs(1700000001),
+                    language: Language::JavaScript,
+                    message: String::from("{\"x\":1}"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_skips_malformed_log_records() {
+        assert_eq!(
+            parse_log_records(Language::AppleScript, json!([[1], "x", [1, "a", 2]])),
+            vec![]
+        );
+        assert_eq!(
+            parse_log_records(Language::AppleScript, Value::Null),
+            vec![]
+        );
+    }
+}
@@ -1,9 +1,22 @@
-use super::script::{Script, ScriptExecutionError};
-use super::value::Value;
+use super::js_exception::JsException;
+use super::main_thread::run_on_main;
+use super::retry::RetryPolicy;
+use super::script::{Language, Script, ScriptCompilationError, ScriptExecutionError};
+use super::value::{to_value, Map, Value};
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::from_value;
+#[cfg(feature = "mock")]
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::sync::{Mutex, OnceLock};
 use thiserror::Error;
 
+#[doc(hidden)]
+pub use ctor::ctor as __ctor;
+
 /// Error returned when calling a method of a script constructed by [`crate::declare_script!`]
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum ScriptFunctionRunError {
@@ -13,6 +26,13 @@ pub enum ScriptFunctionRunError {
     ArgumentSerialization { arg_name: String, message: String },
     #[error("could not deserialize function execution result: {message}")]
     ResultDeserialization { message: String },
+    /// Happens when an argument of a function declared with `#[guard]` has unexpected type.
+    #[error("argument `{arg_name}` has type `{actual}`, expected `{expected}`")]
+    ArgumentType {
+        arg_name: String,
+        expected: String,
+        actual: String,
+    },
 }
 
 #[doc(hidden)]
@@ -31,8 +51,13 @@ pub fn __exec_and_deserialize<T: DeserializeOwned, I: IntoIterator<Item = Value>
     script: &Script,
     fn_name: &str,
     arguments: I,
+    retry: Option<RetryPolicy>,
 ) -> Result<T, ScriptFunctionRunError> {
-    match script.execute_function(fn_name, arguments) {
+    let result = match retry {
+        Some(policy) => script.execute_function_with_retry(fn_name, arguments, &policy),
+        None => script.execute_function(fn_name, arguments),
+    };
+    match result {
         Ok(output) => {
             let deserialized_value: Result<T, serde_json::Error> = from_value(output);
             match deserialized_value {
@@ -46,11 +71,749 @@ pub fn __exec_and_deserialize<T: DeserializeOwned, I: IntoIterator<Item = Value>
     }
 }
 
+impl From<ScriptExecutionError> for ScriptFunctionRunError {
+    fn from(error: ScriptExecutionError) -> Self {
+        ScriptFunctionRunError::Execution(error)
+    }
+}
+
+/// Pattern of `#[on_error(...)]` attribute of functions declared using [`crate::declare_script!`]:
+/// error number or `JavaScript` error name.
+#[doc(hidden)]
+pub trait ErrorPattern {
+    fn matches(&self, error: &ScriptFunctionRunError) -> bool;
+}
+
+impl ErrorPattern for i32 {
+    fn matches(&self, error: &ScriptFunctionRunError) -> bool {
+        match error {
+            ScriptFunctionRunError::Execution(error) => {
+                error.code().map(|code| code.number()) == Some(*self)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl ErrorPattern for &str {
+    fn matches(&self, error: &ScriptFunctionRunError) -> bool {
+        let name = match error {
+            ScriptFunctionRunError::Execution(ScriptExecutionError::JsException(exception)) => {
+                exception.name.clone()
+            }
+            ScriptFunctionRunError::Execution(ScriptExecutionError::Runtime {
+                message, ..
+            }) => JsException::from_error_message(message).and_then(|exception| exception.name),
+            _ => None,
+        };
+        name.as_deref() == Some(*self)
+    }
+}
+
+/// Arguments of a declared script function. Trailing `None` values of optional arguments are
+/// omitted in `JavaScript`, so that the script can use its own defaults. `AppleScript` handlers
+/// cannot omit positional parameters, so `missing value` is passed instead.
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct ScriptArguments {
+    values: Vec<Value>,
+    required: usize,
+}
+
+impl ScriptArguments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_required<T: Serialize>(
+        &mut self,
+        arg_name: &str,
+        value: T,
+    ) -> Result<(), ScriptFunctionRunError> {
+        self.values
+            .push(to_value(value).or_else(|e| __arg_s_error(arg_name, e))?);
+        self.required = self.values.len();
+        Ok(())
+    }
+
+    /// Passes `missing value` (`null` in `JavaScript`). Trailing `None` values are omitted in
+    /// `JavaScript` by [`ScriptArguments::finish`].
+    pub fn push_optional<T: Serialize>(
+        &mut self,
+        arg_name: &str,
+        value: Option<T>,
+    ) -> Result<(), ScriptFunctionRunError> {
+        match value {
+            Some(value) => self.push_required(arg_name, value),
+            None => {
+                self.values.push(Value::Null);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn push_default<T: Serialize, F: FnOnce() -> T>(
+        &mut self,
+        arg_name: &str,
+        value: Option<T>,
+        default: F,
+    ) -> Result<(), ScriptFunctionRunError> {
+        self.push_required(arg_name, value.unwrap_or_else(default))
+    }
+
+    /// Passes every value as a separate argument.
+    pub fn push_variadic<T: Serialize>(
+        &mut self,
+        arg_name: &str,
+        values: &[T],
+    ) -> Result<(), ScriptFunctionRunError> {
+        values
+            .iter()
+            .try_for_each(|value| self.push_required(arg_name, value))
+    }
+
+    pub fn finish(mut self, language: Language) -> Vec<Value> {
+        if language == Language::JavaScript {
+            self.values.truncate(self.required);
+        }
+        self.values
+    }
+}
+
+/// Prefix of errors thrown by the argument type checks of guarded functions.
+const ARGUMENT_TYPE_ERROR: &str = "osakit-argument-type:";
+
+/// Type of values accepted by an argument of a guarded function.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    String,
+    Number,
+    Boolean,
+    List,
+    Record,
+}
+
+impl ValueType {
+    fn name(&self, language: Language) -> &'static str {
+        match (self, language) {
+            (ValueType::String, Language::JavaScript) => "string",
+            (ValueType::String, Language::AppleScript) => "text",
+            (ValueType::Number, _) => "number",
+            (ValueType::Boolean, _) => "boolean",
+            (ValueType::List, Language::JavaScript) => "array",
+            (ValueType::List, Language::AppleScript) => "list",
+            (ValueType::Record, Language::JavaScript) => "object",
+            (ValueType::Record, Language::AppleScript) => "record",
+        }
+    }
+
+    /// Condition checking type of the variable.
+    fn condition(&self, language: Language, variable: &str) -> String {
+        match (self, language) {
+            (ValueType::List, Language::JavaScript) => format!("Array.isArray({})", variable),
+            (ValueType::Record, Language::JavaScript) => format!(
+                "typeof {0} === 'object' && {0} !== null && !Array.isArray({0})",
+                variable
+            ),
+            (_, Language::JavaScript) => {
+                format!("typeof {} === '{}'", variable, self.name(language))
+            }
+            (ValueType::Number, Language::AppleScript) => {
+                format!("class of {0} is integer or class of {0} is real", variable)
+            }
+            (_, Language::AppleScript) => {
+                format!("class of {} is {}", variable, self.name(language))
+            }
+        }
+    }
+}
+
+/// Resolves [`ValueType`] of Rust types using autoref specialization: [`KnownValueType`] is
+/// implemented for `TypeOf<T>` of supported types, [`AnyValueType`] for references to all of them.
+#[doc(hidden)]
+pub struct TypeOf<T: ?Sized>(PhantomData<T>);
+
+impl<T: ?Sized> TypeOf<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait KnownValueType {
+    fn value_type(&self) -> Option<ValueType>;
+}
+
+#[doc(hidden)]
+pub trait AnyValueType {
+    fn value_type(&self) -> Option<ValueType> {
+        None
+    }
+}
+
+impl<T: ?Sized> AnyValueType for &TypeOf<T> {}
+
+macro_rules! known_value_type {
+    ($value_type:ident: $($ty:ty),*) => {
+        $(
+            impl KnownValueType for TypeOf<$ty> {
+                fn value_type(&self) -> Option<ValueType> {
+                    Some(ValueType::$value_type)
+                }
+            }
+        )*
+    };
+}
+
+known_value_type!(String: str, String, char);
+known_value_type!(Number: i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+known_value_type!(Boolean: bool);
+known_value_type!(Record: Map<String, Value>);
+
+impl<T> KnownValueType for TypeOf<[T]> {
+    fn value_type(&self) -> Option<ValueType> {
+        Some(ValueType::List)
+    }
+}
+
+impl<T> KnownValueType for TypeOf<Vec<T>> {
+    fn value_type(&self) -> Option<ValueType> {
+        Some(ValueType::List)
+    }
+}
+
+impl<K, V> KnownValueType for TypeOf<HashMap<K, V>> {
+    fn value_type(&self) -> Option<ValueType> {
+        Some(ValueType::Record)
+    }
+}
+
+impl<K, V> KnownValueType for TypeOf<BTreeMap<K, V>> {
+    fn value_type(&self) -> Option<ValueType> {
+        Some(ValueType::Record)
+    }
+}
+
+impl<T: ?Sized> KnownValueType for TypeOf<&T>
+where
+    TypeOf<T>: KnownValueType,
+{
+    fn value_type(&self) -> Option<ValueType> {
+        TypeOf::<T>::new().value_type()
+    }
+}
+
+/// Parameter of an inline function of a declared script.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptParam {
+    name: &'static str,
+    value_type: Option<ValueType>,
+    optional: bool,
+    variadic: bool,
+}
+
+impl ScriptParam {
+    pub fn new(name: &'static str, value_type: Option<ValueType>) -> Self {
+        Self {
+            name,
+            value_type,
+            optional: false,
+            variadic: false,
+        }
+    }
+
+    /// Accepts `null` and `undefined` (`missing value` in `AppleScript`).
+    pub fn optional(self) -> Self {
+        Self {
+            optional: true,
+            ..self
+        }
+    }
+
+    /// Takes the rest of the arguments (`...name` in `JavaScript`).
+    pub fn variadic(self) -> Self {
+        Self {
+            variadic: true,
+            ..self
+        }
+    }
+
+    fn declaration(&self, language: Language) -> String {
+        match self.variadic {
+            true => format!("...{}", self.name),
+            false => identifier(language, self.name),
+        }
+    }
+
+    /// Statement throwing an error unless the argument has the expected type.
+    fn guard(&self, language: Language) -> Option<String> {
+        let value_type = self.value_type?;
+        let expected = value_type.name(language);
+        Some(match language {
+            Language::JavaScript => {
+                let variable = if self.variadic { "value" } else { self.name };
+                let mut condition = value_type.condition(language, variable);
+                if self.optional {
+                    condition = format!(
+                        "{0} === undefined || {0} === null || {1}",
+                        variable, condition
+                    );
+                }
+                let check = format!(
+                    "if (!({condition})) throw new TypeError('{prefix}{name}:{expected}:' + \
+                     ({variable} === null ? 'null' : Array.isArray({variable}) ? 'array' : typeof {variable}));",
+                    condition = condition,
+                    prefix = ARGUMENT_TYPE_ERROR,
+                    name = self.name,
+                    expected = expected,
+                    variable = variable,
+                );
+                match self.variadic {
+                    true => format!("{}.forEach(function (value) {{ {} }});", self.name, check),
+                    false => check,
+                }
+            }
+            Language::AppleScript => {
+                let variable = identifier(language, self.name);
+                let mut condition = format!("not ({})", value_type.condition(language, &variable));
+                if self.optional {
+                    condition = format!("{} is not missing value and {}", variable, condition);
+                }
+                format!(
+                    "if {condition} then error \"{prefix}{name}:{expected}:\" & (class of {variable} as text) number -1703",
+                    condition = condition,
+                    prefix = ARGUMENT_TYPE_ERROR,
+                    name = self.name,
+                    expected = expected,
+                    variable = variable,
+                )
+            }
+        })
+    }
+}
+
+/// Identifier in the script, `AppleScript` identifiers are enclosed in vertical bars so that
+/// names of Rust parameters and functions don't clash with reserved words such as `text`.
+fn identifier(language: Language, name: &str) -> String {
+    match language {
+        Language::JavaScript => String::from(name),
+        Language::AppleScript => format!("|{}|", name),
+    }
+}
+
+/// Source of a script declared by [`crate::declare_script!`], assembled from `#[source]` and
+/// inline function bodies.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct ScriptSource {
+    language: Language,
+    source: String,
+    guard: bool,
+    bodies: FunctionBodies,
+}
+
+impl ScriptSource {
+    /// Constructs the source, `guard` enables argument type checks of inline functions.
+    pub fn new(language: Language, source: &str, guard: bool) -> Self {
+        Self {
+            language,
+            source: String::from(source),
+            guard,
+            bodies: FunctionBodies::default(),
+        }
+    }
+
+    /// Appends a function (`JavaScript`) or a handler (`AppleScript`) wrapping the body.
+    pub fn push_function(&mut self, name: &'static str, params: &[ScriptParam], body: &str) {
+        let declarations: Vec<String> = params
+            .iter()
+            .map(|param| param.declaration(self.language))
+            .collect();
+        let declarations = declarations.join(", ");
+        let handler = identifier(self.language, name);
+        let (header, footer) = match self.language {
+            Language::JavaScript => (
+                format!("function {}({}) {{\n", handler, declarations),
+                String::from("\n}\n"),
+            ),
+            Language::AppleScript => (
+                format!("on {}({})\n", handler, declarations),
+                format!("\nend {}\n", handler),
+            ),
+        };
+        if !self.source.is_empty() && !self.source.ends_with('\n') {
+            self.source.push('\n');
+        }
+        let start = self.source.encode_utf16().count();
+        self.source.push_str(&header);
+        if self.guard {
+            for guard in params.iter().filter_map(|param| param.guard(self.language)) {
+                self.source.push_str(&guard);
+                self.source.push('\n');
+            }
+        }
+        // Error locations are reported in UTF-16 code units.
+        self.bodies.0.push(FunctionBody {
+            name,
+            start,
+            location: self.source.encode_utf16().count(),
+            length: body.encode_utf16().count(),
+        });
+        self.source.push_str(body);
+        self.source.push_str(&footer);
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Compiles the script, errors inside inline function bodies are reported as
+    /// [`ScriptCompilationError::Function`].
+    pub fn compile(self) -> Result<(Script, FunctionBodies), ScriptCompilationError> {
+        let mut script = Script::new_from_source(self.language, &self.source);
+        match script.compile() {
+            Ok(()) => Ok((script, self.bodies)),
+            Err(error) => Err(self.bodies.map_compilation_error(error)),
+        }
+    }
+}
+
+/// Locations of inline function bodies in the source of a declared script.
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct FunctionBodies(Vec<FunctionBody>);
+
+#[derive(Debug)]
+struct FunctionBody {
+    name: &'static str,
+    /// Start of the generated header and argument type checks preceding the body.
+    start: usize,
+    location: usize,
+    length: usize,
+}
+
+impl FunctionBody {
+    fn contains(&self, location: usize) -> bool {
+        (self.location..=self.location + self.length).contains(&location)
+    }
+
+    /// Whether the location is inside the function, including its generated header.
+    fn wraps(&self, location: usize) -> bool {
+        (self.start..=self.location + self.length).contains(&location)
+    }
+}
+
+impl FunctionBodies {
+    fn map_compilation_error(&self, mut error: ScriptCompilationError) -> ScriptCompilationError {
+        if let ScriptCompilationError::Failure { location, .. } = &mut error {
+            // Errors in the generated header are reported at the start of the body.
+            if let Some(body) = self.0.iter().find(|body| body.wraps(*location)) {
+                *location = location.saturating_sub(body.location);
+                return ScriptCompilationError::Function {
+                    name: String::from(body.name),
+                    error: Box::new(error),
+                };
+            }
+        }
+        error
+    }
+
+    /// Makes locations of runtime errors inside the inline body of the function relative to it,
+    /// converts errors thrown by argument type checks to [`ScriptFunctionRunError::ArgumentType`].
+    pub fn map_run_error(
+        &self,
+        name: &str,
+        mut error: ScriptFunctionRunError,
+    ) -> ScriptFunctionRunError {
+        if let Some(error) = argument_type_error(&error) {
+            return error;
+        }
+        if let ScriptFunctionRunError::Execution(ScriptExecutionError::Runtime {
+            location, ..
+        }) = &mut error
+        {
+            if let Some(body) = self
+                .0
+                .iter()
+                .find(|body| body.name == name && body.contains(*location))
+            {
+                *location -= body.location;
+            }
+        }
+        error
+    }
+}
+
+fn argument_type_error(error: &ScriptFunctionRunError) -> Option<ScriptFunctionRunError> {
+    let message = match error {
+        ScriptFunctionRunError::Execution(ScriptExecutionError::Runtime { message, .. }) => message,
+        ScriptFunctionRunError::Execution(ScriptExecutionError::JsException(exception)) => {
+            &exception.message
+        }
+        _ => return None,
+    };
+    let (_, details) = message.split_once(ARGUMENT_TYPE_ERROR)?;
+    let mut details = details.splitn(3, ':');
+    let arg_name = details.next()?;
+    let expected = details.next()?;
+    let actual = details
+        .next()?
+        .split_whitespace()
+        .next()
+        .unwrap_or_default();
+    Some(ScriptFunctionRunError::ArgumentType {
+        arg_name: String::from(arg_name),
+        expected: String::from(expected),
+        actual: String::from(actual),
+    })
+}
+
+/// Script declared by [`crate::declare_script!`] shared by all threads, returned by the generated
+/// `shared()` and `instance()` functions.
+///
+/// The script is compiled on first use. Scripts can only be used from the main thread, so
+/// compilation and calls made from other threads are dispatched to the main dispatch queue
+/// and wait for the main thread to execute them. The main thread has to run its run loop,
+/// i.e. using `NSApplication` or `CFRunLoopRun`, otherwise these calls fail with
+/// [`ScriptExecutionError::MainThread`] instead of waiting.
+pub struct SharedScript<T: 'static> {
+    name: &'static str,
+    init: fn() -> Result<T, ScriptCompilationError>,
+    instance: OnceLock<Result<T, ScriptCompilationError>>,
+}
+
+// SAFETY: `SharedScript::new` is only called by `declare_script!` for statics, the script is
+// only constructed and accessed on the main thread and, being a static, is never dropped.
+#[cfg(target_os = "macos")]
+unsafe impl<T> Send for SharedScript<T> {}
+#[cfg(target_os = "macos")]
+unsafe impl<T> Sync for SharedScript<T> {}
+
+impl<T> SharedScript<T> {
+    /// ## Safety
+    ///
+    /// Has to be stored in a `static` and `T` has to be a script declared by
+    /// [`crate::declare_script!`], as the script is shared by threads regardless of `Send` and
+    /// `Sync`, relying on all accesses being made on the main thread.
+    #[doc(hidden)]
+    pub const unsafe fn new(
+        name: &'static str,
+        init: fn() -> Result<T, ScriptCompilationError>,
+    ) -> Self {
+        Self {
+            name,
+            init,
+            instance: OnceLock::new(),
+        }
+    }
+
+    /// Compiles the script unless it was compiled before, returns the compilation error if any.
+    #[doc(hidden)]
+    pub fn instance(&'static self) -> Result<&'static Self, ScriptCompilationError>
+    where
+        Self: Sync,
+    {
+        let error = match self.instance.get() {
+            Some(result) => result.as_ref().err().cloned(),
+            None => run_on_main(|| self.instance.get_or_init(self.init).as_ref().err().cloned())
+                .unwrap_or(Some(ScriptCompilationError::MainThread)),
+        };
+        match error {
+            Some(error) => Err(error),
+            None => Ok(self),
+        }
+    }
+
+    /// Calls the function with the script on the main thread, returns
+    /// [`ScriptExecutionError::MainThread`] if it is called from another thread while the main
+    /// thread doesn't run its run loop. The script is compiled first unless it was compiled
+    /// before.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the script fails to compile, the same way as `shared()`.
+    ///
+    /// ```no_run
+    /// use osakit::declare_script;
+    ///
+    /// declare_script! {
+    ///     #[language(JavaScript)]
+    ///     pub MyJsScript {
+    ///         pub fn concat(x: &str, y: &str) -> String {
+    ///             js!("return x + y;")
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// std::thread::spawn(|| {
+    ///     let result = MyJsScript::shared().with(|script| script.concat("Hello, ", "World"));
+    /// });
+    /// ```
+    pub fn with<R, E, F>(&'static self, function: F) -> Result<R, E>
+    where
+        R: Send,
+        E: From<ScriptExecutionError> + Send,
+        F: FnOnce(&T) -> Result<R, E> + Send,
+        Self: Sync,
+    {
+        run_on_main(|| match self.instance.get_or_init(self.init) {
+            Ok(script) => function(script),
+            Err(error) => panic!("script `{}` failed to compile: {}", self.name, error),
+        })
+        .unwrap_or_else(|| Err(E::from(ScriptExecutionError::MainThread)))
+    }
+}
+
+type EagerScript = (&'static str, fn() -> Result<(), ScriptCompilationError>);
+
+static EAGER_SCRIPTS: Mutex<Vec<EagerScript>> = Mutex::new(Vec::new());
+
+#[doc(hidden)]
+pub fn __register_eager(name: &'static str, instance: fn() -> Result<(), ScriptCompilationError>) {
+    EAGER_SCRIPTS
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .push((name, instance));
+}
+
+/// Error returned by [`compile_eager_scripts`], lists all scripts failed to compile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EagerCompilationError {
+    pub errors: Vec<(String, ScriptCompilationError)>,
+}
+
+impl Display for EagerCompilationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} script(s) failed to compile", self.errors.len())?;
+        for (name, error) in &self.errors {
+            write!(f, "\n  `{}`: {}", name, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for EagerCompilationError {}
+
+/// Compiles all scripts declared with `#[eager]`, to be called at startup.
+///
+/// Scripts are compiled on the main thread and shared using [`SharedScript`]. Unlike compiling
+/// scripts on first use, reports all compilation errors together:
+///
+/// ```no_run
+/// use osakit::declare_script;
+///
+/// declare_script! {
+///     #[language(JavaScript)]
+///     #[eager]
+///     pub MyJsScript {
+///         pub fn concat(x: &str, y: &str) -> String {
+///             js!("return x + y;")
+///         }
+///     }
+/// }
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// osakit::macros::compile_eager_scripts()?;
+/// let result = MyJsScript::shared().with(|script| script.concat("Hello, ", "World"))?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn compile_eager_scripts() -> Result<(), EagerCompilationError> {
+    let scripts = EAGER_SCRIPTS
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .clone();
+    let errors: Vec<(String, ScriptCompilationError)> = scripts
+        .into_iter()
+        .filter_map(|(name, instance)| instance().err().map(|error| (String::from(name), error)))
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(EagerCompilationError { errors })
+    }
+}
+
+/// Expectation of a function of a mock generated by [`crate::declare_script!`].
+///
+/// Panics when called more times than expected or without a function set to compute results,
+/// and when dropped after fewer calls than expected.
+#[cfg(feature = "mock")]
+pub struct MockFunction<F: ?Sized> {
+    name: &'static str,
+    returning: RefCell<Option<Box<F>>>,
+    times: Option<usize>,
+    calls: Cell<usize>,
+}
+
+#[cfg(feature = "mock")]
+impl<F: ?Sized> MockFunction<F> {
+    /// Constructs expectation of the function, `name` is used in panic messages.
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            returning: RefCell::new(None),
+            times: None,
+            calls: Cell::new(0),
+        }
+    }
+
+    /// Sets the function computing results of the calls.
+    pub fn set_returning(&mut self, function: Box<F>) {
+        *self.returning.get_mut() = Some(function);
+    }
+
+    /// Sets the number of calls expected before the mock is dropped.
+    pub fn set_times(&mut self, times: usize) {
+        self.times = Some(times);
+    }
+
+    /// Number of calls made so far.
+    pub fn calls(&self) -> usize {
+        self.calls.get()
+    }
+
+    /// Registers the call and computes its result.
+    pub fn call<R>(&self, call: impl FnOnce(&mut F) -> R) -> R {
+        let calls = self.calls.get() + 1;
+        self.calls.set(calls);
+        if let Some(times) = self.times {
+            if calls > times {
+                panic!("{}: expected {} call(s), got {}", self.name, times, calls);
+            }
+        }
+        let mut returning = self.returning.borrow_mut();
+        match returning.as_mut() {
+            Some(function) => call(function),
+            None => panic!("{}: called without an expectation", self.name),
+        }
+    }
+}
+
+#[cfg(feature = "mock")]
+impl<F: ?Sized> Drop for MockFunction<F> {
+    fn drop(&mut self) {
+        if let Some(times) = self.times {
+            if self.calls.get() < times && !std::thread::panicking() {
+                panic!(
+                    "{}: expected {} call(s), got {}",
+                    self.name,
+                    times,
+                    self.calls.get()
+                );
+            }
+        }
+    }
+}
+
 /// Macro to help construct scripts in a form of API.
 ///
 /// ## Example:
 ///
-/// ```
+/// ```ignore-linux
 /// use serde::{Deserialize, Serialize};
 /// use osakit::declare_script;
 ///
@@ -108,106 +871,1613 @@ pub fn __exec_and_deserialize<T: DeserializeOwned, I: IntoIterator<Item = Value>
 /// # Ok(())
 /// # }
 /// ```
-#[cfg(feature = "declare-script")]
-#[macro_export]
-macro_rules! declare_script {
-    (
-        #[language($language:ident)]
-        #[source($source:literal)]
-        $(#[$struct_meta:meta])*
-        $vis:vis $struct_name:ident {
-            $(
-                $(#[$fn_meta:meta])*
-                $fn_vis:vis fn $fn_name:ident(
-                    $($fn_arg_name:ident : $fn_arg_type:ty),*
-                )$( -> $fn_res_type:ty)?;
-            )*
-        }
-    ) => {
-        $(#[$struct_meta])*
-        $vis struct $struct_name {
-            script: $crate::Script
-        }
-
-        impl $struct_name {
-            $vis fn new() -> ::core::result::Result<$struct_name, $crate::ScriptCompilationError> {
-                let mut script = $crate::Script::new_from_source(
-                    $crate::Language::$language,
-                    $source
-                );
-                script.compile()?;
-                Ok($struct_name { script })
-            }
-
-            $(
-                $crate::__script_fn!(
-                    $(#[$fn_meta])*
-                    $fn_vis fn $fn_name($($fn_arg_name : $fn_arg_type),*)$( -> $fn_res_type)?;
-                );
-            )*
-        }
-    };
-}
-
-#[cfg(feature = "declare-script")]
-#[macro_export]
-#[doc(hidden)]
-macro_rules! __script_fn {
-    (
-        $(#[$meta:meta])*
-        $vis:vis fn $name:ident($($arg_name:ident : $arg_type:ty),*) -> $res_type:ty;
-    ) => {
-        $crate::__script_fn_impl!(
-            meta = ($($meta)*)
-            vis = ($vis)
-            name = ($name)
-            args = ($($arg_name : $arg_type),*)
-            res = ($res_type)
-        );
-    };
-    (
-        $(#[$meta:meta])*
-        $vis:vis fn $name:ident($($arg_name:ident : $arg_type:ty),*);
+///
+/// `#[source]` accepts a string literal or a constant, i.e. `#[source(FINDER_SOURCE)]`.
+///
+/// ## Retrying transient failures
+///
+/// Functions marked with `#[retry(attempts = N)]` are retried using [`crate::RetryPolicy`]
+/// in case of transient failures, i.e. when the application is launching or busy:
+///
+/// ```
+/// use osakit::declare_script;
+///
+/// declare_script! {
+///     #[language(AppleScript)]
+///     #[source("
+///         on front_window_name()
+///             tell application \"Finder\" to return name of front window
+///         end front_window_name
+///     ")]
+///     pub FinderScript {
+///         #[retry(attempts = 3)]
+///         pub fn front_window_name() -> String;
+///     }
+/// }
+/// ```
+///
+/// ## Optional, default and variadic arguments
+///
+/// * `None` values of `Option<T>` arguments are passed as `missing value` in `AppleScript`.
+///   In `JavaScript` they are omitted when trailing, so that the function receives `undefined`
+///   and uses default values of its parameters, otherwise they are passed as `null`.
+/// * Arguments with default values (`new_window: bool = false`) take `Option<T>`, `None` is
+///   replaced by the default value.
+/// * Slice arguments (`args: &[T]`) are passed as separate arguments, so they have to be the
+///   last argument.
+///
+/// ```
+/// use osakit::declare_script;
+///
+/// declare_script! {
+///     #[language(JavaScript)]
+///     #[source("
+///         function open(path, newWindow, title = 'Untitled') {
+///             // ...
+///         }
+///
+///         function sum(...values) {
+///             return values.reduce((a, b) => a + b, 0);
+///         }
+///     ")]
+///     pub EditorScript {
+///         // Called as `script.open("/tmp", None, None)`.
+///         pub fn open(path: &str, new_window: bool = false, title: Option<&str>);
+///         // Called as `script.sum(&[1, 2, 3])`.
+///         pub fn sum(values: &[i32]) -> i32;
+///     }
+/// }
+/// ```
+///
+/// ## Custom error types
+///
+/// Functions return [`crate::ScriptFunctionRunError`] unless declared as returning
+/// `Result<T, E>` where `E: From<ScriptFunctionRunError>`. Errors with specific numbers or
+/// `JavaScript` error names can be mapped to unit variants of `E` using `#[on_error(...)]`:
+///
+/// ```
+/// use osakit::{declare_script, ScriptFunctionRunError};
+///
+/// #[derive(Debug)]
+/// pub enum FinderError {
+///     NotFound,
+///     Cancelled,
+///     InvalidArgument,
+///     Script(ScriptFunctionRunError),
+/// }
+///
+/// impl From<ScriptFunctionRunError> for FinderError {
+///     fn from(error: ScriptFunctionRunError) -> Self {
+///         FinderError::Script(error)
+///     }
+/// }
+///
+/// declare_script! {
+///     #[language(JavaScript)]
+///     #[source("
+///         function window_name(index) {
+///             return Application('Finder').windows[index].name();
+///         }
+///     ")]
+///     pub FinderScript {
+///         #[on_error(-1728 => NotFound, -128 => Cancelled, "TypeError" => InvalidArgument)]
+///         pub fn window_name(index: u32) -> Result<String, FinderError>;
+///     }
+/// }
+/// ```
+///
+/// ## Inline function bodies
+///
+/// Functions can be declared with their bodies, wrapped in `js!` or `applescript!`, instead of
+/// (or in addition to) `#[source]`. The function (`function name(args) { ... }`) or the handler
+/// (`on |name|(|args|) ... end |name|`) is generated and appended to the source. `AppleScript`
+/// names are enclosed in vertical bars, so arguments can be named like reserved words; refer to
+/// them as `|text|` in the body. Compilation errors inside the bodies and their generated headers
+/// are reported as [`crate::ScriptCompilationError::Function`], locations of errors are relative
+/// to the body:
+///
+/// ```
+/// use osakit::declare_script;
+///
+/// declare_script! {
+///     #[language(JavaScript)]
+///     pub MyJsScript {
+///         pub fn concat(x: &str, y: &str) -> String {
+///             js!("return x + y;")
+///         }
+///         pub fn sum(values: &[i32]) -> i32 {
+///             js!("return values.reduce((a, b) => a + b, 0);")
+///         }
+///     }
+/// }
+/// ```
+///
+/// ## Argument type checks
+///
+/// Inline functions of scripts marked with `#[guard]` start with checks of argument types
+/// (`typeof` in `JavaScript`, `class of` in `AppleScript`) derived from the Rust types of the
+/// arguments: strings, numbers, booleans, vectors, slices and maps. Arguments of other types
+/// are not checked. Calls with unexpected types, also made from other functions of the script,
+/// fail with [`crate::ScriptFunctionRunError::ArgumentType`]:
+///
+/// ```
+/// use osakit::declare_script;
+///
+/// declare_script! {
+///     #[language(JavaScript)]
+///     #[guard]
+///     pub MyJsScript {
+///         pub fn length(text: &str) -> usize {
+///             js!("return text.length;")
+///         }
+///     }
+/// }
+/// ```
+///
+/// ## Sharing scripts
+///
+/// Besides `new()`, compiling the script on every call, `instance()` and `shared()` return
+/// a [`SharedScript`] compiled once on first use, which can be used from any thread: the calls
+/// are dispatched to the main thread, which has to run its run loop. Scripts marked with `#[eager]` (after `#[language]`,
+/// along with `#[source]`, `#[mock]` and `#[guard]`) are compiled by
+/// [`compile_eager_scripts`], reporting all compilation errors together at startup.
+///
+/// ## Testing
+///
+/// Calls can be recorded on macOS and replayed on any platform using [`crate::Cassette`].
+///
+/// With the `mock` feature, `#[mock(TraitName)]` generates a trait with the declared functions,
+/// implemented by the struct and by a `Mock` prefixed struct, whose results are set in tests
+/// using `expect_*()` methods:
+///
+/// ```
+/// # #[cfg(feature = "mock")]
+/// # {
+/// use osakit::{declare_script, ScriptExecutionError, ScriptFunctionRunError};
+///
+/// declare_script! {
+///     #[language(JavaScript)]
+///     #[source("function concat(x, y) { return x + y; }")]
+///     #[mock(Concatenation)]
+///     pub MyJsScript {
+///         pub fn concat(x: &str, y: &str) -> String;
+///     }
+/// }
+///
+/// fn greeting(script: &impl Concatenation) -> String {
+///     script.concat("Hello, ", "World").unwrap_or_default()
+/// }
+///
+/// let mut script = MockMyJsScript::new();
+/// script
+///     .expect_concat()
+///     .returning(|x, y| Ok(format!("{}{}", x, y)))
+///     .times(1);
+/// assert_eq!(greeting(&script), "Hello, World");
+///
+/// let mut script = MockMyJsScript::new();
+/// script
+///     .expect_concat()
+///     .returning(|_, _| Err(ScriptFunctionRunError::Execution(ScriptExecutionError::Unknown)));
+/// assert_eq!(greeting(&script), "");
+/// # }
+/// ```
+#[cfg(feature = "declare-script")]
+#[macro_export]
+macro_rules! declare_script {
+    (
+        @script
+        (
+            language = ($language:ident)
+            source = ($($source:expr)?)
+            mock = ($($mock_trait:ident)?)
+            eager = ($($eager:ident)?)
+            guard = ($guard:literal)
+        )
+        ($(#[$struct_meta:meta])*) ($vis:vis) $struct_name:ident
+        [$(
+            {
+                meta = [$(#[$($fn_meta:tt)*])*]
+                vis = [$fn_vis:vis]
+                name = [$fn_name:ident]
+                args = [$($fn_args:tt)*]
+                res = [$($fn_res:tt)*]
+                body = [$($fn_body:tt)*]
+            }
+        )*]
+    ) => {
+        $(#[$struct_meta])*
+        $vis struct $struct_name {
+            script: $crate::Script,
+            bodies: $crate::macros::FunctionBodies,
+        }
+
+        impl $struct_name {
+            $vis fn new() -> ::core::result::Result<$struct_name, $crate::ScriptCompilationError> {
+                #[allow(unused_mut)]
+                let mut source = $crate::macros::ScriptSource::new(
+                    $crate::Language::$language,
+                    $crate::__script_source!($($source)?),
+                    $guard
+                );
+                $(
+                    $crate::__script_inline!(
+                        source $language $fn_name($($fn_args)*) $($fn_body)*
+                    );
+                )*
+                let (script, bodies) = source.compile()?;
+                Ok($struct_name { script, bodies })
+            }
+
+            /// Returns the script shared by all threads, compiled on first use.
+            #[allow(dead_code)]
+            $vis fn instance() -> ::core::result::Result<
+                &'static $crate::macros::SharedScript<$struct_name>,
+                $crate::ScriptCompilationError
+            > {
+                // SAFETY: the script is a static of the declared script.
+                static SHARED: $crate::macros::SharedScript<$struct_name> = unsafe {
+                    $crate::macros::SharedScript::new(stringify!($struct_name), $struct_name::new)
+                };
+                SHARED.instance()
+            }
+
+            /// Returns the script shared by all threads, compiled on first use.
+            ///
+            /// ## Panics
+            ///
+            /// Panics if the script fails to compile.
+            #[allow(dead_code)]
+            $vis fn shared() -> &'static $crate::macros::SharedScript<$struct_name> {
+                Self::instance().unwrap_or_else(|error| {
+                    panic!("script `{}` failed to compile: {}", stringify!($struct_name), error)
+                })
+            }
+
+            $(
+                $crate::__script_fn!(
+                    $(#[$($fn_meta)*])*
+                    $fn_vis fn $fn_name($($fn_args)*) -> $($fn_res)*;
+                );
+            )*
+        }
+
+        $(
+            const _: () = {
+                #[$crate::macros::__ctor]
+                fn $eager() {
+                    $crate::macros::__register_eager(stringify!($struct_name), || {
+                        $struct_name::instance().map(|_| ())
+                    });
+                }
+            };
+        )?
+
+        $crate::__script_mock!(
+            [$($mock_trait)?] ($crate) $vis $struct_name {
+                $(
+                    $(#[$($fn_meta)*])*
+                    $fn_vis fn $fn_name($($fn_args)*) -> $($fn_res)*;
+                )*
+            }
+        );
+    };
+    (
+        @header language = $language:tt source = $source:tt mock = $mock_trait:tt eager = $eager:tt guard = $guard:tt
+        #[source($new_source:expr)]
+        $($rest:tt)*
+    ) => {
+        $crate::declare_script!(
+            @header language = $language source = ($new_source) mock = $mock_trait eager = $eager guard = $guard
+            $($rest)*
+        );
+    };
+    (
+        @header language = $language:tt source = $source:tt mock = $mock_trait:tt eager = $eager:tt guard = $guard:tt
+        #[mock($new_mock_trait:ident)]
+        $($rest:tt)*
+    ) => {
+        $crate::declare_script!(
+            @header language = $language source = $source mock = ($new_mock_trait) eager = $eager guard = $guard
+            $($rest)*
+        );
+    };
+    (
+        @header language = $language:tt source = $source:tt mock = $mock_trait:tt eager = $eager:tt guard = $guard:tt
+        #[eager]
+        $($rest:tt)*
+    ) => {
+        $crate::declare_script!(
+            @header language = $language source = $source mock = $mock_trait eager = (register_eager_script) guard = $guard
+            $($rest)*
+        );
+    };
+    (
+        @header language = $language:tt source = $source:tt mock = $mock_trait:tt eager = $eager:tt guard = $guard:tt
+        #[guard]
+        $($rest:tt)*
+    ) => {
+        $crate::declare_script!(
+            @header language = $language source = $source mock = $mock_trait eager = $eager guard = (true)
+            $($rest)*
+        );
+    };
+    (
+        @header language = $language:tt source = $source:tt mock = $mock_trait:tt eager = $eager:tt guard = $guard:tt
+        $(#[$struct_meta:meta])*
+        $vis:vis $struct_name:ident { $($fns:tt)* }
+    ) => {
+        $crate::__script_fns!(
+            (
+                @script
+                (language = $language source = $source mock = $mock_trait eager = $eager guard = $guard)
+                ($(#[$struct_meta])*) ($vis) $struct_name
+            )
+            []
+            $($fns)*
+        );
+    };
+    (
+        #[language($language:ident)]
+        $($rest:tt)*
+    ) => {
+        $crate::declare_script!(
+            @header language = ($language) source = () mock = () eager = () guard = (false)
+            $($rest)*
+        );
+    };
+}
+
+/// Expands to the source specified using `#[source]` in [`declare_script!`] or an empty string.
+#[cfg(feature = "declare-script")]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __script_source {
+    () => {
+        ""
+    };
+    ($source:expr) => {
+        $source
+    };
+}
+
+/// Normalizes functions declared in [`declare_script!`], keeping result types as tokens.
+#[cfg(feature = "declare-script")]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __script_fns {
+    (($($script:tt)*) [$($fns:tt)*]) => {
+        $crate::declare_script!($($script)* [$($fns)*]);
+    };
+    (@res $script:tt [$($fns:tt)*] ($($fn:tt)*) [$($res:tt)*] ; $($rest:tt)*) => {
+        $crate::__script_fns!(
+            $script [$($fns)* { $($fn)* res = [$($res)*] body = [] }] $($rest)*
+        );
+    };
+    (@res $script:tt [$($fns:tt)*] ($($fn:tt)*) [$($res:tt)*] { $($body:tt)* } $($rest:tt)*) => {
+        $crate::__script_fns!(
+            $script [$($fns)* { $($fn)* res = [$($res)*] body = [$($body)*] }] $($rest)*
+        );
+    };
+    (@res $script:tt $fns:tt $fn:tt [$($res:tt)*] $token:tt $($rest:tt)*) => {
+        $crate::__script_fns!(@res $script $fns $fn [$($res)* $token] $($rest)*);
+    };
+    (@res_start $script:tt $fns:tt $fn:tt -> $($rest:tt)*) => {
+        $crate::__script_fns!(@res $script $fns $fn [] $($rest)*);
+    };
+    (@res_start $script:tt $fns:tt $fn:tt $($rest:tt)*) => {
+        $crate::__script_fns!(@res $script $fns $fn [()] $($rest)*);
+    };
+    (
+        $script:tt $fns:tt
+        $(#[$($fn_meta:tt)*])*
+        $fn_vis:vis fn $fn_name:ident($($fn_args:tt)*)
+        $($rest:tt)*
     ) => {
-        $crate::__script_fn_impl!(
+        $crate::__script_fns!(
+            @res_start $script $fns
+            (
+                meta = [$(#[$($fn_meta)*])*]
+                vis = [$fn_vis]
+                name = [$fn_name]
+                args = [$($fn_args)*]
+            )
+            $($rest)*
+        );
+    };
+}
+
+#[cfg(feature = "declare-script")]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __script_inline {
+    ($source:ident $language:ident $name:ident($($args:tt)*)) => {};
+    ($source:ident JavaScript $name:ident($($args:tt)*) js!($body:literal)) => {
+        $source.push_function(
+            stringify!($name),
+            $crate::__script_params!(JavaScript () $($args)*),
+            $body
+        );
+    };
+    ($source:ident AppleScript $name:ident($($args:tt)*) applescript!($body:literal)) => {
+        $source.push_function(
+            stringify!($name),
+            $crate::__script_params!(AppleScript () $($args)*),
+            $body
+        );
+    };
+    ($source:ident $language:ident $name:ident($($args:tt)*) $body_macro:ident!($body:literal)) => {
+        ::core::compile_error!(concat!(
+            "`", stringify!($body_macro), "!` cannot be used in ", stringify!($language),
+            " scripts, use `js!` for JavaScript and `applescript!` for AppleScript"
+        ));
+    };
+}
+
+#[cfg(feature = "declare-script")]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __script_params {
+    (JavaScript ($($params:expr,)*) $arg_name:ident : &[$arg_type:ty] $(, $($rest:tt)*)?) => {
+        $crate::__script_params!(
+            JavaScript (
+                $($params,)*
+                $crate::macros::ScriptParam::new(
+                    stringify!($arg_name),
+                    $crate::__script_value_type!($arg_type)
+                ).variadic(),
+            )
+            $($($rest)*)?
+        )
+    };
+    (AppleScript ($($params:expr,)*) $arg_name:ident : &[$arg_type:ty] $(, $($rest:tt)*)?) => {
+        ::core::compile_error!("AppleScript handlers cannot take variadic arguments")
+    };
+    ($language:ident ($($params:expr,)*) $arg_name:ident : Option<$arg_type:ty> $(, $($rest:tt)*)?) => {
+        $crate::__script_params!(
+            $language (
+                $($params,)*
+                $crate::macros::ScriptParam::new(
+                    stringify!($arg_name),
+                    $crate::__script_value_type!($arg_type)
+                ).optional(),
+            )
+            $($($rest)*)?
+        )
+    };
+    ($language:ident ($($params:expr,)*) $arg_name:ident : $arg_type:ty = $default:expr $(, $($rest:tt)*)?) => {
+        $crate::__script_params!(
+            $language (
+                $($params,)*
+                $crate::macros::ScriptParam::new(
+                    stringify!($arg_name),
+                    $crate::__script_value_type!($arg_type)
+                ),
+            )
+            $($($rest)*)?
+        )
+    };
+    ($language:ident ($($params:expr,)*) $arg_name:ident : $arg_type:ty $(, $($rest:tt)*)?) => {
+        $crate::__script_params!(
+            $language (
+                $($params,)*
+                $crate::macros::ScriptParam::new(
+                    stringify!($arg_name),
+                    $crate::__script_value_type!($arg_type)
+                ),
+            )
+            $($($rest)*)?
+        )
+    };
+    ($language:ident ($($params:expr,)*)) => {
+        &[$($params),*]
+    };
+}
+
+#[cfg(feature = "declare-script")]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __script_value_type {
+    ($arg_type:ty) => {{
+        #[allow(unused_imports)]
+        use $crate::macros::{AnyValueType as _, KnownValueType as _};
+        (&$crate::macros::TypeOf::<$arg_type>::new()).value_type()
+    }};
+}
+
+#[cfg(feature = "declare-script")]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __script_mock {
+    ([] $($tokens:tt)*) => {};
+    ([$mock_trait:ident] ($($krate:tt)*) $vis:vis $struct_name:ident { $($fns:tt)* }) => {
+        $crate::__script_mock_impl!(($($krate)*) $vis $struct_name: $mock_trait { $($fns)* });
+    };
+}
+
+#[cfg(all(feature = "declare-script", not(feature = "mock")))]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __script_mock_impl {
+    ($($tokens:tt)*) => {
+        ::core::compile_error!("`#[mock(...)]` requires the `mock` feature of osakit");
+    };
+}
+
+#[cfg(feature = "declare-script")]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __script_fn {
+    (
+        @parse meta = ($($meta:tt)*) retry = ($retry:expr) on_error = $on_error:tt
+        #[retry(attempts = $attempts:expr)]
+        $($rest:tt)*
+    ) => {
+        $crate::__script_fn!(
+            @parse
             meta = ($($meta)*)
-            vis = ($vis)
-            name = ($name)
-            args = ($($arg_name : $arg_type),*)
-            res = (())
+            retry = (Some($crate::RetryPolicy::new($attempts)))
+            on_error = $on_error
+            $($rest)*
+        );
+    };
+    (
+        @parse meta = ($($meta:tt)*) retry = ($retry:expr) on_error = $on_error:tt
+        #[on_error($($pattern:literal => $variant:ident),* $(,)?)]
+        $($rest:tt)*
+    ) => {
+        $crate::__script_fn!(
+            @parse
+            meta = ($($meta)*)
+            retry = ($retry)
+            on_error = ($($pattern => $variant),*)
+            $($rest)*
+        );
+    };
+    (
+        @parse meta = ($($meta:tt)*) retry = ($retry:expr) on_error = $on_error:tt
+        #[$($attr:tt)*]
+        $($rest:tt)*
+    ) => {
+        $crate::__script_fn!(
+            @parse meta = ($($meta)* #[$($attr)*]) retry = ($retry) on_error = $on_error
+            $($rest)*
+        );
+    };
+    (
+        @parse meta = ($(#[$meta:meta])*) retry = ($retry:expr) on_error = $on_error:tt
+        $vis:vis fn $name:ident($($args:tt)*) -> Result<$res_type:ty, $err_type:ty>;
+    ) => {
+        $crate::__script_args!(
+            fn = (
+                meta = ($($meta)*)
+                vis = ($vis)
+                name = ($name)
+                res = ($res_type)
+                err = ($err_type)
+                retry = ($retry)
+                on_error = $on_error
+            )
+            args = ()
+            push = ()
+            $($args)*
+        );
+    };
+    (
+        @parse meta = ($(#[$meta:meta])*) retry = ($retry:expr) on_error = $on_error:tt
+        $vis:vis fn $name:ident($($args:tt)*) -> $res_type:ty;
+    ) => {
+        $crate::__script_args!(
+            fn = (
+                meta = ($($meta)*)
+                vis = ($vis)
+                name = ($name)
+                res = ($res_type)
+                err = ($crate::ScriptFunctionRunError)
+                retry = ($retry)
+                on_error = $on_error
+            )
+            args = ()
+            push = ()
+            $($args)*
+        );
+    };
+    ($($tokens:tt)*) => {
+        $crate::__script_fn!(@parse meta = () retry = (None) on_error = () $($tokens)*);
+    };
+}
+
+#[cfg(feature = "declare-script")]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __script_args {
+    (
+        fn = $fn:tt args = ($($args:tt)*) push = ($($push:tt)*)
+        $arg_name:ident : Option<$arg_type:ty> $(, $($rest:tt)*)?
+    ) => {
+        $crate::__script_args!(
+            fn = $fn
+            args = ($($args)* $arg_name : Option<$arg_type>,)
+            push = ($($push)* push_optional($arg_name))
+            $($($rest)*)?
+        );
+    };
+    (
+        fn = $fn:tt args = ($($args:tt)*) push = ($($push:tt)*)
+        $arg_name:ident : &[$arg_type:ty] $(,)?
+    ) => {
+        $crate::__script_args!(
+            fn = $fn
+            args = ($($args)* $arg_name : &[$arg_type],)
+            push = ($($push)* push_variadic($arg_name))
+        );
+    };
+    (
+        fn = $fn:tt args = ($($args:tt)*) push = ($($push:tt)*)
+        $arg_name:ident : &[$arg_type:ty], $($rest:tt)+
+    ) => {
+        ::core::compile_error!(concat!(
+            "`", stringify!($arg_name), "`: slice arguments are passed as separate arguments ",
+            "and have to be the last argument"
+        ));
+    };
+    (
+        fn = $fn:tt args = ($($args:tt)*) push = ($($push:tt)*)
+        $arg_name:ident : $arg_type:ty = $default:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::__script_args!(
+            fn = $fn
+            args = ($($args)* $arg_name : Option<$arg_type>,)
+            push = ($($push)* push_default($arg_name, || $default))
+            $($($rest)*)?
+        );
+    };
+    (
+        fn = $fn:tt args = ($($args:tt)*) push = ($($push:tt)*)
+        $arg_name:ident : $arg_type:ty $(, $($rest:tt)*)?
+    ) => {
+        $crate::__script_args!(
+            fn = $fn
+            args = ($($args)* $arg_name : $arg_type,)
+            push = ($($push)* push_required($arg_name))
+            $($($rest)*)?
+        );
+    };
+    (fn = ($($fn:tt)*) args = ($($args:tt)*) push = ($($push:tt)*)) => {
+        $crate::__script_fn_impl!($($fn)* args = ($($args)*) push = ($($push)*));
+    };
+}
+
+#[cfg(feature = "declare-script")]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __script_fn_impl {
+    (
+        meta = ($($meta:meta)*)
+        vis = ($vis:vis)
+        name = ($name:ident)
+        res = ($res_type:ty)
+        err = ($err_type:ty)
+        retry = ($retry:expr)
+        on_error = ($($pattern:literal => $variant:ident),*)
+        args = ($($arg_name:ident : $arg_type:ty,)*)
+        push = ($($push:ident($push_arg:ident $(, $default:expr)?))*)
+    ) => {
+        $(#[$meta])*
+        $vis fn $name(&self $(, $arg_name : $arg_type)*) -> ::core::result::Result<$res_type, $err_type> {
+            #[allow(unused_mut)]
+            let mut arguments = $crate::macros::ScriptArguments::new();
+            $(
+                arguments.$push(stringify!($push_arg), $push_arg $(, $default)?)?;
+            )*
+            $crate::macros::__exec_and_deserialize(
+                &self.script,
+                stringify!($name),
+                arguments.finish(self.script.language()),
+                $retry
+            )
+            .map_err(|error| {
+                let error = self.bodies.map_run_error(stringify!($name), error);
+                $(
+                    if $crate::macros::ErrorPattern::matches(&$pattern, &error) {
+                        return <$err_type>::$variant;
+                    }
+                )*
+                <$err_type as ::core::convert::From<$crate::ScriptFunctionRunError>>::from(error)
+            })
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::cassette::{Cassette, Interaction};
+    use super::super::js_exception::JsException;
+    use super::super::script::{Language, ScriptCompilationError, ScriptExecutionError};
+    use super::super::trace::hash_source;
+    use super::super::value::Value;
+    use super::{
+        compile_eager_scripts, EagerCompilationError, FunctionBodies, ScriptArguments,
+        ScriptFunctionRunError, ScriptParam, ScriptSource, SharedScript, ValueType,
+    };
+    use serde::ser::{Error, Serialize, Serializer};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Runs the test with a cassette replaying the interactions.
+    fn replay(interactions: Vec<Interaction>, test: impl FnOnce()) {
+        static CASSETTES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "osakit_macros_test_{}_{}.json",
+            std::process::id(),
+            CASSETTES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = serde_json::json!({ "interactions": interactions });
+        std::fs::write(&path, file.to_string()).unwrap();
+        let cassette = Cassette::replay(&path);
+        std::fs::remove_file(path).unwrap();
+        let cassette = cassette.unwrap().insert();
+        test();
+        cassette.finish().unwrap();
+    }
+
+    fn compile(language: Language, hash: u64) -> Interaction {
+        Interaction::compile(language, hash).with_compile_result(&Ok(()))
+    }
+
+    fn call(
+        hash: u64,
+        handler: &str,
+        arguments: &[Value],
+        result: Result<Value, ScriptExecutionError>,
+    ) -> Interaction {
+        Interaction::execute_function(Language::JavaScript, hash, handler, arguments)
+            .with_execute_result(&result)
+    }
+
+    fn failure(message: &str, number: i32, location: usize) -> ScriptCompilationError {
+        ScriptCompilationError::Failure {
+            message: message.into(),
+            location,
+            length: 0,
+            number: Some(number),
+            brief_message: None,
+            app_name: None,
+            partial_result: None,
+            offending_object: None,
+        }
+    }
+
+    fn runtime(message: &str, number: Option<i32>, location: usize) -> ScriptExecutionError {
+        ScriptExecutionError::Runtime {
+            message: message.into(),
+            location,
+            length: 0,
+            number,
+            brief_message: None,
+            app_name: None,
+            partial_result: None,
+            offending_object: None,
+            js_exception: None,
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    declare_script! {
+        #[language(JavaScript)]
+        #[source("
+            function concat(x, y) {
+                return x + y;
+            }
+
+            function no_args_no_result() {}
+
+            function throws_an_error(message) {
+                throw new Error(message);
+            }
+        ")]
+        pub(crate) MacroTestScript {
+            pub(crate) fn concat(x: &str, y: &str) -> String;
+            pub(crate) fn no_args_no_result();
+            pub(crate) fn throws_an_error(message: &str);
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    declare_script! {
+        #[language(AppleScript)]
+        #[source("
+            property attempts : 0
+
+            on succeeds_on_second_attempt()
+                set attempts to attempts + 1
+                if attempts < 2 then error \"Application isn’t running.\" number -600
+                return attempts
+            end succeeds_on_second_attempt
+        ")]
+        pub(crate) MacroRetryTestScript {
+            /// Retried in case of transient errors.
+            #[retry(attempts = 2)]
+            pub(crate) fn succeeds_on_second_attempt() -> u32;
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_runs_concat_function() {
+        let script = MacroTestScript::new().unwrap();
+        assert_eq!(script.concat("Hello, ", "World").unwrap(), "Hello, World");
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_runs_no_args_no_result() {
+        let script = MacroTestScript::new().unwrap();
+        assert_eq!(script.no_args_no_result().unwrap(), ());
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_throws_an_error() {
+        let script = MacroTestScript::new().unwrap();
+        assert!(matches!(
+            script.throws_an_error("Test Error").unwrap_err(),
+            ScriptFunctionRunError::Execution(ScriptExecutionError::Runtime {
+                message,
+                location: 0,
+                length: 0,
+                ..
+            }) if message == "Error: Error: Test Error"
+        ));
+    }
+
+    #[cfg(target_os = "macos")]
+    declare_script! {
+        #[language(JavaScript)]
+        #[source("
+            function greet(name, greeting = 'Hello', punctuation) {
+                return greeting + ', ' + name + (punctuation === undefined ? '.' : punctuation);
+            }
+
+            function sum(...values) {
+                return values.reduce((a, b) => a + b, 0);
+            }
+        ")]
+        pub(crate) MacroArgumentsTestScript {
+            pub(crate) fn greet(name: &str, greeting: Option<&str>, punctuation: &str = "!") -> String;
+            pub(crate) fn sum(values: &[i32]) -> i32;
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_passes_optional_default_and_variadic_arguments() {
+        let script = MacroArgumentsTestScript::new().unwrap();
+        assert_eq!(script.greet("World", None, None).unwrap(), "Hello, World!");
+        assert_eq!(
+            script.greet("World", Some("Hi"), Some("?")).unwrap(),
+            "Hi, World?"
         );
-    };
+        assert_eq!(script.sum(&[1, 2, 3]).unwrap(), 6);
+        assert_eq!(script.sum(&[]).unwrap(), 0);
+    }
+
+    #[cfg(target_os = "macos")]
+    declare_script! {
+        #[language(AppleScript)]
+        #[source("
+            on describe(x, y)
+                if y is missing value then return x & \", none\"
+                return x & \", \" & y
+            end describe
+        ")]
+        pub(crate) MacroMissingValueTestScript {
+            pub(crate) fn describe(x: &str, y: Option<&str>) -> String;
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_passes_missing_value_for_optional_apple_script_arguments() {
+        let script = MacroMissingValueTestScript::new().unwrap();
+        assert_eq!(script.describe("a", None).unwrap(), "a, none");
+        assert_eq!(script.describe("a", Some("b")).unwrap(), "a, b");
+    }
+
+    #[cfg(target_os = "macos")]
+    declare_script! {
+        #[language(AppleScript)]
+        pub(crate) MacroInlineTestScript {
+            pub(crate) fn concat(x: &str, y: &str) -> String {
+                applescript!("return x & y")
+            }
+            pub(crate) fn fails() {
+                applescript!("error \"Test Error\" number -1728")
+            }
+            pub(crate) fn repeat(text: &str, count: i32) -> String {
+                applescript!("
+                    set output to \"\"
+                    repeat |count| times
+                        set output to output & |text|
+                    end repeat
+                    return output
+                ")
+            }
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    declare_script! {
+        #[language(JavaScript)]
+        #[source("function valid() {}")]
+        pub(crate) MacroInlineErrorTestScript {
+            pub(crate) fn invalid() {
+                js!("return )")
+            }
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_shares_scripts() {
+        let script = MacroTestScript::shared();
+        assert!(std::ptr::eq(script, MacroTestScript::shared()));
+        assert_eq!(
+            script
+                .with(|script| script.concat("Hello, ", "World"))
+                .unwrap(),
+            "Hello, World"
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    declare_script! {
+        #[language(JavaScript)]
+        #[guard]
+        pub(crate) MacroGuardTestScript {
+            pub(crate) fn length(text: &str) -> usize {
+                js!("return text.length;")
+            }
+            pub(crate) fn length_of_number() -> usize {
+                js!("return length(42);")
+            }
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_guards_argument_types() {
+        let script = MacroGuardTestScript::new().unwrap();
+        assert_eq!(script.length("abc").unwrap(), 3);
+        assert_eq!(
+            script.length_of_number(),
+            Err(ScriptFunctionRunError::ArgumentType {
+                arg_name: "text".into(),
+                expected: "string".into(),
+                actual: "number".into(),
+            })
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_runs_inline_functions() {
+        let script = MacroInlineTestScript::new().unwrap();
+        assert_eq!(script.concat("Hello, ", "World").unwrap(), "Hello, World");
+        assert_eq!(script.repeat("ab", 2).unwrap(), "abab");
+        assert!(matches!(
+            script.fails().unwrap_err(),
+            ScriptFunctionRunError::Execution(ScriptExecutionError::Runtime {
+                number: Some(-1728),
+                ..
+            })
+        ));
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_reports_compilation_errors_of_inline_functions() {
+        let error = MacroInlineErrorTestScript::new()
+            .map(|script| script.invalid())
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ScriptCompilationError::Function { name, .. } if name == "invalid"
+        ));
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_retries_transient_errors() {
+        let script = MacroRetryTestScript::new().unwrap();
+        assert_eq!(script.succeeds_on_second_attempt().unwrap(), 2);
+    }
+
+    const ARGUMENTS_SOURCE: &str = "
+        function open(path, newWindow, title) {}
+
+        function sum(...values) {
+            return values.reduce((a, b) => a + b, 0);
+        }
+    ";
+
+    declare_script! {
+        #[language(JavaScript)]
+        #[source(ARGUMENTS_SOURCE)]
+        ArgumentsTestScript {
+            fn open(path: &str, new_window: bool = false, title: Option<&str>);
+            fn sum(values: &[i32]) -> i32;
+        }
+    }
+
+    struct Unserializable;
+
+    impl Serialize for Unserializable {
+        fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+            Err(S::Error::custom("unserializable"))
+        }
+    }
+
+    fn arguments(
+        push: impl Fn(&mut ScriptArguments) -> Result<(), ScriptFunctionRunError>,
+    ) -> Vec<Value> {
+        language_arguments(Language::JavaScript, push)
+    }
+
+    fn language_arguments(
+        language: Language,
+        push: impl Fn(&mut ScriptArguments) -> Result<(), ScriptFunctionRunError>,
+    ) -> Vec<Value> {
+        let mut arguments = ScriptArguments::new();
+        push(&mut arguments).unwrap();
+        arguments.finish(language)
+    }
+
+    #[test]
+    fn it_omits_trailing_optional_arguments_in_java_script() {
+        let push = |arguments: &mut ScriptArguments| {
+            arguments.push_required("a", 1)?;
+            arguments.push_optional::<i32>("b", None)?;
+            arguments.push_optional("c", Some("c"))?;
+            arguments.push_optional::<i32>("d", None)?;
+            arguments.push_optional::<i32>("e", None)
+        };
+        assert_eq!(
+            language_arguments(Language::JavaScript, push),
+            vec![Value::from(1), Value::Null, Value::from("c")]
+        );
+        assert_eq!(
+            language_arguments(Language::AppleScript, push),
+            vec![
+                Value::from(1),
+                Value::Null,
+                Value::from("c"),
+                Value::Null,
+                Value::Null
+            ]
+        );
+    }
+
+    #[test]
+    fn it_uses_default_values() {
+        assert_eq!(
+            arguments(|arguments| {
+                arguments.push_default("a", None, || false)?;
+                arguments.push_default("b", Some("b"), || "default")
+            }),
+            vec![Value::from(false), Value::from("b")]
+        );
+    }
+
+    #[test]
+    fn it_expands_variadic_arguments() {
+        assert_eq!(
+            arguments(|arguments| {
+                arguments.push_variadic("values", &[1, 2])?;
+                arguments.push_variadic::<i32>("empty", &[])?;
+                arguments.push_optional::<i32>("optional", None)
+            }),
+            vec![Value::from(1), Value::from(2)]
+        );
+    }
+
+    #[test]
+    fn it_passes_declared_arguments() {
+        let hash = hash_source(ARGUMENTS_SOURCE);
+        let interactions = vec![
+            compile(Language::JavaScript, hash),
+            call(
+                hash,
+                "open",
+                &[Value::from("/tmp"), Value::from(false)],
+                Ok(Value::Null),
+            ),
+            call(
+                hash,
+                "open",
+                &[Value::from("/tmp"), Value::from(true), Value::from("Notes")],
+                Ok(Value::Null),
+            ),
+            call(
+                hash,
+                "sum",
+                &[Value::from(1), Value::from(2)],
+                Ok(Value::from(3)),
+            ),
+        ];
+        replay(interactions, || {
+            let script = ArgumentsTestScript::new().unwrap();
+            script.open("/tmp", None, None).unwrap();
+            script.open("/tmp", Some(true), Some("Notes")).unwrap();
+            assert_eq!(script.sum(&[1, 2]).unwrap(), 3);
+        });
+    }
+
+    #[test]
+    fn it_reports_serialization_errors() {
+        let mut arguments = ScriptArguments::new();
+        assert_eq!(
+            arguments.push_variadic("values", &[Unserializable]),
+            Err(ScriptFunctionRunError::ArgumentSerialization {
+                arg_name: "values".into(),
+                message: "unserializable".into()
+            })
+        );
+    }
+
+    declare_script! {
+        #[language(JavaScript)]
+        #[source("const separator = ', ';")]
+        InlineTestScript {
+            fn join(x: &str, y: &str) -> String {
+                js!("return x + separator + y;")
+            }
+            fn sum(values: &[i32]) -> i32 {
+                js!("return values.reduce((a, b) => a + b, 0);")
+            }
+        }
+    }
+
+    const INLINE_SOURCE: &str = "const separator = ', ';
+function join(x, y) {
+return x + separator + y;
+}
+function sum(...values) {
+return values.reduce((a, b) => a + b, 0);
 }
+";
 
-#[cfg(feature = "declare-script")]
-#[macro_export]
-#[doc(hidden)]
-macro_rules! __script_fn_impl {
-    (
-        meta = ($($meta:meta)*)
-        vis = ($vis:vis)
-        name = ($name:ident)
-        args = ($($arg_name:ident : $arg_type:ty),*)
-        res = ($res_type:ty)
-    ) => {
-        $(#[$meta])*
-        $vis fn $name(&self $(, $arg_name : $arg_type)*) -> ::core::result::Result<$res_type, $crate::ScriptFunctionRunError> {
-            let arguments: Vec<$crate::Value> = vec![$(
-                $crate::to_value($arg_name).or_else(|e| $crate::macros::__arg_s_error(stringify!($arg_name), e))?,
-            )*];
-            $crate::macros::__exec_and_deserialize(
-                &self.script,
-                stringify!($name),
-                arguments
-            )
+    #[test]
+    fn it_wraps_javascript_function_bodies() {
+        let mut source = ScriptSource::new(Language::JavaScript, "const separator = ', ';", false);
+        source.push_function(
+            "join",
+            &[ScriptParam::new("x", None), ScriptParam::new("y", None)],
+            "return x + separator + y;",
+        );
+        source.push_function(
+            "sum",
+            &[ScriptParam::new("values", None).variadic()],
+            "return values.reduce((a, b) => a + b, 0);",
+        );
+        assert_eq!(source.source(), INLINE_SOURCE);
+    }
+
+    #[test]
+    fn it_wraps_applescript_handlers() {
+        let mut source = ScriptSource::new(Language::AppleScript, "", false);
+        source.push_function(
+            "concat",
+            &[
+                ScriptParam::new("text", None),
+                ScriptParam::new("count", None),
+            ],
+            "return text & count",
+        );
+        source.push_function("ping", &[], "return \"pong\"");
+        assert_eq!(
+            source.source(),
+            "on |concat|(|text|, |count|)\nreturn text & count\nend |concat|\n\
+             on |ping|()\nreturn \"pong\"\nend |ping|\n"
+        );
+    }
+
+    #[test]
+    fn it_maps_error_locations_to_functions() {
+        let mut source = ScriptSource::new(Language::JavaScript, "// ünïcödé\n", false);
+        source.push_function("first", &[], "return 1;");
+        source.push_function("second", &[], "return 2;");
+        let bodies = source.bodies;
+        let failure = |location| failure("Error: SyntaxError", -2700, location);
+        let runtime = |location| ScriptFunctionRunError::from(runtime("Error", None, location));
+        // "// ünïcödé\nfunction first() {\n" is 30 UTF-16 code units long.
+        assert_eq!(
+            bodies.map_compilation_error(failure(33)),
+            ScriptCompilationError::Function {
+                name: "first".into(),
+                error: Box::new(failure(3)),
+            }
+        );
+        assert_eq!(
+            bodies.map_compilation_error(failure(64)),
+            ScriptCompilationError::Function {
+                name: "second".into(),
+                error: Box::new(failure(2)),
+            }
+        );
+        // Errors in the header "function first() {" are reported at the start of the body.
+        assert_eq!(
+            bodies.map_compilation_error(failure(15)),
+            ScriptCompilationError::Function {
+                name: "first".into(),
+                error: Box::new(failure(0)),
+            }
+        );
+        assert_eq!(bodies.map_compilation_error(failure(3)), failure(3));
+        assert_eq!(bodies.map_run_error("first", runtime(35)), runtime(5));
+        assert_eq!(bodies.map_run_error("second", runtime(35)), runtime(35));
+        assert_eq!(bodies.map_run_error("first", runtime(0)), runtime(0));
+    }
+
+    #[test]
+    fn it_calls_inline_functions() {
+        let hash = hash_source(INLINE_SOURCE);
+        let interactions = vec![
+            compile(Language::JavaScript, hash),
+            call(
+                hash,
+                "join",
+                &[Value::from("a"), Value::from("b")],
+                Ok(Value::from("a, b")),
+            ),
+            call(
+                hash,
+                "sum",
+                &[Value::from(1), Value::from(2)],
+                Ok(Value::from(3)),
+            ),
+        ];
+        replay(interactions, || {
+            let script = InlineTestScript::new().unwrap();
+            assert_eq!(script.join("a", "b").unwrap(), "a, b");
+            assert_eq!(script.sum(&[1, 2]).unwrap(), 3);
+        });
+    }
+
+    declare_script! {
+        #[language(JavaScript)]
+        SharedTestScript {
+            fn concat(x: &str, y: &str) -> String {
+                js!("return x + y;")
+            }
         }
-    };
+    }
+
+    declare_script! {
+        #[language(AppleScript)]
+        #[source("on broken(")]
+        #[eager]
+        EagerTestScript {
+            fn broken();
+        }
+    }
+
+    #[test]
+    fn it_compiles_shared_scripts_once() {
+        let error = failure("Expected variable name", -2741, 10);
+        let hash = hash_source("function concat(x, y) {\nreturn x + y;\n}\n");
+        let interactions = vec![
+            Interaction::compile(Language::AppleScript, hash_source("on broken("))
+                .with_compile_result(&Err(error.clone())),
+            compile(Language::JavaScript, hash),
+            call(
+                hash,
+                "concat",
+                &[Value::from("a"), Value::from("b")],
+                Ok(Value::from("ab")),
+            ),
+        ];
+        replay(interactions, || {
+            assert_eq!(
+                compile_eager_scripts(),
+                Err(EagerCompilationError {
+                    errors: vec![("EagerTestScript".into(), error.clone())]
+                })
+            );
+            assert_eq!(
+                EagerTestScript::instance()
+                    .map(|script| script.with(EagerTestScript::broken))
+                    .err(),
+                Some(error)
+            );
+            let script = SharedTestScript::shared();
+            assert!(std::ptr::eq(script, SharedTestScript::shared()));
+            assert_eq!(script.with(|script| script.concat("a", "b")).unwrap(), "ab");
+        });
+    }
+
+    #[test]
+    fn it_compiles_shared_scripts_on_first_call() {
+        static SHARED: SharedScript<SharedTestScript> =
+            unsafe { SharedScript::new("SharedTestScript", SharedTestScript::new) };
+
+        let hash = hash_source("function concat(x, y) {\nreturn x + y;\n}\n");
+        let interactions = vec![
+            compile(Language::JavaScript, hash),
+            call(
+                hash,
+                "concat",
+                &[Value::from("a"), Value::from("b")],
+                Ok(Value::from("ab")),
+            ),
+        ];
+        replay(interactions, || {
+            assert_eq!(SHARED.with(|script| script.concat("a", "b")).unwrap(), "ab");
+            assert!(SHARED.instance().is_ok());
+        });
+    }
+
+    #[test]
+    fn it_lists_eager_compilation_errors() {
+        let error = EagerCompilationError {
+            errors: vec![
+                ("A".into(), ScriptCompilationError::Unknown),
+                ("B".into(), ScriptCompilationError::Unknown),
+            ],
+        };
+        assert_eq!(
+            error.to_string(),
+            "2 script(s) failed to compile\n  `A`: unknown compilation error\n  `B`: unknown compilation error"
+        );
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum FinderError {
+        NotFound,
+        Cancelled,
+        Type,
+        Script(ScriptFunctionRunError),
+    }
+
+    impl From<ScriptFunctionRunError> for FinderError {
+        fn from(error: ScriptFunctionRunError) -> Self {
+            FinderError::Script(error)
+        }
+    }
+
+    declare_script! {
+        #[language(JavaScript)]
+        #[source("function find(name) {}")]
+        ErrorTestScript {
+            #[on_error(-1728 => NotFound, -128 => Cancelled, "TypeError" => Type)]
+            fn find(name: &str) -> Result<Vec<String>, FinderError>;
+        }
+    }
+
+    #[test]
+    fn it_maps_errors_to_variants() {
+        let hash = hash_source("function find(name) {}");
+        let find = |result| call(hash, "find", &[Value::from("a")], result);
+        let other = runtime("Error: Error: Unexpected", None, 0);
+        let interactions = vec![
+            compile(Language::JavaScript, hash),
+            find(Ok(Value::from(vec!["a"]))),
+            find(Err(runtime("Can’t get window \"a\".", Some(-1728), 0))),
+            find(Err(runtime("User canceled. (-128)", None, 0))),
+            find(Err(runtime("Error: TypeError: undefined", None, 0))),
+            find(Err(other.clone())),
+        ];
+        replay(interactions, || {
+            let script = ErrorTestScript::new().unwrap();
+            assert_eq!(script.find("a"), Ok(vec!["a".into()]));
+            assert_eq!(script.find("a"), Err(FinderError::NotFound));
+            assert_eq!(script.find("a"), Err(FinderError::Cancelled));
+            assert_eq!(script.find("a"), Err(FinderError::Type));
+            assert_eq!(
+                script.find("a"),
+                Err(FinderError::Script(ScriptFunctionRunError::Execution(
+                    other
+                )))
+            );
+        });
+    }
+
+    declare_script! {
+        #[language(JavaScript)]
+        #[guard]
+        GuardTestScript {
+            fn length(text: &str) -> usize {
+                js!("return text.length;")
+            }
+        }
+    }
+
+    struct Unknown;
+
+    #[test]
+    fn it_resolves_value_types() {
+        assert_eq!(__script_value_type!(&str), Some(ValueType::String));
+        assert_eq!(__script_value_type!(String), Some(ValueType::String));
+        assert_eq!(__script_value_type!(&&String), Some(ValueType::String));
+        assert_eq!(__script_value_type!(u8), Some(ValueType::Number));
+        assert_eq!(__script_value_type!(f64), Some(ValueType::Number));
+        assert_eq!(__script_value_type!(bool), Some(ValueType::Boolean));
+        assert_eq!(__script_value_type!(Vec<Unknown>), Some(ValueType::List));
+        assert_eq!(__script_value_type!(&[u8]), Some(ValueType::List));
+        assert_eq!(
+            __script_value_type!(HashMap<String, Unknown>),
+            Some(ValueType::Record)
+        );
+        assert_eq!(__script_value_type!(Unknown), None);
+        assert_eq!(__script_value_type!(&Unknown), None);
+        assert_eq!(__script_value_type!(Value), None);
+    }
+
+    #[test]
+    fn it_generates_javascript_guards() {
+        let mut source = ScriptSource::new(Language::JavaScript, "", true);
+        source.push_function(
+            "f",
+            &[
+                ScriptParam::new("a", Some(ValueType::String)),
+                ScriptParam::new("b", Some(ValueType::Record)).optional(),
+                ScriptParam::new("c", None),
+                ScriptParam::new("d", Some(ValueType::Number)).variadic(),
+            ],
+            "return a;",
+        );
+        assert_eq!(
+            source.source(),
+            "function f(a, b, c, ...d) {
+if (!(typeof a === 'string')) throw new TypeError('osakit-argument-type:a:string:' + \
+(a === null ? 'null' : Array.isArray(a) ? 'array' : typeof a));
+if (!(b === undefined || b === null || typeof b === 'object' && b !== null && !Array.isArray(b))) \
+throw new TypeError('osakit-argument-type:b:object:' + \
+(b === null ? 'null' : Array.isArray(b) ? 'array' : typeof b));
+d.forEach(function (value) { if (!(typeof value === 'number')) \
+throw new TypeError('osakit-argument-type:d:number:' + \
+(value === null ? 'null' : Array.isArray(value) ? 'array' : typeof value)); });
+return a;
 }
+"
+        );
+    }
 
-#[cfg(test)]
-mod test {
+    #[test]
+    fn it_generates_applescript_guards() {
+        let mut source = ScriptSource::new(Language::AppleScript, "", true);
+        source.push_function(
+            "f",
+            &[
+                ScriptParam::new("a", Some(ValueType::Number)),
+                ScriptParam::new("b", Some(ValueType::List)).optional(),
+            ],
+            "return a",
+        );
+        assert_eq!(
+            source.source(),
+            "on |f|(|a|, |b|)
+if not (class of |a| is integer or class of |a| is real) then \
+error \"osakit-argument-type:a:number:\" & (class of |a| as text) number -1703
+if |b| is not missing value and not (class of |b| is list) then \
+error \"osakit-argument-type:b:list:\" & (class of |b| as text) number -1703
+return a
+end |f|
+"
+        );
+    }
+
+    #[test]
+    fn it_reports_argument_type_errors() {
+        let mut source = ScriptSource::new(Language::JavaScript, "", true);
+        source.push_function(
+            "length",
+            &[ScriptParam::new("text", Some(ValueType::String))],
+            "return text.length;",
+        );
+        let hash = hash_source(source.source());
+        let error = |message: &str| runtime(message, Some(-2700), 0);
+        let interactions = vec![
+            compile(Language::JavaScript, hash),
+            call(
+                hash,
+                "length",
+                &["a".into()],
+                Err(error(
+                    "Error: TypeError: osakit-argument-type:text:string:number",
+                )),
+            ),
+            call(
+                hash,
+                "length",
+                &["a".into()],
+                Err(error("Error: TypeError: x is undefined")),
+            ),
+        ];
+        replay(interactions, || {
+            let script = GuardTestScript::new().unwrap();
+            assert_eq!(
+                script.length("a"),
+                Err(ScriptFunctionRunError::ArgumentType {
+                    arg_name: "text".into(),
+                    expected: "string".into(),
+                    actual: "number".into(),
+                })
+            );
+            assert_eq!(
+                script.length("a"),
+                Err(ScriptFunctionRunError::Execution(error(
+                    "Error: TypeError: x is undefined"
+                )))
+            );
+        });
+    }
+
+    #[test]
+    fn it_reports_argument_type_errors_of_js_exceptions() {
+        let error =
+            ScriptFunctionRunError::Execution(ScriptExecutionError::JsException(JsException {
+                name: Some("TypeError".into()),
+                message: "osakit-argument-type:values:array:object".into(),
+                ..JsException::default()
+            }));
+        assert_eq!(
+            FunctionBodies::default().map_run_error("f", error),
+            ScriptFunctionRunError::ArgumentType {
+                arg_name: "values".into(),
+                expected: "array".into(),
+                actual: "object".into(),
+            }
+        );
+    }
+
+    #[cfg(feature = "script-macro")]
+    const CONCATENATION_SOURCE: &str = "function concatAll(x, y) { return x + y; }";
+
+    #[cfg(feature = "script-macro")]
+    #[osakit::script(language = "JavaScript", source = CONCATENATION_SOURCE, crate = "crate")]
+    trait Concatenation {
+        fn concat_all(&self, x: &str, y: &str) -> String;
+        #[handler = "concatAll"]
+        fn concat<'a>(&self, x: &'a str, y: &'a str) -> String;
+        fn concat_twice(&self, x: &str) -> Result<String, ScriptFunctionRunError> {
+            self.concat(x, x)
+        }
+    }
+
+    #[cfg(feature = "script-macro")]
+    #[test]
+    fn it_calls_script_functions() {
+        let hash = hash_source(CONCATENATION_SOURCE);
+        let concat = |x: &str, y: &str| {
+            call(
+                hash,
+                "concatAll",
+                &[Value::from(x), Value::from(y)],
+                Ok(Value::from(format!("{}{}", x, y))),
+            )
+        };
+        let interactions = vec![
+            compile(Language::JavaScript, hash),
+            concat("a", "b"),
+            concat("a", "b"),
+            concat("a", "a"),
+        ];
+        replay(interactions, || {
+            let script = ConcatenationScript::new().unwrap();
+            assert_eq!(script.concat_all("a", "b").unwrap(), "ab");
+            assert_eq!(script.concat("a", "b").unwrap(), "ab");
+            assert_eq!(script.concat_twice("a").unwrap(), "aa");
+        });
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod mock_test {
     use super::super::script::ScriptExecutionError;
     use super::ScriptFunctionRunError;
+    use std::cell::Cell;
+    use std::rc::Rc;
 
     declare_script! {
         #[language(JavaScript)]
@@ -217,40 +2487,87 @@ mod test {
             }
 
             function no_args_no_result() {}
-
-            function throws_an_error(message) {
-                throw new Error(message);
-            }
         ")]
-        pub(crate) MacroTestScript {
+        #[mock(MockTestApi)]
+        pub(crate) MockTestScript {
+            /// Concatenates strings.
             pub(crate) fn concat(x: &str, y: &str) -> String;
+            #[retry(attempts = 2)]
             pub(crate) fn no_args_no_result();
-            pub(crate) fn throws_an_error(message: &str);
         }
     }
 
+    fn concat_twice(script: &impl MockTestApi) -> Result<String, ScriptFunctionRunError> {
+        let result = script.concat("a", "b")?;
+        script.concat(&result, &result)
+    }
+
     #[test]
-    fn it_runs_concat_function() {
-        let script = MacroTestScript::new().unwrap();
-        assert_eq!(script.concat("Hello, ", "World").unwrap(), "Hello, World");
+    fn it_implements_trait_for_script() {
+        fn assert_implemented<T: MockTestApi, E>(_: fn() -> Result<T, E>) {}
+        assert_implemented(MockTestScript::new);
     }
 
     #[test]
-    fn it_runs_no_args_no_result() {
-        let script = MacroTestScript::new().unwrap();
-        assert_eq!(script.no_args_no_result().unwrap(), ());
+    fn it_returns_mocked_results() {
+        let calls = Rc::new(Cell::new(0));
+        let mut script = MockMockTestScript::new();
+        let counter = calls.clone();
+        script
+            .expect_concat()
+            .returning(move |x, y| {
+                counter.set(counter.get() + 1);
+                Ok(format!("{}{}", x, y))
+            })
+            .times(2);
+        script.expect_no_args_no_result().never();
+        assert_eq!(concat_twice(&script).unwrap(), "abab");
+        assert_eq!(calls.get(), 2);
     }
 
     #[test]
-    fn it_throws_an_error() {
-        let script = MacroTestScript::new().unwrap();
+    fn it_returns_mocked_errors() {
+        let mut script = MockMockTestScript::new();
+        script.expect_concat().returning(|_, _| {
+            Err(ScriptFunctionRunError::Execution(
+                ScriptExecutionError::Unknown,
+            ))
+        });
         assert_eq!(
-            script.throws_an_error("Test Error").unwrap_err(),
-            ScriptFunctionRunError::Execution(ScriptExecutionError::Runtime {
-                message: "Error: Error: Test Error".into(),
-                location: 0,
-                length: 0
-            })
+            concat_twice(&script),
+            Err(ScriptFunctionRunError::Execution(
+                ScriptExecutionError::Unknown
+            ))
         );
     }
+
+    #[test]
+    #[should_panic(
+        expected = "MockMockTestScript::no_args_no_result: called without an expectation"
+    )]
+    fn it_panics_without_expectation() {
+        let _ = MockMockTestScript::new().no_args_no_result();
+    }
+
+    #[test]
+    #[should_panic(expected = "MockMockTestScript::concat: expected 1 call(s), got 2")]
+    fn it_panics_on_unexpected_calls() {
+        let mut script = MockMockTestScript::new();
+        script
+            .expect_concat()
+            .returning(|x, y| Ok(format!("{}{}", x, y)))
+            .times(1);
+        let _ = concat_twice(&script);
+    }
+
+    #[test]
+    #[should_panic(expected = "MockMockTestScript::concat: expected 3 call(s), got 2")]
+    fn it_panics_on_missing_calls() {
+        let mut script = MockMockTestScript::new();
+        script
+            .expect_concat()
+            .returning(|x, y| Ok(format!("{}{}", x, y)))
+            .times(3);
+        let _ = concat_twice(&script);
+    }
 }
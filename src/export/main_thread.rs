@@ -0,0 +1,180 @@
+#[cfg(target_os = "macos")]
+use std::ffi::{c_int, c_void};
+#[cfg(target_os = "macos")]
+use std::time::Duration;
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn pthread_main_np() -> c_int;
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRunLoopGetMain() -> *mut c_void;
+    fn CFRunLoopCopyCurrentMode(run_loop: *mut c_void) -> *const c_void;
+    fn CFRelease(object: *const c_void);
+}
+
+/// Whether the current thread is the main thread of the process, regardless of its name.
+#[cfg(target_os = "macos")]
+pub(crate) fn is_main_thread() -> bool {
+    // SAFETY: `pthread_main_np` has no preconditions.
+    unsafe { pthread_main_np() != 0 }
+}
+
+/// Whether the main thread runs its run loop, i.e. using `NSApplication` or `CFRunLoopRun`.
+#[cfg(target_os = "macos")]
+fn is_main_run_loop_running() -> bool {
+    // SAFETY: the main run loop is never released, the returned mode is released after the check.
+    unsafe {
+        let mode = CFRunLoopCopyCurrentMode(CFRunLoopGetMain());
+        if mode.is_null() {
+            return false;
+        }
+        CFRelease(mode);
+        true
+    }
+}
+
+/// Time the main thread has to start a dispatched function before [`run_on_main`] gives up.
+#[cfg(target_os = "macos")]
+const MAIN_THREAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs the function on the main thread and returns its result.
+///
+/// Calls made from other threads are dispatched to the main dispatch queue and wait until the
+/// function is executed, so the main thread has to run its run loop, i.e. using `NSApplication`
+/// or `CFRunLoopRun`, instead of waiting for these threads. Returns `None` without running the
+/// function if the run loop is not running, e.g. in tests run by libtest or in command line tools,
+/// or if the main thread does not start the function within [`MAIN_THREAD_TIMEOUT`], e.g. when it
+/// stops running the run loop after the check. Once started, the function is waited for until it
+/// returns. Panics are propagated to the calling thread.
+#[cfg(target_os = "macos")]
+pub(crate) fn run_on_main<R: Send, F: FnOnce() -> R + Send>(function: F) -> Option<R> {
+    use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+    use std::sync::{Arc, Condvar, Mutex, PoisonError};
+    use std::thread::Result;
+
+    #[repr(C)]
+    struct DispatchQueue {
+        _private: [u8; 0],
+    }
+
+    extern "C" {
+        static _dispatch_main_q: DispatchQueue;
+        fn dispatch_async_f(
+            queue: *const DispatchQueue,
+            context: *mut c_void,
+            work: extern "C" fn(*mut c_void),
+        );
+    }
+
+    enum State<R, F> {
+        Pending(F),
+        Running,
+        Finished(Result<R>),
+        Cancelled,
+    }
+
+    struct Shared<R, F> {
+        state: Mutex<State<R, F>>,
+        finished: Condvar,
+    }
+
+    extern "C" fn work<R, F: FnOnce() -> R>(context: *mut c_void) {
+        // SAFETY: `context` is the reference passed to `dispatch_async_f` by `run_on_main`.
+        // Cancelled states hold neither the function nor its result, so borrows captured by them
+        // are never used after `run_on_main` returns.
+        let shared = unsafe { Arc::from_raw(context as *const Shared<R, F>) };
+        let mut state = shared.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let State::Pending(function) = std::mem::replace(&mut *state, State::Running) else {
+            *state = State::Cancelled;
+            return;
+        };
+        drop(state);
+        // Unwinding through `extern "C"` functions aborts, so the panic is resumed later.
+        let result = catch_unwind(AssertUnwindSafe(function));
+        *shared.state.lock().unwrap_or_else(PoisonError::into_inner) = State::Finished(result);
+        shared.finished.notify_one();
+    }
+
+    if is_main_thread() {
+        return Some(function());
+    }
+    if !is_main_run_loop_running() {
+        return None;
+    }
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State::Pending(function)),
+        finished: Condvar::new(),
+    });
+    // SAFETY: the dispatched reference is released by `work`.
+    unsafe {
+        dispatch_async_f(
+            &_dispatch_main_q,
+            Arc::into_raw(Arc::clone(&shared)) as *mut c_void,
+            work::<R, F>,
+        );
+    }
+    let state = shared.state.lock().unwrap_or_else(PoisonError::into_inner);
+    let (mut state, _) = shared
+        .finished
+        .wait_timeout_while(state, MAIN_THREAD_TIMEOUT, |state| {
+            matches!(state, State::Pending(_))
+        })
+        .unwrap_or_else(PoisonError::into_inner);
+    if matches!(*state, State::Pending(_)) {
+        // The function is dropped here, so it never runs after returning.
+        *state = State::Cancelled;
+        return None;
+    }
+    let mut state = shared
+        .finished
+        .wait_while(state, |state| matches!(state, State::Running))
+        .unwrap_or_else(PoisonError::into_inner);
+    match std::mem::replace(&mut *state, State::Cancelled) {
+        State::Finished(Ok(result)) => Some(result),
+        State::Finished(Err(panic)) => {
+            drop(state);
+            resume_unwind(panic)
+        }
+        State::Pending(_) | State::Running | State::Cancelled => None,
+    }
+}
+
+/// Runs the function on the current thread, scripts are only replayed on other platforms.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn run_on_main<R: Send, F: FnOnce() -> R + Send>(function: F) -> Option<R> {
+    Some(function())
+}
+
+#[cfg(test)]
+mod test {
+    use super::run_on_main;
+
+    #[test]
+    fn it_runs_functions_on_the_main_thread() {
+        assert_eq!(
+            run_on_main(|| std::thread::current().name().map(String::from)),
+            Some(Some("main".into()))
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_detects_the_main_thread() {
+        use super::is_main_thread;
+
+        assert!(is_main_thread());
+        let thread = std::thread::Builder::new().name(String::from("main"));
+        assert!(!thread.spawn(is_main_thread).unwrap().join().unwrap());
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_does_not_wait_for_the_main_thread_without_run_loop() {
+        let thread = std::thread::spawn(|| run_on_main(|| ()));
+        assert_eq!(thread.join().unwrap(), None);
+    }
+}
@@ -1,12 +1,100 @@
+pub(crate) mod batch;
+#[cfg(target_os = "macos")]
+pub(crate) mod cache;
+pub(crate) mod cassette;
+pub(crate) mod error_code;
+pub(crate) mod globals;
+pub(crate) mod handlers;
+pub(crate) mod js_exception;
+pub(crate) mod log_capture;
+#[cfg_attr(not(feature = "declare-script"), allow(dead_code))]
+pub(crate) mod main_thread;
+pub(crate) mod properties;
+#[cfg(target_os = "macos")]
+pub(crate) mod registry;
+pub(crate) mod report;
+pub(crate) mod retry;
 pub(crate) mod script;
+pub(crate) mod statistics;
+pub(crate) mod storage;
+/// Test harness running tests on the main thread, see [`crate::harness!`].
+#[cfg(feature = "testing")]
+pub mod testing;
+pub(crate) mod trace;
 pub(crate) mod value;
 
+pub use batch::BatchMode;
+#[cfg(target_os = "macos")]
+pub use cache::{ScriptCache, ScriptCacheStats};
+pub use cassette::{Cassette, CassetteError, CassetteGuard, CassetteMode, CASSETTE_MODE_VARIABLE};
+pub use error_code::OsaErrorCode;
+pub use globals::ScriptGlobalError;
+pub use js_exception::JsException;
+pub use log_capture::LogRecord;
+pub use properties::ScriptPropertiesError;
+#[cfg(target_os = "macos")]
+pub use registry::{RegistryEvent, ScriptRegistry, ScriptRegistryError};
+pub use report::ExecutionReport;
+pub use retry::RetryPolicy;
 pub use script::{Language, Script, ScriptCompilationError, ScriptExecutionError};
 pub use serde_json::Error as JsonError;
+pub use statistics::{LatencyHistogram, ScriptStatistics, StatisticsHandle};
+pub use storage::{
+    read_script_file_header, ScriptFileFormat, ScriptFileHeader, ScriptStorageError, StorageOptions,
+};
+#[cfg(feature = "testing")]
+pub use testing::test;
+#[cfg(feature = "tracing")]
+pub use trace::{redact_strings, set_value_recording, ValueRecording};
 pub use value::{from_value, to_value, Map, Number, Value};
 
 #[cfg(feature = "declare-script")]
 pub use macros::ScriptFunctionRunError;
+#[cfg(feature = "mock")]
+#[doc(hidden)]
+pub use osakit_macros::__declare_script_mock as __script_mock_impl;
+/// Successor of [`declare_script!`] generating a struct implementing a trait.
+///
+/// ## Example
+///
+/// ```no_run
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize, Debug)]
+/// pub struct Window {
+///     pub name: String,
+/// }
+///
+/// #[osakit::script(
+///     language = "JavaScript",
+///     source = "
+///         function windows(prefix) {
+///             return Application('Finder').windows().map(w => ({name: w.name()}))
+///                 .filter(w => w.name.startsWith(prefix));
+///         }
+///
+///         function frontWindowName() {
+///             return Application('Finder').windows[0].name();
+///         }
+///     "
+/// )]
+/// pub trait Finder {
+///     fn windows(&self, prefix: &str) -> Vec<Window>;
+///     /// Calls `frontWindowName`.
+///     #[retry(attempts = 3)]
+///     fn front_window_name(&self) -> String;
+///     #[handler = "frontWindowName"]
+///     fn current_window_name(&self) -> String;
+/// }
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let finder = FinderScript::new()?;
+/// println!("{:?} {}", finder.windows("Do")?, finder.front_window_name()?);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "script-macro")]
+pub use osakit_macros::script;
 /// [`declare_script!`] macro related types.
 #[cfg(feature = "declare-script")]
 pub mod macros;
@@ -0,0 +1,201 @@
+use crate::script::ScriptExecutionError;
+use crate::value::{Map, Value};
+use thiserror::Error;
+
+/// Name of the handler returning property values as a record.
+pub(crate) const GET_PROPERTIES_HANDLER: &str = "__osakit_get_properties";
+
+/// Name of the handler setting a property value by name.
+pub(crate) const SET_PROPERTY_HANDLER: &str = "__osakit_set_property";
+
+/// Error happening when saving or loading properties.
+/// Returned by [`crate::Script::save_properties`] and [`crate::Script::load_properties`].
+#[derive(Error, Debug)]
+pub enum ScriptPropertiesError {
+    #[error("properties file error")]
+    Io(#[from] std::io::Error),
+    #[error("properties file format error")]
+    Format(#[from] serde_json::Error),
+    #[error("properties execution error")]
+    Execution(#[from] ScriptExecutionError),
+}
+
+/// Returns names of the top-level properties declared in `AppleScript` source.
+/// Properties of nested `script` objects, the `parent` property and properties inside
+/// comments are skipped.
+pub(crate) fn property_names(source: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut comment_depth = 0usize;
+    let mut script_depth = 0usize;
+    for line in source.lines() {
+        let line = strip_comments(line, &mut comment_depth);
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("script"), Some(_)) => script_depth += 1,
+            (Some("end"), Some("script")) => script_depth = script_depth.saturating_sub(1),
+            (Some("property" | "prop"), Some(_)) if script_depth == 0 => {
+                if let Some(name) = parse_property_name(&line) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Removes `AppleScript` comments from a line, tracking nested block comments across lines.
+pub(crate) fn strip_comments(line: &str, comment_depth: &mut usize) -> String {
+    let mut result = String::new();
+    let mut chars = line.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if *comment_depth > 0 {
+            match (c, chars.peek()) {
+                ('(', Some('*')) => {
+                    chars.next();
+                    *comment_depth += 1;
+                }
+                ('*', Some(')')) => {
+                    chars.next();
+                    *comment_depth -= 1;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => in_string = !in_string,
+            ('\\', Some(_)) if in_string => {
+                result.push(c);
+                result.extend(chars.next());
+                continue;
+            }
+            ('(', Some('*')) if !in_string => {
+                chars.next();
+                *comment_depth += 1;
+                continue;
+            }
+            ('-', Some('-')) | ('#', _) if !in_string => break,
+            _ => {}
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn parse_property_name(line: &str) -> Option<String> {
+    let rest = line.trim_start();
+    let rest = rest
+        .strip_prefix("property")
+        .or_else(|| rest.strip_prefix("prop"))?
+        .trim_start();
+    let name = match rest.strip_prefix('|') {
+        Some(rest) => &rest[..rest.find('|')?],
+        None => rest.split([':', ' ', '\t']).next()?,
+    };
+    let is_valid = !name.is_empty()
+        && !name.eq_ignore_ascii_case("parent")
+        && !name.starts_with("__osakit")
+        && !name.contains(['"', '\\', '|']);
+    is_valid.then(|| name.to_string())
+}
+
+/// Generates `AppleScript` handlers reading and writing the specified properties.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn apple_script_properties_epilogue(names: &[String]) -> String {
+    let fields = names
+        .iter()
+        .map(|name| format!("|{0}|:|{0}|", name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let setters = names
+        .iter()
+        .map(|name| {
+            format!(
+                "    if __osakit_name is \"{0}\" then\n        set |{0}| to __osakit_value\n        return\n    end if\n",
+                name
+            )
+        })
+        .collect::<String>();
+    format!(
+        "
+on {get}()
+    return {{{fields}}}
+end {get}
+
+on {set}(__osakit_name, __osakit_value)
+{setters}    error \"Unknown property: \" & __osakit_name number -1728
+end {set}
+",
+        get = GET_PROPERTIES_HANDLER,
+        set = SET_PROPERTY_HANDLER,
+        fields = fields,
+        setters = setters,
+    )
+}
+
+/// Converts the result of [`GET_PROPERTIES_HANDLER`] call to a map.
+pub(crate) fn parse_properties(value: Value) -> Result<Map<String, Value>, ScriptExecutionError> {
+    match value {
+        Value::Object(properties) => Ok(properties),
+        _ => Err(ScriptExecutionError::Unknown),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_finds_top_level_properties() {
+        assert_eq!(
+            property_names(
+                "property counter : 0
+                prop |cached items| : {}
+                property parent : AppleScript
+                (* property commented : 1
+                   property alsoCommented: 1 *)
+                -- property lineComment : 1
+                script Nested
+                    property nested : 1
+                end script
+                on run
+                    set counter to counter + 1
+                end run
+                property lastRun:missing value"
+            ),
+            vec!["counter", "cached items", "lastRun"]
+        );
+    }
+
+    #[test]
+    fn it_ignores_comment_markers_in_strings() {
+        assert_eq!(
+            property_names("property label : \"(* -- \"\nproperty count2 : 0"),
+            vec!["label", "count2"]
+        );
+    }
+
+    #[test]
+    fn it_generates_handlers() {
+        let epilogue =
+            apple_script_properties_epilogue(&[String::from("counter"), String::from("a b")]);
+        assert!(epilogue.contains("return {|counter|:|counter|, |a b|:|a b|}"));
+        assert!(epilogue.contains("if __osakit_name is \"a b\" then\n        set |a b| to"));
+    }
+
+    #[test]
+    fn it_parses_properties() {
+        assert_eq!(
+            parse_properties(serde_json::json!({"counter": 1})),
+            Ok(Map::from_iter([(String::from("counter"), Value::from(1))]))
+        );
+        assert_eq!(
+            parse_properties(Value::Array(vec![])),
+            Err(ScriptExecutionError::Unknown)
+        );
+    }
+}
@@ -0,0 +1,432 @@
+use crate::script::{Language, Script, ScriptCompilationError, ScriptExecutionError};
+use crate::trace::hash_source;
+use crate::value::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Error happening when calling a script from a [`ScriptRegistry`].
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ScriptRegistryError {
+    /// Happens when there is no script with the specified name.
+    #[error("unknown script: `{0}`")]
+    UnknownScript(String),
+    /// Happens when the script was never compiled successfully.
+    #[error("script `{name}` failed to compile: {error}")]
+    Compilation {
+        name: String,
+        error: Box<ScriptCompilationError>,
+    },
+    #[error("execution error: {0}")]
+    Execution(#[from] ScriptExecutionError),
+}
+
+/// Change detected by [`ScriptRegistry::refresh`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryEvent {
+    /// New script was loaded.
+    Loaded(String),
+    /// Changed script was recompiled and replaced the previous version.
+    Reloaded(String),
+    /// Script file was removed.
+    Removed(String),
+    /// Script failed to compile. The last successfully compiled version, if any, is kept.
+    Failed {
+        name: String,
+        error: ScriptCompilationError,
+    },
+    /// File or directory could not be read. The last successfully compiled version of
+    /// an unreadable script, if any, is kept.
+    Unreadable { path: PathBuf, error: String },
+    /// Several files have the same name, i.e. `name.applescript` and `name.js`.
+    /// None of them is loaded until the others are removed.
+    Duplicate { name: String, paths: Vec<PathBuf> },
+}
+
+#[derive(Debug)]
+struct Entry {
+    source_hash: u64,
+    script: Option<Script>,
+    error: Option<ScriptCompilationError>,
+}
+
+/// Named scripts loaded from a directory.
+///
+/// Files with `.applescript` and `.js` extensions are loaded recursively and named by their
+/// path relative to the directory without extension, i.e. `folder/name`.
+/// The directory is polled for changes: changed files are recompiled, keeping the last
+/// successfully compiled version if the new one fails to compile. Unreadable files and
+/// duplicate names are reported as events once, until they change.
+///
+/// ## Example
+///
+/// ```no_run
+/// use osakit::{ScriptRegistry, Value};
+/// use std::time::Duration;
+///
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// let mut registry = ScriptRegistry::load("scripts")?.with_poll_interval(Duration::from_secs(1));
+/// loop {
+///     for event in registry.poll()? {
+///         println!("{:?}", event);
+///     }
+///     let value = registry.call("finder/selection", "get_selection", vec![])?;
+///     println!("{}", value);
+///     # break;
+/// }
+/// #
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ScriptRegistry {
+    directory: PathBuf,
+    poll_interval: Duration,
+    last_refresh: Instant,
+    entries: BTreeMap<String, Entry>,
+    problems: Vec<RegistryEvent>,
+}
+
+impl ScriptRegistry {
+    /// Loads and compiles all scripts from the directory.
+    /// Compilation errors are available using [`ScriptRegistry::errors`], unreadable files and
+    /// duplicate names using [`ScriptRegistry::problems`].
+    pub fn load<P: Into<PathBuf>>(directory: P) -> Result<Self, std::io::Error> {
+        let mut registry = Self {
+            directory: directory.into(),
+            poll_interval: Duration::from_secs(2),
+            last_refresh: Instant::now(),
+            entries: BTreeMap::new(),
+            problems: Vec::new(),
+        };
+        registry.refresh()?;
+        Ok(registry)
+    }
+
+    /// Sets the minimal interval between directory scans performed by [`ScriptRegistry::poll`].
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Scans the directory if the poll interval has elapsed since the previous scan.
+    pub fn poll(&mut self) -> Result<Vec<RegistryEvent>, std::io::Error> {
+        if self.last_refresh.elapsed() < self.poll_interval {
+            return Ok(Vec::new());
+        }
+        self.refresh()
+    }
+
+    /// Scans the directory and loads new and changed scripts.
+    /// Only fails if the directory itself can not be read.
+    pub fn refresh(&mut self) -> Result<Vec<RegistryEvent>, std::io::Error> {
+        self.last_refresh = Instant::now();
+        let mut files = Vec::new();
+        let mut problems = Vec::new();
+        collect_files(
+            &self.directory,
+            &mut files,
+            &mut problems,
+            &mut HashSet::new(),
+        )?;
+        let mut found: BTreeMap<String, Vec<(PathBuf, Language)>> = BTreeMap::new();
+        for path in files {
+            if let Some((name, language)) = script_name(&self.directory, &path) {
+                found.entry(name).or_default().push((path, language));
+            }
+        }
+
+        let mut sources = BTreeMap::new();
+        for (name, mut paths) in found {
+            if paths.len() > 1 {
+                let paths = paths.into_iter().map(|(path, _)| path).collect();
+                problems.push(RegistryEvent::Duplicate { name, paths });
+                continue;
+            }
+            let (path, language) = paths.remove(0);
+            // Files removed during the scan are skipped.
+            match std::fs::read_to_string(&path) {
+                Ok(source) => {
+                    sources.insert(name, Some((source, language)));
+                }
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => {
+                    let error = error.to_string();
+                    problems.push(RegistryEvent::Unreadable { path, error });
+                    sources.insert(name, None);
+                }
+            }
+        }
+
+        let mut events = Vec::new();
+        let removed: Vec<String> = self
+            .entries
+            .keys()
+            .filter(|name| !sources.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in removed {
+            self.entries.remove(&name);
+            events.push(RegistryEvent::Removed(name));
+        }
+        for (name, source) in sources {
+            if let Some((source, language)) = source {
+                events.extend(self.refresh_entry(name, &source, language));
+            }
+        }
+        for problem in &problems {
+            if !self.problems.contains(problem) {
+                events.push(problem.clone());
+            }
+        }
+        self.problems = problems;
+        Ok(events)
+    }
+
+    fn refresh_entry(
+        &mut self,
+        name: String,
+        source: &str,
+        language: Language,
+    ) -> Option<RegistryEvent> {
+        // Sources are compared by hash, since modification times may have a coarse resolution.
+        let source_hash = hash_source(source);
+        let entry = self.entries.entry(name.clone()).or_insert_with(|| Entry {
+            source_hash,
+            script: None,
+            error: None,
+        });
+        let is_new = entry.script.is_none() && entry.error.is_none();
+        if !is_new && entry.source_hash == source_hash {
+            return None;
+        }
+        entry.source_hash = source_hash;
+
+        let mut script = Script::new_from_source(language, source);
+        Some(match script.compile() {
+            Ok(()) => {
+                let had_script = entry.script.is_some();
+                entry.script = Some(script);
+                entry.error = None;
+                match had_script {
+                    true => RegistryEvent::Reloaded(name),
+                    false => RegistryEvent::Loaded(name),
+                }
+            }
+            Err(error) => {
+                entry.error = Some(error.clone());
+                RegistryEvent::Failed { name, error }
+            }
+        })
+    }
+
+    /// Calls a handler of the named script. Uses the last successfully compiled version.
+    pub fn call<I: IntoIterator<Item = Value>>(
+        &self,
+        name: &str,
+        handler: &str,
+        arguments: I,
+    ) -> Result<Value, ScriptRegistryError> {
+        Ok(self.script(name)?.execute_function(handler, arguments)?)
+    }
+
+    /// Returns the last successfully compiled version of the named script.
+    pub fn get(&self, name: &str) -> Option<&Script> {
+        self.entries
+            .get(name)
+            .and_then(|entry| entry.script.as_ref())
+    }
+
+    fn script(&self, name: &str) -> Result<&Script, ScriptRegistryError> {
+        let entry = self
+            .entries
+            .get(name)
+            .ok_or_else(|| ScriptRegistryError::UnknownScript(name.to_string()))?;
+        match (&entry.script, &entry.error) {
+            (Some(script), _) => Ok(script),
+            (None, Some(error)) => Err(ScriptRegistryError::Compilation {
+                name: name.to_string(),
+                error: Box::new(error.clone()),
+            }),
+            (None, None) => Err(ScriptRegistryError::UnknownScript(name.to_string())),
+        }
+    }
+
+    /// Names of all loaded scripts, including the ones which failed to compile.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|name| name.as_str())
+    }
+
+    /// Unreadable files and duplicate names found by the latest scan.
+    pub fn problems(&self) -> &[RegistryEvent] {
+        &self.problems
+    }
+
+    /// Compilation errors of the latest versions of scripts.
+    pub fn errors(&self) -> impl Iterator<Item = (&str, &ScriptCompilationError)> {
+        self.entries
+            .iter()
+            .filter_map(|(name, entry)| entry.error.as_ref().map(|error| (name.as_str(), error)))
+    }
+}
+
+/// Collects files of the directory recursively. Unreadable subdirectories are reported as
+/// problems, directories reached again through symbolic links are skipped.
+fn collect_files(
+    directory: &Path,
+    files: &mut Vec<PathBuf>,
+    problems: &mut Vec<RegistryEvent>,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<(), std::io::Error> {
+    if !visited.insert(directory.canonicalize()?) {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(directory)? {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(error) => {
+                let path = directory.to_path_buf();
+                let error = error.to_string();
+                problems.push(RegistryEvent::Unreadable { path, error });
+                continue;
+            }
+        };
+        if path.is_dir() {
+            match collect_files(&path, files, problems, visited) {
+                Ok(()) => {}
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => {
+                    let error = error.to_string();
+                    problems.push(RegistryEvent::Unreadable { path, error });
+                }
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the registry name and language of a script file, `None` for unsupported files.
+fn script_name(directory: &Path, path: &Path) -> Option<(String, Language)> {
+    let language = match path.extension()?.to_str()? {
+        "applescript" => Language::AppleScript,
+        "js" => Language::JavaScript,
+        _ => return None,
+    };
+    let relative = path.strip_prefix(directory).ok()?.with_extension("");
+    let name = relative
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?
+        .join("/");
+    Some((name, language))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_names_scripts_by_relative_path() {
+        let directory = Path::new("/scripts");
+        assert_eq!(
+            script_name(
+                directory,
+                Path::new("/scripts/finder/selection.applescript")
+            ),
+            Some((String::from("finder/selection"), Language::AppleScript))
+        );
+        assert_eq!(
+            script_name(directory, Path::new("/scripts/tabs.js")),
+            Some((String::from("tabs"), Language::JavaScript))
+        );
+        assert_eq!(
+            script_name(directory, Path::new("/scripts/README.md")),
+            None
+        );
+    }
+
+    #[test]
+    fn it_reloads_changed_scripts() {
+        let directory = std::env::temp_dir().join("osakit_it_reloads_changed_scripts");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(directory.join("text")).unwrap();
+        let path = directory.join("text/concat.applescript");
+        std::fs::write(&path, "on concat(x, y)\nreturn x & y\nend concat").unwrap();
+
+        let mut registry = ScriptRegistry::load(&directory).unwrap();
+        assert_eq!(registry.names().collect::<Vec<_>>(), vec!["text/concat"]);
+        let arguments = || vec![Value::from("a"), Value::from("b")];
+        assert_eq!(
+            registry.call("text/concat", "concat", arguments()),
+            Ok(Value::from("ab"))
+        );
+
+        std::fs::write(&path, "on concat(x, y)\nreturn y & x\nend concat").unwrap();
+        assert_eq!(
+            registry.refresh().unwrap(),
+            vec![RegistryEvent::Reloaded(String::from("text/concat"))]
+        );
+        assert_eq!(
+            registry.call("text/concat", "concat", arguments()),
+            Ok(Value::from("ba"))
+        );
+
+        std::fs::write(&path, "on concat(x, y)\nreturn (\nend concat").unwrap();
+        assert!(matches!(
+            registry.refresh().unwrap().as_slice(),
+            [RegistryEvent::Failed { name, .. }] if name == "text/concat"
+        ));
+        assert_eq!(registry.errors().count(), 1);
+        assert_eq!(
+            registry.call("text/concat", "concat", arguments()),
+            Ok(Value::from("ba"))
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            registry.refresh().unwrap(),
+            vec![RegistryEvent::Removed(String::from("text/concat"))]
+        );
+        assert_eq!(
+            registry.call("text/concat", "concat", arguments()),
+            Err(ScriptRegistryError::UnknownScript(String::from(
+                "text/concat"
+            )))
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn it_reports_duplicate_names_and_unreadable_files() {
+        let directory = std::env::temp_dir().join("osakit_it_reports_duplicate_names");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("answer.applescript"), "return 42").unwrap();
+        std::fs::write(directory.join("answer.js"), "42").unwrap();
+        std::fs::write(directory.join("invalid.js"), [0xff, 0xfe]).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&directory, directory.join("loop")).unwrap();
+
+        let mut registry = ScriptRegistry::load(&directory).unwrap();
+        assert_eq!(registry.names().count(), 0);
+        assert!(matches!(
+            registry.problems(),
+            [RegistryEvent::Duplicate { name, paths }, RegistryEvent::Unreadable { path, .. }]
+                if name == "answer" && paths.len() == 2 && path.ends_with("invalid.js")
+        ));
+        assert_eq!(registry.refresh().unwrap(), vec![]);
+
+        std::fs::remove_file(directory.join("answer.js")).unwrap();
+        std::fs::remove_file(directory.join("invalid.js")).unwrap();
+        assert_eq!(
+            registry.refresh().unwrap(),
+            vec![RegistryEvent::Loaded(String::from("answer"))]
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,34 @@
+use crate::log_capture::LogRecord;
+use crate::script::{Language, ScriptExecutionError};
+use crate::value::Value;
+use std::time::Duration;
+
+/// Result of a script execution together with the data collected during the execution.
+/// Returned by [`crate::Script::execute_with_report`] and
+/// [`crate::Script::execute_function_with_report`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExecutionReport {
+    /// Execution result.
+    pub value: Result<Value, ScriptExecutionError>,
+    /// Time spent executing the script.
+    pub duration: Duration,
+    /// Time spent compiling the script, `None` if the script was not compiled using
+    /// [`crate::Script::compile`].
+    pub compile_time: Option<Duration>,
+    /// Name of the executed handler, `None` for [`crate::Script::execute_with_report`].
+    pub handler: Option<String>,
+    /// Language of the executed script.
+    pub language: Language,
+    /// Lines logged during the execution. Empty unless log capturing is enabled using
+    /// [`crate::Script::with_log_capture`].
+    pub logs: Vec<LogRecord>,
+    /// Non-fatal issues detected during the execution.
+    pub warnings: Vec<String>,
+}
+
+impl ExecutionReport {
+    /// Returns the execution result, discarding the rest of the report.
+    pub fn into_result(self) -> Result<Value, ScriptExecutionError> {
+        self.value
+    }
+}
@@ -0,0 +1,277 @@
+use crate::script::ScriptExecutionError;
+use std::collections::hash_map::RandomState;
+use std::fmt::{Debug, Formatter};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+type RetryPredicate = Arc<dyn Fn(&ScriptExecutionError) -> bool + Send + Sync>;
+type Sleeper = Arc<dyn Fn(Duration) + Send + Sync>;
+
+/// Policy for retrying script executions failing with transient errors, i.e. when an application
+/// is launching or busy. Used by [`crate::Script::execute_function_with_retry`].
+///
+/// Delays between attempts grow exponentially starting from the initial delay, are limited by
+/// the maximum delay and are randomly reduced by up to `jitter` fraction of the delay.
+///
+/// ## Example
+///
+/// ```
+/// use osakit::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new(5)
+///     .with_initial_delay(Duration::from_millis(50))
+///     .with_max_delay(Duration::from_secs(1))
+///     .with_predicate(|error| error.is_retryable() || error.number() == Some(-1728));
+///
+/// assert_eq!(policy.delay_for_attempt(1), Duration::from_millis(50));
+/// assert_eq!(policy.delay_for_attempt(2), Duration::from_millis(100));
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+    jitter: f64,
+    predicate: RetryPredicate,
+    sleeper: Sleeper,
+}
+
+impl RetryPolicy {
+    /// Constructs policy making at most `max_attempts` attempts (including the first one).
+    /// By default retries errors for which [`ScriptExecutionError::is_retryable`] returns `true`.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: 0.1,
+            predicate: Arc::new(ScriptExecutionError::is_retryable),
+            sleeper: Arc::new(std::thread::sleep),
+        }
+    }
+
+    /// Sets delay before the second attempt.
+    pub fn with_initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    /// Sets maximum delay between attempts.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Sets factor by which the delay is multiplied after each attempt.
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Sets maximum fraction (from `0.0` to `1.0`) by which the delay is randomly reduced.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets predicate deciding whether an error should be retried.
+    pub fn with_predicate<F: Fn(&ScriptExecutionError) -> bool + Send + Sync + 'static>(
+        mut self,
+        predicate: F,
+    ) -> Self {
+        self.predicate = Arc::new(predicate);
+        self
+    }
+
+    /// Sets function used to wait between attempts. Defaults to [`std::thread::sleep`].
+    pub fn with_sleeper<F: Fn(Duration) + Send + Sync + 'static>(mut self, sleeper: F) -> Self {
+        self.sleeper = Arc::new(sleeper);
+        self
+    }
+
+    /// Maximum number of attempts.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Delay after the specified failed attempt (starting from `1`), without jitter applied.
+    pub fn delay_for_attempt(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        if delay.is_finite() && delay < self.max_delay.as_secs_f64() {
+            Duration::from_secs_f64(delay)
+        } else {
+            self.max_delay
+        }
+    }
+
+    /// Returns `true` if the error should be retried according to the predicate.
+    pub fn should_retry(&self, error: &ScriptExecutionError) -> bool {
+        (self.predicate)(error)
+    }
+
+    /// Runs the operation, retrying it according to the policy.
+    /// Returns the first successful result or the last error.
+    pub fn run<T, F: FnMut() -> Result<T, ScriptExecutionError>>(
+        &self,
+        mut operation: F,
+    ) -> Result<T, ScriptExecutionError> {
+        let mut attempt = 1;
+        loop {
+            match operation() {
+                Err(error) if attempt < self.max_attempts && self.should_retry(&error) => {
+                    (self.sleeper)(self.jittered_delay(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn jittered_delay(&self, attempt: u32) -> Duration {
+        let delay = self.delay_for_attempt(attempt);
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(attempt);
+        let random = (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64;
+        delay.mul_f64(1.0 - self.jitter * random)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl Debug for RetryPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .field("multiplier", &self.multiplier)
+            .field("jitter", &self.jitter)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use std::sync::Mutex;
+
+    fn runtime_error(number: i32) -> ScriptExecutionError {
+        ScriptExecutionError::Runtime {
+            message: String::from("error"),
+            location: 0,
+            length: 0,
+            number: Some(number),
+            brief_message: None,
+            app_name: None,
+            partial_result: None,
+            offending_object: None,
+            js_exception: None,
+        }
+    }
+
+    fn recording_policy(max_attempts: u32) -> (RetryPolicy, Arc<Mutex<Vec<Duration>>>) {
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let recorded_sleeps = sleeps.clone();
+        let policy = RetryPolicy::new(max_attempts)
+            .with_jitter(0.0)
+            .with_sleeper(move |duration| recorded_sleeps.lock().unwrap().push(duration));
+        (policy, sleeps)
+    }
+
+    #[test]
+    fn it_returns_first_successful_result() {
+        let (policy, sleeps) = recording_policy(3);
+        let attempts = Cell::new(0);
+        let result = policy.run(|| {
+            attempts.set(attempts.get() + 1);
+            match attempts.get() {
+                1 => Err(runtime_error(-600)),
+                _ => Ok(attempts.get()),
+            }
+        });
+        assert_eq!(result, Ok(2));
+        assert_eq!(*sleeps.lock().unwrap(), vec![Duration::from_millis(100)]);
+    }
+
+    #[test]
+    fn it_returns_last_error_after_max_attempts() {
+        let (policy, sleeps) = recording_policy(3);
+        let attempts = Cell::new(0);
+        let result: Result<(), _> = policy.run(|| {
+            attempts.set(attempts.get() + 1);
+            Err(runtime_error(-1712))
+        });
+        assert_eq!(result, Err(runtime_error(-1712)));
+        assert_eq!(attempts.get(), 3);
+        assert_eq!(
+            *sleeps.lock().unwrap(),
+            vec![Duration::from_millis(100), Duration::from_millis(200)]
+        );
+    }
+
+    #[test]
+    fn it_does_not_retry_non_retryable_errors() {
+        let (policy, sleeps) = recording_policy(3);
+        let attempts = Cell::new(0);
+        let result: Result<(), _> = policy.run(|| {
+            attempts.set(attempts.get() + 1);
+            Err(runtime_error(-1728))
+        });
+        assert_eq!(result, Err(runtime_error(-1728)));
+        assert_eq!(attempts.get(), 1);
+        assert!(sleeps.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_uses_custom_predicate() {
+        let (policy, _) = recording_policy(2);
+        let policy = policy.with_predicate(|error| error.number() == Some(-1728));
+        let attempts = Cell::new(0);
+        let _: Result<(), _> = policy.run(|| {
+            attempts.set(attempts.get() + 1);
+            Err(runtime_error(-1728))
+        });
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[test]
+    fn it_limits_delay() {
+        let policy = RetryPolicy::new(10)
+            .with_initial_delay(Duration::from_secs(1))
+            .with_max_delay(Duration::from_secs(3));
+        assert_eq!(policy.delay_for_attempt(1), Duration::from_secs(1));
+        assert_eq!(policy.delay_for_attempt(2), Duration::from_secs(2));
+        assert_eq!(policy.delay_for_attempt(3), Duration::from_secs(3));
+        assert_eq!(policy.delay_for_attempt(100), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn it_applies_jitter() {
+        let policy = RetryPolicy::new(3)
+            .with_initial_delay(Duration::from_secs(1))
+            .with_jitter(0.5);
+        for _ in 0..100 {
+            let delay = policy.jittered_delay(1);
+            assert!(delay <= Duration::from_secs(1));
+            assert!(delay >= Duration::from_millis(500));
+        }
+    }
+
+    #[test]
+    fn it_makes_at_least_one_attempt() {
+        assert_eq!(RetryPolicy::new(0).max_attempts(), 1);
+    }
+}
@@ -0,0 +1,224 @@
+use crate::error_code::OsaErrorCode;
+use crate::globals::ScriptGlobalError;
+use crate::js_exception::JsException;
+use crate::value::{ScriptInputConversionError, ScriptOutputConversionError, Value};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[cfg(target_os = "macos")]
+mod osa;
+#[cfg(not(target_os = "macos"))]
+mod replay;
+
+#[cfg(target_os = "macos")]
+pub use osa::Script;
+#[cfg(not(target_os = "macos"))]
+pub use replay::Script;
+
+/// Languages supported by `OSAKit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Language {
+    AppleScript,
+    JavaScript,
+}
+
+impl Language {
+    /// Name of the language as used by `OSAKit`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::AppleScript => "AppleScript",
+            Language::JavaScript => "JavaScript",
+        }
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "AppleScript" => Some(Language::AppleScript),
+            "JavaScript" => Some(Language::JavaScript),
+            _ => None,
+        }
+    }
+}
+
+/// Error happening during compilation. Returned by [`Script::compile`].
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScriptCompilationError {
+    #[error("unknown compilation error")]
+    Unknown,
+    #[error("compilation error: {message}")]
+    Failure {
+        message: String,
+        location: usize,
+        length: usize,
+        /// Error number reported by the language component, i.e. `-2740` for a syntax error.
+        number: Option<i32>,
+        /// Short version of the error message, if provided.
+        brief_message: Option<String>,
+        /// Name of the application which reported the error, if any.
+        app_name: Option<String>,
+        /// Partial result of the operation, if any.
+        partial_result: Option<Box<Value>>,
+        /// Object which caused the error, if any.
+        offending_object: Option<Box<Value>>,
+    },
+    /// Happens when compilation of an inline function body of a script declared using
+    /// `declare_script!` fails. The location of the error is relative to the function body.
+    #[error("in function `{name}`: {error}")]
+    Function {
+        name: String,
+        error: Box<ScriptCompilationError>,
+    },
+    /// Happens when a script shared by threads using `declare_script!` is compiled on another
+    /// thread while the main thread doesn't run its run loop.
+    #[error("the main thread is not running its run loop to compile the script")]
+    MainThread,
+}
+
+impl ScriptCompilationError {
+    /// Error number reported by the language component, if any.
+    pub fn number(&self) -> Option<i32> {
+        match self {
+            ScriptCompilationError::Failure { number, .. } => *number,
+            ScriptCompilationError::Function { error, .. } => error.number(),
+            _ => None,
+        }
+    }
+
+    /// Error code, based on the error number or the `"... (-2740)"` suffix of the message.
+    pub fn code(&self) -> Option<OsaErrorCode> {
+        match self {
+            ScriptCompilationError::Failure {
+                number: Some(number),
+                ..
+            } => Some(OsaErrorCode::from_number(*number)),
+            ScriptCompilationError::Failure { message, .. } => {
+                OsaErrorCode::from_error_text(message)
+            }
+            ScriptCompilationError::Function { error, .. } => error.code(),
+            _ => None,
+        }
+    }
+}
+
+/// Error happening during execution. Returned by [`Script::execute`] and [`Script::execute_function`].
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScriptExecutionError {
+    #[error("unknown execution error")]
+    Unknown,
+    /// Happens when an error is thrown during script execution.
+    #[error("execution error: {message}")]
+    Runtime {
+        message: String,
+        location: usize,
+        length: usize,
+        /// Error number, i.e. `-1728` for "Can’t get" or `-1743` for "Not authorized".
+        number: Option<i32>,
+        /// Short version of the error message, if provided.
+        brief_message: Option<String>,
+        /// Name of the application which reported the error, if any.
+        app_name: Option<String>,
+        /// Partial result of the operation, if any.
+        partial_result: Option<Box<Value>>,
+        /// Object which caused the error, if any.
+        /// Object specifiers which cannot be converted to [`Value`] are returned as
+        /// [`Value::String`] containing their description.
+        offending_object: Option<Box<Value>>,
+        /// Exception parsed from the error message of the top-level `JavaScript` code, if
+        /// exception capturing is enabled using [`Script::with_js_exceptions`].
+        js_exception: Option<Box<JsException>>,
+    },
+    /// Happens when a value is thrown from a `JavaScript` function and exception capturing is
+    /// enabled using [`Script::with_js_exceptions`].
+    #[error("JavaScript exception: {0}")]
+    JsException(JsException),
+    /// Happens when trying to convert execution result (`NSAppleEventDescriptor`) to [`Value`].
+    #[error("output value conversion error")]
+    OutputConversion(#[from] ScriptOutputConversionError),
+    /// Happens when trying to convert arguments to the format compatible with `OSAScript`.
+    #[error("input value conversion error")]
+    InputConversion(#[from] ScriptInputConversionError),
+    /// Happens when globals passed to [`Script::execute_with_globals`] cannot be set.
+    #[error("globals error: {0}")]
+    Globals(#[from] ScriptGlobalError),
+    /// Happens when setting a property which is not declared at the top level of the script.
+    #[error("unknown property: `{0}`")]
+    UnknownProperty(String),
+    #[error("osakit can only be used from the main thread")]
+    MainThread,
+}
+
+impl ScriptExecutionError {
+    /// Error number reported by the script, if any.
+    ///
+    /// Allows reacting on specific errors without matching localized messages:
+    ///
+    /// ```
+    /// # use osakit::ScriptExecutionError;
+    /// # fn handle(error: ScriptExecutionError) {
+    /// match error.number() {
+    ///     Some(-1728) => println!("object not found"),
+    ///     Some(-1743) => println!("not authorized"),
+    ///     _ => println!("{}", error),
+    /// }
+    /// # }
+    /// ```
+    pub fn number(&self) -> Option<i32> {
+        match self {
+            ScriptExecutionError::Runtime { number, .. } => *number,
+            ScriptExecutionError::JsException(exception) => exception.number(),
+            _ => None,
+        }
+    }
+
+    /// Error code, based on the error number or the `"... (-1728)"` suffix of the message.
+    pub fn code(&self) -> Option<OsaErrorCode> {
+        match self {
+            ScriptExecutionError::Runtime {
+                number: Some(number),
+                ..
+            } => Some(OsaErrorCode::from_number(*number)),
+            ScriptExecutionError::Runtime { message, .. } => OsaErrorCode::from_error_text(message),
+            ScriptExecutionError::JsException(exception) => {
+                exception.number().map(OsaErrorCode::from_number)
+            }
+            _ => None,
+        }
+    }
+
+    /// Captured `JavaScript` exception, either thrown from a function or parsed from the error
+    /// of the top-level code. See [`Script::with_js_exceptions`].
+    pub fn js_exception(&self) -> Option<&JsException> {
+        match self {
+            ScriptExecutionError::Runtime { js_exception, .. } => js_exception.as_deref(),
+            ScriptExecutionError::JsException(exception) => Some(exception),
+            _ => None,
+        }
+    }
+
+    /// Short name of the error kind, i.e. `"runtime"` or `"output_conversion"`.
+    /// Suitable for metrics labels and log fields.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ScriptExecutionError::Unknown => "unknown",
+            ScriptExecutionError::Runtime { .. } => "runtime",
+            ScriptExecutionError::JsException(_) => "js_exception",
+            ScriptExecutionError::OutputConversion(_) => "output_conversion",
+            ScriptExecutionError::InputConversion(_) => "input_conversion",
+            ScriptExecutionError::Globals(_) => "globals",
+            ScriptExecutionError::UnknownProperty(_) => "unknown_property",
+            ScriptExecutionError::MainThread => "main_thread",
+        }
+    }
+
+    /// Returns `true` if the error is likely transient. See [`OsaErrorCode::is_retryable`].
+    pub fn is_retryable(&self) -> bool {
+        self.code().is_some_and(|code| code.is_retryable())
+    }
+
+    /// Returns `true` if the error is caused by missing automation permissions.
+    /// See [`OsaErrorCode::is_permission_denied`].
+    pub fn is_permission_denied(&self) -> bool {
+        self.code().is_some_and(|code| code.is_permission_denied())
+    }
+}
@@ -0,0 +1,1992 @@
+use super::{Language, ScriptCompilationError, ScriptExecutionError};
+use crate::batch::{
+    apple_script_batch_epilogue, batch_arguments, parse_batch_results, BatchMode, BATCH_HANDLER,
+    JS_BATCH_EPILOGUE,
+};
+use crate::cache::ScriptCacheKey;
+use crate::cassette::{is_recording, is_replaying, record, replay, Interaction};
+use crate::globals::{
+    global_declaration, globals_epilogue, split_declarations, ScriptGlobalError, SET_GLOBAL_HANDLER,
+};
+use crate::handlers::{apple_script_positional_handlers, handler_names};
+use crate::js_exception::{JsException, JS_EXCEPTION_CALL_HANDLER, JS_EXCEPTION_HELPER};
+use crate::log_capture::{
+    parse_log_records, LogRecord, APPLE_SCRIPT_LOG_EPILOGUE, JS_LOG_EPILOGUE, JS_LOG_PRELUDE,
+    TAKE_LOGS_HANDLER,
+};
+use crate::main_thread::is_main_thread;
+use crate::properties::{
+    apple_script_properties_epilogue, parse_properties, property_names, ScriptPropertiesError,
+    GET_PROPERTIES_HANDLER, SET_PROPERTY_HANDLER,
+};
+use crate::report::ExecutionReport;
+use crate::retry::RetryPolicy;
+use crate::statistics::{ScriptStatistics, StatisticsHandle};
+use crate::storage::{
+    file_url, prelude_end_marker, LoadedSource, ScriptStorageError, StorageOptions, StoredHelpers,
+};
+use crate::trace::{hash_source, CallSpan};
+use crate::value::input::values_vec_to_ns_array;
+use crate::value::output::get_value_from_ns_apple_event_descriptor;
+use crate::value::{Map, Value};
+use objc2::{rc::Retained, runtime::AnyObject, AllocAnyThread};
+use objc2_foundation::{NSAppleEventDescriptor, NSData, NSDictionary, NSNumber, NSString, NSValue};
+use objc2_osa_kit::{
+    OSALanguage, OSALanguageInstance, OSAScript, OSAScriptErrorAppNameKey,
+    OSAScriptErrorBriefMessageKey, OSAScriptErrorMessageKey, OSAScriptErrorNumberKey,
+    OSAScriptErrorOffendingObjectKey, OSAScriptErrorPartialResultKey, OSAScriptErrorRangeKey,
+    OSAStorageOptions, OSAStorageScriptType,
+};
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use std::ops::Deref;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+fn check_main_thread() -> Result<(), ScriptExecutionError> {
+    if !is_main_thread() {
+        return Err(ScriptExecutionError::MainThread);
+    }
+    Ok(())
+}
+
+type LogCallback = Box<dyn Fn(&LogRecord)>;
+
+/// Script instance, allowing to compile and execute `AppleScript`/`JavaScript` using `OSAKit`.
+/// Uses `OSAScript` class from `OSAKit Framework` directly.
+///
+/// ## Example
+///
+/// ```
+/// use osakit::{Language, Map, Script, Value, Number};
+///
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// let mut script = Script::new_from_source(
+///     Language::AppleScript,
+///     "
+///     on is_app_running()
+///         tell application \"Hopefully Non-Existing Application\" to running
+///     end is_app_running
+///
+///     on concat(x, y)
+///         return x & y
+///     end concat
+///
+///     return {id: 21, name: \"root\"}",
+/// );
+///
+/// script.compile()?;
+///
+/// assert_eq!(
+///     script.execute()?,
+///     Value::Object(Map::from_iter(vec![
+///         ("id".into(), Value::Number(Number::from(21))),
+///         ("name".into(), Value::String("root".into()))
+///     ]))
+/// );
+///
+/// assert_eq!(
+///     script.execute_function("concat", vec![
+///         Value::String("Hello, ".into()),
+///         Value::String("World!".into())
+///     ])?,
+///     Value::String("Hello, World!".into())
+/// );
+///
+/// assert_eq!(
+///     script.execute_function("is_app_running", vec![])?,
+///     Value::Bool(false)
+/// );
+/// #
+/// # Ok(())
+/// # }
+/// ```
+pub struct Script {
+    script: Retained<OSAScript>,
+    language: Language,
+    source: String,
+    source_hash: u64,
+    prelude_length: usize,
+    compiled: bool,
+    run_only: bool,
+    compile_time: Option<Duration>,
+    js_exceptions: bool,
+    log_capture: bool,
+    log_callback: Option<LogCallback>,
+    batching: bool,
+    properties: bool,
+    globals: BTreeMap<String, String>,
+    statistics: StatisticsHandle,
+}
+
+impl Debug for Script {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Script {{ language: Language::{}, source: {:?}, compiled: {:?} }}",
+            unsafe { self.script.language().name() }
+                .map(|l| l.to_string())
+                .unwrap_or_else(|| "?".to_string()),
+            self.source,
+            self.compiled
+        )
+    }
+}
+
+struct ErrorData {
+    message: String,
+    location: usize,
+    length: usize,
+    number: Option<i32>,
+    brief_message: Option<String>,
+    app_name: Option<String>,
+    partial_result: Option<Value>,
+    offending_object: Option<Value>,
+}
+
+impl From<ErrorData> for ScriptCompilationError {
+    fn from(data: ErrorData) -> Self {
+        ScriptCompilationError::Failure {
+            message: data.message,
+            location: data.location,
+            length: data.length,
+            number: data.number,
+            brief_message: data.brief_message,
+            app_name: data.app_name,
+            partial_result: data.partial_result.map(Box::new),
+            offending_object: data.offending_object.map(Box::new),
+        }
+    }
+}
+
+impl From<ErrorData> for ScriptExecutionError {
+    fn from(data: ErrorData) -> Self {
+        ScriptExecutionError::Runtime {
+            message: data.message,
+            location: data.location,
+            length: data.length,
+            number: data.number,
+            brief_message: data.brief_message,
+            app_name: data.app_name,
+            partial_result: data.partial_result.map(Box::new),
+            offending_object: data.offending_object.map(Box::new),
+            js_exception: None,
+        }
+    }
+}
+
+#[inline]
+fn get_error_string(
+    error_dict: &NSDictionary<NSString, AnyObject>,
+    key: &NSString,
+) -> Option<String> {
+    unsafe { error_dict.valueForKey(key) }
+        .and_then(|obj| obj.downcast::<NSString>().ok())
+        .map(|ns_str| ns_str.to_string())
+}
+
+#[inline]
+fn get_error_descriptor(
+    error_dict: &NSDictionary<NSString, AnyObject>,
+    key: &NSString,
+) -> Option<Retained<NSAppleEventDescriptor>> {
+    unsafe { error_dict.valueForKey(key) }
+        .and_then(|obj| obj.downcast::<NSAppleEventDescriptor>().ok())
+}
+
+fn extract_error_data(
+    error_dict_opt: Option<Retained<NSDictionary<NSString, AnyObject>>>,
+    prelude_length: usize,
+) -> Option<ErrorData> {
+    let error_dict = error_dict_opt?;
+    let message = get_error_string(&error_dict, unsafe { OSAScriptErrorMessageKey })?;
+    let (location, length) = match unsafe { error_dict.valueForKey(OSAScriptErrorRangeKey) }
+        .map(|range| -> Retained<NSValue> { unsafe { Retained::cast_unchecked(range) } })
+        .map(|range| range.get_range())
+    {
+        Some(Some(range)) => (range.location.saturating_sub(prelude_length), range.length),
+        _ => (0, 0),
+    };
+    Some(ErrorData {
+        message,
+        location,
+        length,
+        number: unsafe { error_dict.valueForKey(OSAScriptErrorNumberKey) }
+            .and_then(|obj| obj.downcast::<NSNumber>().ok())
+            .map(|number| number.as_i32()),
+        brief_message: get_error_string(&error_dict, unsafe { OSAScriptErrorBriefMessageKey }),
+        app_name: get_error_string(&error_dict, unsafe { OSAScriptErrorAppNameKey }),
+        partial_result: get_error_descriptor(&error_dict, unsafe {
+            OSAScriptErrorPartialResultKey
+        })
+        .and_then(|descriptor| get_value_from_ns_apple_event_descriptor(descriptor).ok()),
+        offending_object: get_error_descriptor(&error_dict, unsafe {
+            OSAScriptErrorOffendingObjectKey
+        })
+        .map(|descriptor| {
+            let description = format!("{:?}", descriptor);
+            get_value_from_ns_apple_event_descriptor(descriptor)
+                .unwrap_or(Value::String(description))
+        }),
+    })
+}
+
+fn init_osa_script(language: Language, source: &str) -> Retained<OSAScript> {
+    let script_ns_string = NSString::from_str(source);
+    let script = OSAScript::alloc();
+    let ns_language_instance = get_osa_language_instance(language);
+    unsafe {
+        OSAScript::initWithSource_fromURL_languageInstance_usingStorageOptions(
+            script,
+            &script_ns_string,
+            None,
+            Some(ns_language_instance.deref()),
+            OSAStorageOptions::Null,
+        )
+    }
+}
+
+#[inline]
+fn get_osa_language_instance(language: Language) -> Retained<OSALanguageInstance> {
+    let language =
+        unsafe { OSALanguage::languageForName(&NSString::from_str(language.name())) }.unwrap();
+    unsafe { OSALanguageInstance::languageInstanceWithLanguage(language.deref()) }
+}
+
+impl Script {
+    /// Constructs Script instance using language and source code.
+    pub fn new_from_source(language: Language, source: &str) -> Self {
+        Self::with_osa_script(init_osa_script(language, source), language, source)
+    }
+
+    fn with_osa_script(script: Retained<OSAScript>, language: Language, source: &str) -> Self {
+        Self {
+            script,
+            language,
+            source: source.to_string(),
+            source_hash: hash_source(source),
+            prelude_length: 0,
+            compiled: false,
+            run_only: false,
+            compile_time: None,
+            js_exceptions: false,
+            log_capture: false,
+            log_callback: None,
+            batching: false,
+            properties: false,
+            globals: BTreeMap::new(),
+            statistics: StatisticsHandle::default(),
+        }
+    }
+
+    /// Loads a compiled script from a file written by [`Script::write_to`] or Script Editor.
+    /// Script bundles and applications are supported. Language is detected from the file.
+    ///
+    /// Helpers enabled using builder methods before writing are stored as a part of the script
+    /// and enabled again when loading it, [`Script::source`] returns the source without them.
+    /// Builder methods reinitialize the script from its source, discarding the compiled state.
+    /// Run-only scripts cannot be reinitialized, so builder methods only enable helpers which
+    /// were compiled into them.
+    pub fn new_from_compiled<P: AsRef<Path>>(path: P) -> Result<Self, ScriptStorageError> {
+        let url = file_url(path.as_ref())?;
+        let script = unsafe {
+            OSAScript::initWithContentsOfURL_languageInstance_usingStorageOptions_error(
+                OSAScript::alloc(),
+                &url,
+                None,
+                OSAStorageOptions::Null,
+            )
+        }
+        .map_err(|error| ScriptStorageError::Failure {
+            message: error.localizedDescription().to_string(),
+            number: i32::try_from(error.code()).ok(),
+        })?;
+        let language_name = unsafe { script.language().name() }
+            .map(|name| name.to_string())
+            .unwrap_or_default();
+        let language = Language::from_name(&language_name)
+            .ok_or(ScriptStorageError::UnsupportedLanguage(language_name))?;
+        let loaded_source = unsafe { script.source() }.to_string();
+        let compiled = unsafe { script.isCompiled() };
+        let Some(loaded) = LoadedSource::parse(language, &loaded_source) else {
+            let mut script = Self::with_osa_script(script, language, &loaded_source);
+            script.compiled = compiled;
+            script.run_only = compiled && loaded_source.is_empty();
+            return Ok(script);
+        };
+        let helpers = loaded.helpers;
+        let mut script = Self::with_osa_script(script, language, loaded.source);
+        script.compiled = compiled;
+        script.prelude_length = loaded.prelude.encode_utf16().count();
+        script.js_exceptions = helpers.js_exceptions;
+        script.log_capture = helpers.log_capture;
+        script.batching = helpers.batching;
+        script.properties = helpers.properties;
+        script.globals = split_declarations(language, loaded.prelude, &helpers.globals);
+        Ok(script)
+    }
+
+    /// Reinitializes the underlying `OSAScript` using the source with enabled helpers.
+    /// Error locations are adjusted by the prelude length, so they point to the original source.
+    fn rebuild(&mut self) {
+        if self.run_only {
+            return;
+        }
+        let mut prelude = self.globals.values().cloned().collect::<String>();
+        let mut epilogue = String::new();
+        if self.log_capture {
+            match self.language {
+                Language::AppleScript => epilogue.push_str(APPLE_SCRIPT_LOG_EPILOGUE),
+                Language::JavaScript => {
+                    prelude.push_str(JS_LOG_PRELUDE);
+                    epilogue.push_str(JS_LOG_EPILOGUE);
+                }
+            }
+        }
+        if self.language == Language::JavaScript && (self.js_exceptions || self.batching) {
+            epilogue.push_str(JS_EXCEPTION_HELPER);
+        }
+        if self.batching {
+            match self.language {
+                Language::AppleScript => epilogue.push_str(&apple_script_batch_epilogue(
+                    &apple_script_positional_handlers(&self.source),
+                )),
+                Language::JavaScript => epilogue.push_str(JS_BATCH_EPILOGUE),
+            }
+        }
+        if !self.globals.is_empty() {
+            epilogue.push_str(&globals_epilogue(
+                self.language,
+                self.globals.keys().map(|name| name.as_str()),
+            ));
+        }
+        if self.properties && self.language == Language::AppleScript {
+            epilogue.push_str(&apple_script_properties_epilogue(&property_names(
+                &self.source,
+            )));
+        }
+        if !prelude.is_empty() {
+            prelude.push_str(prelude_end_marker(self.language));
+        }
+        if !epilogue.is_empty() {
+            let helpers = StoredHelpers {
+                js_exceptions: self.js_exceptions,
+                log_capture: self.log_capture,
+                batching: self.batching,
+                properties: self.properties,
+                globals: self.globals.keys().cloned().collect(),
+            };
+            epilogue.insert_str(0, &helpers.marker(self.language));
+        }
+        // `OSAKit` reports error ranges in UTF-16 code units.
+        self.prelude_length = prelude.encode_utf16().count();
+        self.script = init_osa_script(
+            self.language,
+            &format!("{}{}{}", prelude, self.source, epilogue),
+        );
+        self.compiled = false;
+        self.compile_time = None;
+    }
+
+    /// Enables capturing of values thrown from `JavaScript` functions called using
+    /// [`Script::execute_function`]. Thrown values are returned as
+    /// [`ScriptExecutionError::JsException`] including error name, message, stack, line and
+    /// custom properties, instead of [`ScriptExecutionError::Runtime`] with a plain message.
+    /// Errors of the top-level code are still returned as [`ScriptExecutionError::Runtime`],
+    /// keeping the error number and location, with the parsed exception attached.
+    ///
+    /// Appends a helper function to the source, so it has to be called before
+    /// [`Script::compile`]. Has no effect on `AppleScript`.
+    ///
+    /// ```
+    /// use osakit::{Language, Script, ScriptExecutionError};
+    ///
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// let mut script = Script::new_from_source(
+    ///     Language::JavaScript,
+    ///     "function fail() { throw new TypeError('Test Error'); }",
+    /// )
+    /// .with_js_exceptions();
+    /// script.compile()?;
+    ///
+    /// match script.execute_function("fail", vec![]) {
+    ///     Err(ScriptExecutionError::JsException(exception)) => {
+    ///         assert_eq!(exception.name.as_deref(), Some("TypeError"));
+    ///         assert_eq!(exception.message, "Test Error");
+    ///     }
+    ///     result => panic!("unexpected result: {:?}", result),
+    /// }
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_js_exceptions(mut self) -> Self {
+        if self.language != Language::JavaScript || self.js_exceptions {
+            return self;
+        }
+        self.js_exceptions = true;
+        self.rebuild();
+        self
+    }
+
+    /// Enables capturing of lines logged using `log` in `AppleScript` and `console.log` in
+    /// `JavaScript`. Captured lines are returned in [`ExecutionReport::logs`] by
+    /// [`Script::execute_with_report`] and [`Script::execute_function_with_report`].
+    ///
+    /// Adds helper code to the source, so it has to be called before [`Script::compile`].
+    /// `AppleScript` `log` commands inside `tell application ... end tell` blocks are sent to the
+    /// application and are not captured, use `tell me to log ...` there.
+    ///
+    /// ```
+    /// use osakit::{Language, Script, Value};
+    ///
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// let mut script = Script::new_from_source(
+    ///     Language::AppleScript,
+    ///     "log \"Starting\"
+    ///     return 1",
+    /// )
+    /// .with_log_capture();
+    /// script.compile()?;
+    ///
+    /// let report = script.execute_with_report();
+    /// assert_eq!(report.value?, Value::from(1));
+    /// assert_eq!(report.logs[0].message, "Starting");
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_log_capture(mut self) -> Self {
+        if self.log_capture {
+            return self;
+        }
+        self.log_capture = true;
+        self.rebuild();
+        self
+    }
+
+    /// Enables batching of handler calls, so [`Script::execute_batch`] executes the whole batch
+    /// in a single `OSAKit` call using a generated dispatcher handler instead of one call
+    /// per handler.
+    ///
+    /// In case of `AppleScript` the dispatcher only calls positional handlers declared at the
+    /// top level of the source, i.e. `on name(x, y)`, other names fail with error `-1708`.
+    ///
+    /// Adds the dispatcher to the source, so it has to be called before [`Script::compile`].
+    pub fn with_batching(mut self) -> Self {
+        if self.batching {
+            return self;
+        }
+        self.batching = true;
+        self.rebuild();
+        self
+    }
+
+    /// Enables reading and writing of top-level `AppleScript` properties using
+    /// [`Script::properties`] and [`Script::set_property`]. Property values are kept between
+    /// executions of a compiled script, so they can hold counters and caches.
+    ///
+    /// Adds helper handlers to the source, so it has to be called before [`Script::compile`].
+    /// Has no effect on `JavaScript`.
+    ///
+    /// ```
+    /// use osakit::{Language, Script, Value};
+    ///
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// let mut script = Script::new_from_source(
+    ///     Language::AppleScript,
+    ///     "property counter : 0
+    ///     set counter to counter + 1",
+    /// )
+    /// .with_properties();
+    /// script.compile()?;
+    ///
+    /// script.execute()?;
+    /// script.execute()?;
+    /// assert_eq!(script.properties()?["counter"], Value::from(2));
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_properties(mut self) -> Self {
+        if self.properties {
+            return self;
+        }
+        self.properties = true;
+        self.rebuild();
+        self
+    }
+
+    /// Enables log capturing (see [`Script::with_log_capture`]) and forwards each captured line
+    /// to the callback after every execution, including calls to [`Script::execute`] and
+    /// [`Script::execute_function`].
+    pub fn with_log_callback<F: Fn(&LogRecord) + 'static>(mut self, callback: F) -> Self {
+        self.log_callback = Some(Box::new(callback));
+        self.with_log_capture()
+    }
+
+    /// Language of the script.
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    /// Stable 64-bit hash of the source code, as it was specified on construction.
+    pub fn source_hash(&self) -> u64 {
+        self.source_hash
+    }
+
+    /// Source code of the script, as it was specified on construction.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Names of the top-level handlers declared in the source: `on name(...)` and `to name(...)`
+    /// in `AppleScript`, `function name(...)` in `JavaScript`. Empty for run-only scripts.
+    pub fn handler_names(&self) -> Vec<String> {
+        handler_names(self.language, &self.source)
+    }
+
+    /// Returns `true` if the script was loaded from a run-only file and has no source.
+    pub fn is_run_only(&self) -> bool {
+        self.run_only
+    }
+
+    /// Writes the compiled script to a file. The script has to be compiled using
+    /// [`Script::compile`] first, otherwise [`ScriptStorageError::NotCompiled`] is returned.
+    ///
+    /// ```no_run
+    /// use osakit::{Language, Script, ScriptFileFormat, StorageOptions};
+    ///
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// let mut script = Script::new_from_source(Language::AppleScript, "return 1");
+    /// script.compile()?;
+    /// script.write_to(
+    ///     "script.scpt",
+    ///     StorageOptions {
+    ///         format: ScriptFileFormat::Script,
+    ///         run_only: true,
+    ///         ..Default::default()
+    ///     },
+    /// )?;
+    ///
+    /// let loaded = Script::new_from_compiled("script.scpt")?;
+    /// assert!(loaded.is_run_only());
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_to<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: StorageOptions,
+    ) -> Result<(), ScriptStorageError> {
+        if !self.compiled {
+            return Err(ScriptStorageError::NotCompiled);
+        }
+        let url = file_url(path.as_ref())?;
+        let mut error_opt: Option<Retained<NSDictionary<NSString, AnyObject>>> = None;
+        if unsafe {
+            self.script.writeToURL_ofType_usingStorageOptions_error(
+                &url,
+                options.osa_storage_type(),
+                options.osa_storage_options(),
+                Some(&mut error_opt),
+            )
+        } {
+            return Ok(());
+        }
+        match extract_error_data(error_opt, 0) {
+            None => Err(ScriptStorageError::Unknown),
+            Some(error_data) => Err(ScriptStorageError::Failure {
+                message: error_data.message,
+                number: error_data.number,
+            }),
+        }
+    }
+
+    /// Compiles previously specified source code and returns an error in case of compilation failure.
+    pub fn compile(&mut self) -> Result<(), ScriptCompilationError> {
+        if self.compiled {
+            return Ok(());
+        }
+
+        let span = CallSpan::compile(self);
+        let result = match replay(|| {
+            self.recorded_call(Interaction::compile(self.language, self.source_hash()))
+        }) {
+            Some(interaction) => {
+                let result = interaction.compile_result();
+                self.compiled = result.is_ok();
+                result
+            }
+            None => {
+                let result = self.compile_source();
+                record(|| {
+                    self.recorded_call(Interaction::compile(self.language, self.source_hash()))
+                        .with_compile_result(&result)
+                });
+                result
+            }
+        };
+        let duration = span.finish_compile(&result);
+        if result.is_ok() {
+            self.compile_time = Some(duration);
+        }
+        result
+    }
+
+    pub(crate) fn is_compiled(&self) -> bool {
+        self.compiled
+    }
+
+    /// Key identifying the compiled data of the script in [`crate::ScriptCache`].
+    pub(crate) fn cache_key(&self) -> ScriptCacheKey {
+        let mut options = format!(
+            "{}{}{}{}",
+            u8::from(self.js_exceptions),
+            u8::from(self.log_capture),
+            u8::from(self.batching),
+            u8::from(self.properties),
+        );
+        self.globals
+            .values()
+            .for_each(|declaration| options.push_str(declaration));
+        ScriptCacheKey {
+            language: self.language,
+            source: self.source.clone(),
+            options,
+        }
+    }
+
+    /// Adds the enabled helpers and declared globals to a call stored in cassettes.
+    fn recorded_call(&self, interaction: Interaction) -> Interaction {
+        interaction.with_state(
+            [
+                ("js_exceptions", self.js_exceptions),
+                ("log_capture", self.log_capture),
+                ("batching", self.batching),
+                ("properties", self.properties),
+            ],
+            self.globals.values(),
+        )
+    }
+
+    /// Returns compiled data of the script, `None` if it cannot be retrieved.
+    pub(crate) fn compiled_data(&self) -> Option<Vec<u8>> {
+        unsafe {
+            self.script.compiledDataForType_usingStorageOptions_error(
+                OSAStorageScriptType,
+                OSAStorageOptions::Null,
+                None,
+            )
+        }
+        .map(|data| data.to_vec())
+    }
+
+    /// Replaces the underlying `OSAScript` with the one loaded from compiled data.
+    /// Returns `false` if the data cannot be loaded.
+    pub(crate) fn load_compiled_data(&mut self, data: &[u8], started: Instant) -> bool {
+        let script = unsafe {
+            OSAScript::initWithCompiledData_fromURL_usingStorageOptions_error(
+                OSAScript::alloc(),
+                &NSData::with_bytes(data),
+                None,
+                OSAStorageOptions::Null,
+            )
+        };
+        match script {
+            Ok(script) => {
+                self.script = script;
+                self.compiled = true;
+                self.compile_time = Some(started.elapsed());
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn compile_source(&mut self) -> Result<(), ScriptCompilationError> {
+        let mut error_opt: Option<Retained<NSDictionary<NSString, AnyObject>>> = None;
+        if unsafe { self.script.compileAndReturnError(Some(&mut error_opt)) } {
+            self.compiled = true;
+            return Ok(());
+        }
+
+        match extract_error_data(error_opt, self.prelude_length) {
+            None => Err(ScriptCompilationError::Unknown),
+            Some(error_data) => Err(error_data.into()),
+        }
+    }
+
+    /// Binds a top-level property in case of `AppleScript` and a global variable in case of
+    /// `JavaScript`, so the value is available to the script code without being baked into the
+    /// source. Declarations are generated and prepended to the source, error locations are
+    /// still reported relative to the original source.
+    ///
+    /// `AppleScript` properties are declared as `|name|`, so the source must not declare a
+    /// property with the same name. If the script was already compiled, values of the globals
+    /// declared before are set on the compiled script, keeping its state, i.e. values of
+    /// properties. Declaring a new global recompiles the script, which resets the state: values of
+    /// properties and globals changed by previous executions are lost.
+    ///
+    /// ```
+    /// use osakit::{Language, Script, Value};
+    ///
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// let mut script = Script::new_from_source(Language::AppleScript, "return greeting & \"!\"");
+    /// script.set_global("greeting", Value::from("Hello"))?;
+    /// script.compile()?;
+    /// assert_eq!(script.execute()?, Value::from("Hello!"));
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_global(&mut self, name: &str, value: Value) -> Result<(), ScriptGlobalError> {
+        let declaration = global_declaration(self.language, name, &value)?;
+        let declared = self.globals.insert(name.to_string(), declaration).is_some();
+        if !self.compiled {
+            self.rebuild();
+            return Ok(());
+        }
+        if !declared {
+            self.rebuild();
+            return Ok(self.compile()?);
+        }
+        // Replayed scripts are not compiled, results are served from the cassette anyway.
+        if !is_replaying() {
+            self.call_handler(SET_GLOBAL_HANDLER, vec![Value::from(name), value])
+                .map_err(|error| ScriptGlobalError::Execution(Box::new(error)))?;
+        }
+        Ok(())
+    }
+
+    /// Sets the specified globals using [`Script::set_global`] and executes the script.
+    /// Globals set previously are kept.
+    pub fn execute_with_globals<I: IntoIterator<Item = (String, Value)>>(
+        &mut self,
+        globals: I,
+    ) -> Result<Value, ScriptExecutionError> {
+        for (name, value) in globals {
+            self.set_global(&name, value)?;
+        }
+        self.execute()
+    }
+
+    /// Executes script and returns the output.
+    /// In case of `AppleScript` output can be returned using `return` keyword. I.e. `return "test"`.
+    /// In case of `JavaScript` output can be returned using `output` variable. I.e. `output = "test";`.
+    pub fn execute(&self) -> Result<Value, ScriptExecutionError> {
+        self.execute_with_report().into_result()
+    }
+
+    /// Executes script the same way as [`Script::execute`] and returns the output together with
+    /// the data collected during the execution.
+    pub fn execute_with_report(&self) -> ExecutionReport {
+        let span = CallSpan::execute(self);
+        let result = match replay(|| {
+            self.recorded_call(Interaction::execute(self.language, self.source_hash()))
+        }) {
+            Some(interaction) => interaction.execute_result(),
+            None => {
+                let result = self.execute_script();
+                record(|| {
+                    self.recorded_call(Interaction::execute(self.language, self.source_hash()))
+                        .with_execute_result(&result)
+                });
+                result
+            }
+        };
+        let duration = span.finish(&result);
+        self.report(result, duration, None)
+    }
+
+    fn execute_script(&self) -> Result<Value, ScriptExecutionError> {
+        check_main_thread()?;
+        let mut error_opt: Option<Retained<NSDictionary<NSString, AnyObject>>> = None;
+        let result = unsafe { self.script.executeAndReturnError(Some(&mut error_opt)) };
+        let mut result = self.process_execution_result(result, error_opt);
+        if self.js_exceptions {
+            if let Err(ScriptExecutionError::Runtime {
+                message,
+                js_exception,
+                ..
+            }) = &mut result
+            {
+                *js_exception = JsException::from_error_message(message).map(Box::new);
+            }
+        }
+        result
+    }
+
+    fn process_execution_result(
+        &self,
+        result: Option<Retained<NSAppleEventDescriptor>>,
+        error_opt: Option<Retained<NSDictionary<NSString, AnyObject>>>,
+    ) -> Result<Value, ScriptExecutionError> {
+        match error_opt {
+            None => match result {
+                Some(event_descriptor) => {
+                    Ok(get_value_from_ns_apple_event_descriptor(event_descriptor)?)
+                }
+                None => Ok(Value::Null),
+            },
+            Some(error) => match extract_error_data(Some(error), self.prelude_length) {
+                None => Err(ScriptExecutionError::Unknown),
+                Some(error_data) => Err(error_data.into()),
+            },
+        }
+    }
+
+    /// Executes a function in case of `JavaScript` and a subroutine in case of `AppleScript`.
+    /// Specified `arguments` are passed to the function and function return value is retuned as [`Value`].
+    pub fn execute_function<I: IntoIterator<Item = Value>>(
+        &self,
+        function_name: &str,
+        arguments: I,
+    ) -> Result<Value, ScriptExecutionError> {
+        self.execute_function_with_report(function_name, arguments)
+            .into_result()
+    }
+
+    /// Executes a function the same way as [`Script::execute_function`] and returns the result
+    /// together with the data collected during the execution.
+    pub fn execute_function_with_report<I: IntoIterator<Item = Value>>(
+        &self,
+        function_name: &str,
+        arguments: I,
+    ) -> ExecutionReport {
+        let arguments: Vec<Value> = arguments.into_iter().collect();
+        let span = CallSpan::execute_function(self, function_name, &arguments);
+        let call = || {
+            self.recorded_call(Interaction::execute_function(
+                self.language,
+                self.source_hash(),
+                function_name,
+                &arguments,
+            ))
+        };
+        let result = match replay(call) {
+            Some(interaction) => interaction.execute_result(),
+            None if is_recording() => {
+                let result = self.execute_handler(function_name, arguments.clone());
+                record(|| call().with_execute_result(&result));
+                result
+            }
+            None => self.execute_handler(function_name, arguments),
+        };
+        let duration = span.finish(&result);
+        self.report(result, duration, Some(function_name))
+    }
+
+    fn report(
+        &self,
+        value: Result<Value, ScriptExecutionError>,
+        duration: Duration,
+        handler: Option<&str>,
+    ) -> ExecutionReport {
+        self.statistics.record(
+            handler,
+            duration,
+            value.as_ref().err().map(|error| error.kind()),
+        );
+        let mut warnings = Vec::new();
+        if !self.compiled {
+            warnings.push(String::from(
+                "script was not compiled using `Script::compile` before execution",
+            ));
+        }
+        let logs = match self.take_logs() {
+            Ok(logs) => logs,
+            Err(error) => {
+                warnings.push(format!("could not retrieve captured logs: {}", error));
+                Vec::new()
+            }
+        };
+        ExecutionReport {
+            value,
+            duration,
+            compile_time: self.compile_time,
+            handler: handler.map(|handler| handler.to_string()),
+            language: self.language,
+            logs,
+            warnings,
+        }
+    }
+
+    /// Retrieves lines logged since the previous call and forwards them to the log callback.
+    fn take_logs(&self) -> Result<Vec<LogRecord>, ScriptExecutionError> {
+        if !self.log_capture {
+            return Ok(Vec::new());
+        }
+        let logs = parse_log_records(
+            self.language,
+            self.call_recorded_handler(TAKE_LOGS_HANDLER, vec![])?,
+        );
+        if let Some(callback) = &self.log_callback {
+            logs.iter().for_each(callback);
+        }
+        Ok(logs)
+    }
+
+    /// Returns current values of the top-level properties declared in the source.
+    /// Always empty unless property access is enabled using [`Script::with_properties`].
+    pub fn properties(&self) -> Result<Map<String, Value>, ScriptExecutionError> {
+        if !self.properties || property_names(&self.source).is_empty() {
+            return Ok(Map::new());
+        }
+        parse_properties(self.call_recorded_handler(GET_PROPERTIES_HANDLER, vec![])?)
+    }
+
+    /// Sets value of a top-level property declared in the source.
+    /// Requires property access to be enabled using [`Script::with_properties`].
+    pub fn set_property(&self, name: &str, value: Value) -> Result<(), ScriptExecutionError> {
+        if !self.properties || !property_names(&self.source).iter().any(|n| n == name) {
+            return Err(ScriptExecutionError::UnknownProperty(name.to_string()));
+        }
+        self.call_recorded_handler(SET_PROPERTY_HANDLER, vec![Value::from(name), value])?;
+        Ok(())
+    }
+
+    /// Saves current property values (see [`Script::properties`]) to a JSON file.
+    pub fn save_properties<P: AsRef<Path>>(&self, path: P) -> Result<(), ScriptPropertiesError> {
+        let properties = Value::Object(self.properties()?);
+        std::fs::write(path, serde_json::to_vec_pretty(&properties)?)?;
+        Ok(())
+    }
+
+    /// Restores property values from a JSON file created by [`Script::save_properties`].
+    /// Values of properties which are no longer declared in the source are skipped.
+    pub fn load_properties<P: AsRef<Path>>(&self, path: P) -> Result<(), ScriptPropertiesError> {
+        let properties: Map<String, Value> = serde_json::from_slice(&std::fs::read(path)?)?;
+        let names = property_names(&self.source);
+        for (name, value) in properties {
+            if names.contains(&name) {
+                self.set_property(&name, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a copy of the aggregated execution statistics.
+    pub fn statistics(&self) -> ScriptStatistics {
+        self.statistics.snapshot()
+    }
+
+    /// Returns a thread-safe handle to the execution statistics, which can be read from other
+    /// threads, i.e. by a metrics exporter.
+    pub fn statistics_handle(&self) -> StatisticsHandle {
+        self.statistics.clone()
+    }
+
+    fn execute_handler(
+        &self,
+        function_name: &str,
+        arguments: Vec<Value>,
+    ) -> Result<Value, ScriptExecutionError> {
+        if self.js_exceptions {
+            let arguments = Value::Array(arguments);
+            return self
+                .call_handler(
+                    JS_EXCEPTION_CALL_HANDLER,
+                    vec![Value::String(function_name.into()), arguments],
+                )
+                .and_then(|result| {
+                    JsException::from_helper_result(result)
+                        .map_err(ScriptExecutionError::JsException)
+                });
+        }
+        self.call_handler(function_name, arguments)
+    }
+
+    /// Executes multiple functions and returns per-call results in the same order.
+    /// In case of [`BatchMode::StopOnFirstError`] no results are returned for the calls following
+    /// the first failed one.
+    ///
+    /// When batching is enabled using [`Script::with_batching`], the whole batch is executed in
+    /// a single `OSAKit` call. If the batch itself cannot be executed, i.e. when called outside
+    /// of the main thread, a single error is returned.
+    ///
+    /// ```
+    /// use osakit::{BatchMode, Language, Script, Value};
+    ///
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// let mut script = Script::new_from_source(
+    ///     Language::JavaScript,
+    ///     "function concat(x, y) { return x + y; }",
+    /// )
+    /// .with_batching();
+    /// script.compile()?;
+    ///
+    /// let results = script.execute_batch(
+    ///     &[
+    ///         ("concat", vec![Value::from("a"), Value::from("b")]),
+    ///         ("concat", vec![Value::from("c"), Value::from("d")]),
+    ///     ],
+    ///     BatchMode::StopOnFirstError,
+    /// );
+    /// assert_eq!(results, vec![Ok(Value::from("ab")), Ok(Value::from("cd"))]);
+    /// #
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn execute_batch(
+        &self,
+        calls: &[(&str, Vec<Value>)],
+        mode: BatchMode,
+    ) -> Vec<Result<Value, ScriptExecutionError>> {
+        if !self.batching {
+            let mut results = Vec::with_capacity(calls.len());
+            for (function_name, arguments) in calls {
+                let result = self.execute_function(function_name, arguments.clone());
+                let failed = result.is_err();
+                results.push(result);
+                if failed && mode == BatchMode::StopOnFirstError {
+                    break;
+                }
+            }
+            return results;
+        }
+        let arguments = batch_arguments(calls, mode);
+        let span = CallSpan::execute_function(self, BATCH_HANDLER, &arguments);
+        let result = self.call_recorded_handler(BATCH_HANDLER, arguments);
+        let duration = span.finish(&result);
+        match self
+            .report(result, duration, Some(BATCH_HANDLER))
+            .into_result()
+            .and_then(|results| parse_batch_results(self.language, self.js_exceptions, results))
+        {
+            Ok(results) => results,
+            Err(error) => vec![Err(error)],
+        }
+    }
+
+    /// Executes a function the same way as [`Script::execute_function`], retrying it
+    /// according to the specified [`RetryPolicy`] in case of transient failures.
+    pub fn execute_function_with_retry<I: IntoIterator<Item = Value>>(
+        &self,
+        function_name: &str,
+        arguments: I,
+        policy: &RetryPolicy,
+    ) -> Result<Value, ScriptExecutionError> {
+        let arguments: Vec<Value> = arguments.into_iter().collect();
+        policy.run(|| self.execute_function(function_name, arguments.clone()))
+    }
+
+    /// Calls a generated helper handler, recording the call and serving it from a replaying
+    /// cassette the same way as [`Script::execute_function`].
+    fn call_recorded_handler(
+        &self,
+        handler: &str,
+        arguments: Vec<Value>,
+    ) -> Result<Value, ScriptExecutionError> {
+        let call = || {
+            self.recorded_call(Interaction::execute_function(
+                self.language,
+                self.source_hash(),
+                handler,
+                &arguments,
+            ))
+        };
+        match replay(call) {
+            Some(interaction) => interaction.execute_result(),
+            None if is_recording() => {
+                let result = self.call_handler(handler, arguments.clone());
+                record(|| call().with_execute_result(&result));
+                result
+            }
+            None => self.call_handler(handler, arguments),
+        }
+    }
+
+    fn call_handler<I: IntoIterator<Item = Value>>(
+        &self,
+        function_name: &str,
+        arguments: I,
+    ) -> Result<Value, ScriptExecutionError> {
+        check_main_thread()?;
+        let mut error_opt: Option<Retained<NSDictionary<NSString, AnyObject>>> = None;
+        let ns_handler_name = NSString::from_str(function_name);
+        let ns_arguments = values_vec_to_ns_array(arguments)?;
+        let result = unsafe {
+            self.script.executeHandlerWithName_arguments_error(
+                ns_handler_name.deref(),
+                ns_arguments.deref(),
+                Some(&mut error_opt),
+            )
+        };
+        self.process_execution_result(result, error_opt)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error_code::OsaErrorCode;
+    use crate::value::{Map, Number};
+
+    macro_rules! str {
+        ($str:literal) => {
+            Value::String(String::from($str))
+        };
+    }
+
+    macro_rules! rec {
+        ($($key:ident: $value:expr,)*) => {
+            {
+                let mut map: Map<String, Value> = Map::new();
+                $(map.insert(String::from(stringify!($key)), $value);)*
+                Value::Object(map)
+            }
+        };
+    }
+
+    #[test]
+    fn it_fails_in_case_of_invalid_syntax_in_apple_script() {
+        let mut script = Script::new_from_source(Language::AppleScript, "hello world");
+        let error = script.compile().unwrap_err();
+        assert_eq!(error.number(), Some(-2740));
+        assert_eq!(error.code(), Some(OsaErrorCode::SyntaxError));
+        assert!(matches!(
+            error,
+            ScriptCompilationError::Failure {
+                message,
+                location: 0,
+                length: 11,
+                ..
+            } if message == "A identifier can’t go after this identifier."
+        ));
+    }
+
+    #[test]
+    fn it_fails_in_case_of_invalid_syntax_in_java_script() {
+        let mut script = Script::new_from_source(Language::JavaScript, "hello world");
+        assert!(matches!(
+            script.compile().unwrap_err(),
+            ScriptCompilationError::Failure {
+                message,
+                location: 0,
+                length: 11,
+                ..
+            } if message == "Error on line 1: SyntaxError: Unexpected identifier 'world'"
+        ));
+    }
+
+    #[test]
+    fn it_compiles_correct_apple_script() {
+        let mut script = Script::new_from_source(Language::AppleScript, "return 1");
+        assert_eq!(script.compile(), Ok(()));
+    }
+
+    #[test]
+    fn it_compiles_correct_java_script() {
+        let mut script = Script::new_from_source(Language::JavaScript, "output = 1;");
+        assert_eq!(script.compile(), Ok(()));
+    }
+
+    #[test]
+    fn it_fails_in_case_of_runtime_error_in_apple_script() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "tell application \"_NonExistingApplicationName_\" to launch",
+        );
+        script.compile().unwrap();
+        let error = script.execute().unwrap_err();
+        assert_eq!(error.number(), Some(-43));
+        assert_eq!(error.code(), Some(OsaErrorCode::FileNotFound));
+        assert!(matches!(
+            error,
+            ScriptExecutionError::Runtime {
+                message,
+                location: 51,
+                length: 6,
+                partial_result: None,
+                ..
+            } if message == "File _NonExistingApplicationName_ wasn’t found."
+        ));
+    }
+
+    #[test]
+    fn it_fails_in_case_of_runtime_error_in_java_script() {
+        let mut script = Script::new_from_source(Language::JavaScript, "var x = y;");
+        script.compile().unwrap();
+        assert!(matches!(
+            script.execute().unwrap_err(),
+            ScriptExecutionError::Runtime {
+                message,
+                location: 0,
+                length: 0,
+                ..
+            } if message == "Error: ReferenceError: Can't find variable: y"
+        ));
+    }
+
+    #[test]
+    fn it_returns_error_number_of_thrown_error_in_apple_script() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "error \"Custom Error\" number 1234 partial result {1, 2}",
+        );
+        script.compile().unwrap();
+        let error = script.execute().unwrap_err();
+        assert_eq!(error.number(), Some(1234));
+        assert!(matches!(
+            error,
+            ScriptExecutionError::Runtime {
+                message,
+                partial_result: Some(result),
+                ..
+            } if message == "Custom Error" && result.is_array()
+        ));
+    }
+
+    #[test]
+    fn it_returns_offending_object_in_apple_script() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "error \"Wrong value\" number -1700 from \"abc\"",
+        );
+        script.compile().unwrap();
+        let error = script.execute().unwrap_err();
+        assert_eq!(error.number(), Some(-1700));
+        assert!(matches!(
+            error,
+            ScriptExecutionError::Runtime {
+                offending_object: Some(object),
+                ..
+            } if object.as_str() == Some("abc")
+        ));
+    }
+
+    #[test]
+    fn it_returns_null_if_nothing_was_returned_in_apple_script() {
+        let mut script = Script::new_from_source(Language::AppleScript, "");
+        script.compile().unwrap();
+        assert_eq!(script.execute().unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn it_returns_null_if_nothing_was_returned_in_java_script() {
+        let mut script = Script::new_from_source(Language::JavaScript, "");
+        script.compile().unwrap();
+        assert_eq!(script.execute().unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn it_returns_calculated_string_value() {
+        let mut script = Script::new_from_source(Language::AppleScript, "return \"Hello World\"");
+        script.compile().unwrap();
+        assert_eq!(script.execute().unwrap(), str!("Hello World"));
+    }
+
+    #[test]
+    fn it_returns_complex_calculated_value() {
+        let mut script = Script::new_from_source(
+            Language::JavaScript,
+            "output = {\
+                string: \"Hello\",\
+                small_int: 3,\
+                neg_small_int: -3,\
+                big_int: 12312312,\
+                neg_big_int: -12312312,\
+                double: 5.64,\
+                bool_true: true,\
+                bool_false: false,\
+                list: [\"First\", \"Second\", \"épistèmê\"],\
+                list_empty: [],\
+                null: null,\
+                undef: undefined,\
+                nested: {\
+                    field: 55\
+                }\
+            };",
+        );
+        script.compile().unwrap();
+        assert_eq!(
+            script.execute().unwrap(),
+            rec! {
+                big_int: Value::Number(Number::from(12312312)),
+                bool_false: Value::Bool(false),
+                bool_true: Value::Bool(true),
+                double: Value::Number(Number::from_f64(5.64).unwrap()),
+                list: Value::Array(vec![
+                    str!("First"),
+                    str!("Second"),
+                    str!("épistèmê")
+                ]),
+                list_empty: Value::Array(vec![]),
+                neg_small_int: Value::Number(Number::from(-3)),
+                neg_big_int: Value::Number(Number::from(-12312312)),
+                nested: rec! {
+                    field: Value::Number(Number::from(55)),
+                },
+                null: Value::Null,
+                small_int: Value::Number(Number::from(3)),
+                string: str!("Hello"),
+                undef: Value::Null,
+            }
+        );
+    }
+
+    #[test]
+    fn it_returns_passed_arguments_in_java_script() {
+        let mut script = Script::new_from_source(
+            Language::JavaScript,
+            "function test(x, y) {\
+                return [x, y];\
+            }",
+        );
+        script.compile().unwrap();
+        assert_eq!(
+            script
+                .execute_function("test", vec![Value::Bool(true), Value::Null])
+                .unwrap(),
+            Value::Array(vec![Value::Bool(true), Value::Null])
+        );
+    }
+
+    #[test]
+    fn it_returns_passed_arguments_in_apple_script() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "on test_handler(x, y)
+                return {x, y}
+            end test_handler",
+        );
+        script.compile().unwrap();
+        assert_eq!(
+            script
+                .execute_function("test_handler", vec![Value::Bool(true), Value::Null])
+                .unwrap(),
+            Value::Array(vec![Value::Bool(true), Value::Null])
+        );
+    }
+
+    #[test]
+    fn it_captures_thrown_errors_in_java_script() {
+        let mut script = Script::new_from_source(
+            Language::JavaScript,
+            "function fail(message) {
+                var error = new TypeError(message);
+                error.code = 42;
+                throw error;
+            }",
+        )
+        .with_js_exceptions();
+        script.compile().unwrap();
+        match script.execute_function("fail", vec![str!("Test Error")]) {
+            Err(ScriptExecutionError::JsException(exception)) => {
+                assert_eq!(exception.name.as_deref(), Some("TypeError"));
+                assert_eq!(exception.message, "Test Error");
+                assert_eq!(
+                    exception.value,
+                    Some(rec! {
+                        code: Value::Number(Number::from(42)),
+                    })
+                );
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_captures_thrown_values_in_java_script() {
+        let mut script = Script::new_from_source(
+            Language::JavaScript,
+            "function fail() { throw {reason: \"custom\"}; }",
+        )
+        .with_js_exceptions();
+        script.compile().unwrap();
+        match script.execute_function("fail", vec![]) {
+            Err(ScriptExecutionError::JsException(exception)) => {
+                assert_eq!(exception.name, None);
+                assert_eq!(
+                    exception.value,
+                    Some(rec! {
+                        reason: str!("custom"),
+                    })
+                );
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_returns_results_when_capturing_exceptions_in_java_script() {
+        let mut script = Script::new_from_source(
+            Language::JavaScript,
+            "function test(x, y) {\
+                return [x, y];\
+            }",
+        )
+        .with_js_exceptions();
+        script.compile().unwrap();
+        assert_eq!(
+            script
+                .execute_function("test", vec![Value::Bool(true), Value::Null])
+                .unwrap(),
+            Value::Array(vec![Value::Bool(true), Value::Null])
+        );
+    }
+
+    #[test]
+    fn it_parses_top_level_errors_when_capturing_exceptions_in_java_script() {
+        let mut script =
+            Script::new_from_source(Language::JavaScript, "var x = y;").with_js_exceptions();
+        script.compile().unwrap();
+        let error = script.execute().unwrap_err();
+        assert!(matches!(
+            error,
+            ScriptExecutionError::Runtime {
+                ref message,
+                location: 0,
+                ..
+            } if message == "Error: ReferenceError: Can't find variable: y"
+        ));
+        let exception = error.js_exception().unwrap();
+        assert_eq!(exception.name.as_deref(), Some("ReferenceError"));
+        assert_eq!(exception.message, "Can't find variable: y");
+    }
+
+    #[test]
+    fn it_captures_thrown_errors_in_strict_java_script() {
+        let mut script = Script::new_from_source(
+            Language::JavaScript,
+            "'use strict';
+            function answer() { return 42; }
+            function fail() { throw new RangeError('Test Error'); }",
+        )
+        .with_js_exceptions();
+        script.compile().unwrap();
+        assert_eq!(
+            script.execute_function("answer", vec![]),
+            Ok(Value::from(42))
+        );
+        assert!(matches!(
+            script.execute_function("fail", vec![]),
+            Err(ScriptExecutionError::JsException(exception))
+                if exception.name.as_deref() == Some("RangeError")
+        ));
+    }
+
+    #[test]
+    fn it_hides_exception_helper_from_source() {
+        let script =
+            Script::new_from_source(Language::JavaScript, "output = 1;").with_js_exceptions();
+        assert_eq!(script.source(), "output = 1;");
+    }
+
+    #[test]
+    fn it_captures_logs_in_apple_script() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "on test_handler(x)
+                log \"Hello, \" & x
+                return x
+            end test_handler",
+        )
+        .with_log_capture();
+        script.compile().unwrap();
+        let report = script.execute_function_with_report("test_handler", vec![str!("World")]);
+        assert_eq!(report.value, Ok(str!("World")));
+        assert_eq!(report.logs.len(), 1);
+        assert_eq!(report.logs[0].message, "Hello, World");
+        assert_eq!(report.logs[0].language, Language::AppleScript);
+        let second_report = script.execute_function_with_report("test_handler", vec![str!("!")]);
+        assert_eq!(second_report.logs.len(), 1);
+        assert_eq!(second_report.logs[0].message, "Hello, !");
+    }
+
+    #[test]
+    fn it_captures_logs_in_java_script() {
+        let mut script = Script::new_from_source(
+            Language::JavaScript,
+            "console.log(\"Hello\", 1, {x: true});
+            output = 1;",
+        )
+        .with_log_capture();
+        script.compile().unwrap();
+        let report = script.execute_with_report();
+        assert_eq!(report.value, Ok(Value::Number(Number::from(1))));
+        assert_eq!(
+            report
+                .logs
+                .iter()
+                .map(|record| record.message.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Hello 1 {\"x\":true}"]
+        );
+    }
+
+    #[test]
+    fn it_forwards_logs_to_callback() {
+        let messages = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let collected_messages = messages.clone();
+        let mut script = Script::new_from_source(Language::AppleScript, "log \"Test\"")
+            .with_log_callback(move |record| {
+                collected_messages.borrow_mut().push(record.message.clone())
+            });
+        script.compile().unwrap();
+        script.execute().unwrap();
+        assert_eq!(*messages.borrow(), vec![String::from("Test")]);
+    }
+
+    #[test]
+    fn it_adjusts_error_location_when_capturing_logs() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "tell application \"_NonExistingApplicationName_\" to launch",
+        )
+        .with_log_capture();
+        script.compile().unwrap();
+        assert!(matches!(
+            script.execute().unwrap_err(),
+            ScriptExecutionError::Runtime {
+                location: 51,
+                length: 6,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn it_reports_execution_metadata() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "on test_handler(x)
+                return x
+            end test_handler",
+        );
+        script.compile().unwrap();
+        let report = script.execute_function_with_report("test_handler", vec![str!("x")]);
+        assert_eq!(report.value, Ok(str!("x")));
+        assert_eq!(report.handler.as_deref(), Some("test_handler"));
+        assert_eq!(report.language, Language::AppleScript);
+        assert!(report.compile_time.is_some());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn it_warns_about_execution_without_compilation() {
+        let script = Script::new_from_source(Language::AppleScript, "return 1");
+        let report = script.execute_with_report();
+        assert_eq!(report.compile_time, None);
+        assert_eq!(report.handler, None);
+        assert_eq!(report.warnings.len(), 1);
+    }
+
+    #[test]
+    fn it_collects_statistics() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "on test_handler(x)
+                return x
+            end test_handler",
+        );
+        script.compile().unwrap();
+        let handle = script.statistics_handle();
+        script.execute().unwrap();
+        script
+            .execute_function("test_handler", vec![str!("x")])
+            .unwrap();
+        script.execute_function("missing", vec![]).unwrap_err();
+        let statistics = handle.snapshot();
+        assert_eq!(statistics.calls, 3);
+        assert_eq!(statistics.calls_by_handler.get("test_handler"), Some(&1));
+        assert_eq!(statistics.errors, 1);
+        assert_eq!(statistics.errors_by_kind.get("runtime"), Some(&1));
+        assert_eq!(statistics.latency.count(), 3);
+        assert_eq!(script.statistics(), statistics);
+    }
+
+    #[test]
+    fn it_executes_batch_in_apple_script() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "on concat(x, y)
+                return x & y
+            end concat
+            on fail()
+                error \"Failure\" number 1234
+            end fail
+            on no_result()
+            end no_result",
+        )
+        .with_batching();
+        script.compile().unwrap();
+        let results = script.execute_batch(
+            &[
+                ("concat", vec![str!("a"), str!("b")]),
+                ("no_result", vec![]),
+                ("fail", vec![]),
+                ("concat", vec![str!("c"), str!("d")]),
+            ],
+            BatchMode::ContinueOnError,
+        );
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0], Ok(str!("ab")));
+        assert_eq!(results[1], Ok(Value::Null));
+        assert_eq!(results[2].as_ref().unwrap_err().number(), Some(1234));
+        assert_eq!(results[3], Ok(str!("cd")));
+        assert_eq!(script.statistics().calls, 1);
+    }
+
+    #[test]
+    fn it_does_not_evaluate_handler_names_in_apple_script_batches() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "on concat(x, y)
+                return x & y
+            end concat",
+        )
+        .with_batching();
+        script.compile().unwrap();
+        let results = script.execute_batch(
+            &[
+                ("concat(\"a\", \"b\") & \"c\"", vec![]),
+                ("Concat", vec![str!("a"), str!("b")]),
+                ("concat", vec![str!("a")]),
+            ],
+            BatchMode::ContinueOnError,
+        );
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.as_ref().unwrap_err().number())
+                .collect::<Vec<_>>(),
+            vec![Some(-1708), Some(-1708), Some(-1721)]
+        );
+    }
+
+    #[test]
+    fn it_executes_batch_in_java_script() {
+        let mut script = Script::new_from_source(
+            Language::JavaScript,
+            "function concat(x, y) { return x + y; }
+            function fail() { throw new Error(\"Failure\"); }",
+        )
+        .with_batching();
+        script.compile().unwrap();
+        let results = script.execute_batch(
+            &[
+                ("concat", vec![str!("a"), str!("b")]),
+                ("fail", vec![]),
+                ("concat", vec![str!("c"), str!("d")]),
+            ],
+            BatchMode::StopOnFirstError,
+        );
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0], Ok(str!("ab")));
+        assert!(matches!(
+            &results[1],
+            Err(ScriptExecutionError::Runtime { message, .. }) if message == "Error: Error: Failure"
+        ));
+    }
+
+    #[test]
+    fn it_executes_batch_without_dispatcher() {
+        let mut script = Script::new_from_source(
+            Language::JavaScript,
+            "function concat(x, y) { return x + y; }",
+        );
+        script.compile().unwrap();
+        let results = script.execute_batch(
+            &[
+                ("concat", vec![str!("a"), str!("b")]),
+                ("missing", vec![]),
+                ("concat", vec![str!("c"), str!("d")]),
+            ],
+            BatchMode::StopOnFirstError,
+        );
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0], Ok(str!("ab")));
+        assert!(results[1].is_err());
+        assert_eq!(script.statistics().calls, 2);
+    }
+
+    #[test]
+    fn it_sets_globals_in_apple_script() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "return (|name| of config) & \" \" & (count of |items| of config)",
+        );
+        script.compile().unwrap();
+        script
+            .set_global(
+                "config",
+                serde_json::json!({"name": "with \"quotes\"", "items": [1, 2, 3]}),
+            )
+            .unwrap();
+        assert!(script.compiled);
+        assert_eq!(script.execute(), Ok(str!("with \"quotes\" 3")));
+    }
+
+    #[test]
+    fn it_sets_declared_globals_without_recompiling() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "property counter : 0
+            set counter to counter + step
+            return counter",
+        );
+        script.set_global("step", Value::from(1)).unwrap();
+        script.compile().unwrap();
+        let compile_time = script.compile_time;
+        assert_eq!(script.execute(), Ok(Value::from(1)));
+        script.set_global("step", Value::from(10)).unwrap();
+        assert_eq!(script.compile_time, compile_time);
+        assert_eq!(script.execute(), Ok(Value::from(11)));
+    }
+
+    #[test]
+    fn it_resets_state_when_declaring_new_globals_after_compiling() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "property counter : 0
+            set counter to counter + step
+            return counter",
+        );
+        script.set_global("step", Value::from(1)).unwrap();
+        script.compile().unwrap();
+        assert_eq!(script.execute(), Ok(Value::from(1)));
+        assert_eq!(script.execute(), Ok(Value::from(2)));
+        script.set_global("unused", Value::from(0)).unwrap();
+        assert!(script.compiled);
+        assert_eq!(script.execute(), Ok(Value::from(1)));
+    }
+
+    #[test]
+    fn it_keeps_set_globals_in_java_script() {
+        let mut script = Script::new_from_source(
+            Language::JavaScript,
+            "function get() { return value; }
+            output = value;",
+        );
+        script.set_global("value", Value::from(1)).unwrap();
+        script.compile().unwrap();
+        assert_eq!(script.execute(), Ok(Value::from(1)));
+        script.set_global("value", Value::from(2)).unwrap();
+        assert_eq!(script.execute_function("get", vec![]), Ok(Value::from(2)));
+        assert_eq!(script.execute(), Ok(Value::from(2)));
+    }
+
+    #[test]
+    fn it_sets_globals_in_java_script() {
+        let mut script = Script::new_from_source(Language::JavaScript, "output = x + y.length;");
+        assert_eq!(
+            script.execute_with_globals([
+                (String::from("x"), Value::from(2)),
+                (String::from("y"), serde_json::json!(["a\nb", "c"])),
+            ]),
+            Ok(Value::from(4))
+        );
+    }
+
+    #[test]
+    fn it_adjusts_error_locations_when_globals_are_set() {
+        let mut script = Script::new_from_source(Language::AppleScript, "error \"Failure\"");
+        script.set_global("value", str!("abc")).unwrap();
+        assert!(matches!(
+            script.execute(),
+            Err(ScriptExecutionError::Runtime { location: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn it_rejects_invalid_global_names() {
+        let mut script = Script::new_from_source(Language::JavaScript, "output = 1;");
+        assert_eq!(
+            script.set_global("a; b", Value::Null),
+            Err(ScriptGlobalError::InvalidName(String::from("a; b")))
+        );
+    }
+
+    #[test]
+    fn it_reads_and_writes_properties() {
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "property counter : 0
+            property |last value| : missing value
+            set counter to counter + 1
+            set |last value| to \"run \" & counter",
+        )
+        .with_properties();
+        script.compile().unwrap();
+        script.execute().unwrap();
+        script.execute().unwrap();
+        assert_eq!(
+            script.properties(),
+            Ok(Map::from_iter([
+                (String::from("counter"), Value::from(2)),
+                (String::from("last value"), str!("run 2")),
+            ]))
+        );
+        script.set_property("counter", Value::from(10)).unwrap();
+        script.execute().unwrap();
+        assert_eq!(script.properties().unwrap()["counter"], Value::from(11));
+        assert_eq!(
+            script.set_property("missing", Value::Null),
+            Err(ScriptExecutionError::UnknownProperty(String::from(
+                "missing"
+            )))
+        );
+    }
+
+    #[test]
+    fn it_saves_and_loads_properties() {
+        let source = "property counter : 0
+            set counter to counter + 1";
+        let path = std::env::temp_dir().join("osakit_it_saves_and_loads_properties.json");
+        let mut script = Script::new_from_source(Language::AppleScript, source).with_properties();
+        script.compile().unwrap();
+        script.execute().unwrap();
+        script.execute().unwrap();
+        script.save_properties(&path).unwrap();
+
+        let mut restored = Script::new_from_source(Language::AppleScript, source).with_properties();
+        restored.compile().unwrap();
+        restored.load_properties(&path).unwrap();
+        restored.execute().unwrap();
+        assert_eq!(restored.properties().unwrap()["counter"], Value::from(3));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_writes_and_loads_compiled_scripts() {
+        let path = std::env::temp_dir().join("osakit_it_writes_and_loads_compiled_scripts.scpt");
+        let mut script = Script::new_from_source(
+            Language::AppleScript,
+            "on concat(x, y)
+                return x & y
+            end concat",
+        );
+        script.compile().unwrap();
+        script.write_to(&path, StorageOptions::default()).unwrap();
+
+        let header = crate::read_script_file_header(&path).unwrap();
+        assert_eq!(header.language, Language::AppleScript);
+        assert_eq!(header.run_only, Some(false));
+
+        let loaded = Script::new_from_compiled(&path).unwrap();
+        assert_eq!(loaded.language(), Language::AppleScript);
+        assert!(!loaded.is_run_only());
+        assert_eq!(
+            loaded.execute_function("concat", vec![str!("a"), str!("b")]),
+            Ok(str!("ab"))
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_restores_helpers_of_compiled_scripts() {
+        let path = std::env::temp_dir().join("osakit_it_restores_helpers_of_compiled_scripts.scpt");
+        let mut script = Script::new_from_source(
+            Language::JavaScript,
+            "function fail() { throw new TypeError('Test Error'); }
+            var x = y;",
+        )
+        .with_js_exceptions();
+        script.set_global("value", Value::from(1)).unwrap();
+        script.compile().unwrap();
+        script.write_to(&path, StorageOptions::default()).unwrap();
+
+        let loaded = Script::new_from_compiled(&path).unwrap();
+        assert_eq!(loaded.source(), script.source());
+        assert!(!loaded.is_run_only());
+        assert!(matches!(
+            loaded.execute_function("fail", vec![]),
+            Err(ScriptExecutionError::JsException(exception))
+                if exception.name.as_deref() == Some("TypeError")
+        ));
+        assert!(matches!(
+            loaded.execute(),
+            Err(ScriptExecutionError::Runtime { location: 0, .. })
+        ));
+        assert_eq!(loaded.globals.keys().collect::<Vec<_>>(), vec!["value"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_does_not_write_scripts_without_compiling() {
+        let script = Script::new_from_source(Language::AppleScript, "return 1");
+        assert!(matches!(
+            script.write_to("/nonexistent/osakit.scpt", StorageOptions::default()),
+            Err(ScriptStorageError::NotCompiled)
+        ));
+    }
+
+    #[test]
+    fn it_does_not_consider_empty_scripts_run_only() {
+        let mut script = Script::new_from_source(Language::AppleScript, "");
+        script.compile().unwrap();
+        assert!(!script.is_run_only());
+    }
+
+    #[test]
+    fn it_writes_run_only_scripts() {
+        let path = std::env::temp_dir().join("osakit_it_writes_run_only_scripts.scpt");
+        let mut script =
+            Script::new_from_source(Language::JavaScript, "function answer() { return 42; }");
+        script.compile().unwrap();
+        script
+            .write_to(
+                &path,
+                StorageOptions {
+                    run_only: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let header = crate::read_script_file_header(&path).unwrap();
+        assert_eq!(header.run_only, Some(true));
+        let loaded = Script::new_from_compiled(&path).unwrap();
+        assert_eq!(loaded.language(), Language::JavaScript);
+        assert!(loaded.is_run_only());
+        assert_eq!(
+            loaded.execute_function("answer", vec![]),
+            Ok(Value::from(42))
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_fails_to_load_missing_files() {
+        assert!(matches!(
+            Script::new_from_compiled("/nonexistent/osakit.scpt"),
+            Err(ScriptStorageError::Failure { .. })
+        ));
+    }
+
+    #[test]
+    fn it_lists_handlers() {
+        let script = Script::new_from_source(
+            Language::JavaScript,
+            "function concat(x, y) { return x + y; }\nfunction answer() { return 42; }",
+        )
+        .with_js_exceptions();
+        assert_eq!(script.handler_names(), vec!["concat", "answer"]);
+    }
+
+    #[test]
+    fn it_supports_debug() {
+        let script = Script::new_from_source(Language::AppleScript, "return 123");
+        assert_eq!(
+            format!("{:?}", script),
+            "Script { language: Language::AppleScript, source: \"return 123\", compiled: false }"
+        );
+    }
+}
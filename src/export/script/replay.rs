@@ -0,0 +1,507 @@
+use super::{Language, ScriptCompilationError, ScriptExecutionError};
+use crate::batch::{batch_arguments, parse_batch_results, BatchMode, BATCH_HANDLER};
+use crate::cassette::{replay, Interaction};
+use crate::globals::{global_declaration, ScriptGlobalError};
+use crate::handlers::handler_names;
+use crate::log_capture::{parse_log_records, LogRecord, TAKE_LOGS_HANDLER};
+use crate::properties::{
+    parse_properties, property_names, ScriptPropertiesError, GET_PROPERTIES_HANDLER,
+    SET_PROPERTY_HANDLER,
+};
+use crate::report::ExecutionReport;
+use crate::retry::RetryPolicy;
+use crate::statistics::{ScriptStatistics, StatisticsHandle};
+use crate::storage::{ScriptStorageError, StorageOptions};
+use crate::trace::{hash_source, CallSpan};
+use crate::value::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use std::path::Path;
+use std::time::Duration;
+
+/// Script instance serving results from a replaying [`crate::Cassette`].
+///
+/// Scripts can only be executed on macOS, on other platforms calls are served from the
+/// cassette inserted for the current thread and panic if there is none. Calls made by helpers,
+/// i.e. retrieving captured logs, property access and batches, are replayed as well.
+/// Loading and writing compiled scripts return [`ScriptStorageError::Unsupported`].
+pub struct Script {
+    language: Language,
+    source: String,
+    source_hash: u64,
+    compiled: bool,
+    compile_time: Option<Duration>,
+    js_exceptions: bool,
+    log_capture: bool,
+    batching: bool,
+    properties: bool,
+    globals: BTreeMap<String, String>,
+    statistics: StatisticsHandle,
+}
+
+impl Debug for Script {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Script {{ language: Language::{}, source: {:?}, compiled: {:?} }}",
+            self.language.name(),
+            self.source,
+            self.compiled
+        )
+    }
+}
+
+fn replayed<F: FnOnce() -> Interaction>(call: F) -> Interaction {
+    replay(call)
+        .unwrap_or_else(|| panic!("osakit can only execute scripts on macOS or from a cassette"))
+}
+
+impl Script {
+    /// Constructs Script instance using language and source code.
+    pub fn new_from_source(language: Language, source: &str) -> Self {
+        Self {
+            language,
+            source: source.to_string(),
+            source_hash: hash_source(source),
+            compiled: false,
+            compile_time: None,
+            js_exceptions: false,
+            log_capture: false,
+            batching: false,
+            properties: false,
+            globals: BTreeMap::new(),
+            statistics: StatisticsHandle::default(),
+        }
+    }
+
+    /// Compiled scripts can only be loaded on macOS, always returns
+    /// [`ScriptStorageError::Unsupported`].
+    pub fn new_from_compiled<P: AsRef<Path>>(_path: P) -> Result<Self, ScriptStorageError> {
+        Err(ScriptStorageError::Unsupported)
+    }
+
+    /// Recorded results already contain captured exceptions, only results of batches depend
+    /// on it.
+    pub fn with_js_exceptions(mut self) -> Self {
+        self.js_exceptions = self.language == Language::JavaScript;
+        self
+    }
+
+    /// Serves captured lines recorded after each execution in reports.
+    pub fn with_log_capture(mut self) -> Self {
+        self.log_capture = true;
+        self
+    }
+
+    /// Serves batches recorded as a single call, see [`Script::execute_batch`].
+    pub fn with_batching(mut self) -> Self {
+        self.batching = true;
+        self
+    }
+
+    /// Enables replaying of property access. See [`Script::properties`].
+    pub fn with_properties(mut self) -> Self {
+        self.properties = true;
+        self
+    }
+
+    /// Enables log capturing, the callback is not called when replaying.
+    pub fn with_log_callback<F: Fn(&LogRecord) + 'static>(self, _callback: F) -> Self {
+        self.with_log_capture()
+    }
+
+    /// Language of the script.
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    /// Stable 64-bit hash of the source code, as it was specified on construction.
+    pub fn source_hash(&self) -> u64 {
+        self.source_hash
+    }
+
+    /// Source code of the script, as it was specified on construction.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Names of the top-level handlers declared in the source: `on name(...)` and `to name(...)`
+    /// in `AppleScript`, `function name(...)` in `JavaScript`.
+    pub fn handler_names(&self) -> Vec<String> {
+        handler_names(self.language, &self.source)
+    }
+
+    /// Always `false`, since compiled scripts cannot be loaded.
+    pub fn is_run_only(&self) -> bool {
+        false
+    }
+
+    /// Compiled scripts can only be written on macOS. Returns
+    /// [`ScriptStorageError::NotCompiled`] if the script was not compiled and
+    /// [`ScriptStorageError::Unsupported`] otherwise.
+    pub fn write_to<P: AsRef<Path>>(
+        &self,
+        _path: P,
+        _options: StorageOptions,
+    ) -> Result<(), ScriptStorageError> {
+        match self.compiled {
+            true => Err(ScriptStorageError::Unsupported),
+            false => Err(ScriptStorageError::NotCompiled),
+        }
+    }
+
+    /// Serves the recorded compilation result.
+    pub fn compile(&mut self) -> Result<(), ScriptCompilationError> {
+        if self.compiled {
+            return Ok(());
+        }
+
+        let span = CallSpan::compile(self);
+        let result = replayed(|| {
+            self.recorded_call(Interaction::compile(self.language, self.source_hash()))
+        })
+        .compile_result();
+        let duration = span.finish_compile(&result);
+        self.compiled = result.is_ok();
+        if result.is_ok() {
+            self.compile_time = Some(duration);
+        }
+        result
+    }
+
+    /// Validates the global, recorded results are served for calls made with the same values
+    /// of the globals. Declaring a new global after compiling serves the recorded compilation
+    /// again, the same way the script is recompiled on macOS.
+    pub fn set_global(&mut self, name: &str, value: Value) -> Result<(), ScriptGlobalError> {
+        let declaration = global_declaration(self.language, name, &value)?;
+        let declared = self.globals.insert(name.to_string(), declaration).is_some();
+        if self.compiled && !declared {
+            self.compiled = false;
+            return Ok(self.compile()?);
+        }
+        Ok(())
+    }
+
+    /// Sets the specified globals using [`Script::set_global`] and serves the recorded
+    /// execution result.
+    pub fn execute_with_globals<I: IntoIterator<Item = (String, Value)>>(
+        &mut self,
+        globals: I,
+    ) -> Result<Value, ScriptExecutionError> {
+        for (name, value) in globals {
+            self.set_global(&name, value)?;
+        }
+        self.execute()
+    }
+
+    /// Serves the recorded execution result.
+    pub fn execute(&self) -> Result<Value, ScriptExecutionError> {
+        self.execute_with_report().into_result()
+    }
+
+    /// Serves the recorded execution result the same way as [`Script::execute`] together with
+    /// the replay duration.
+    pub fn execute_with_report(&self) -> ExecutionReport {
+        let span = CallSpan::execute(self);
+        let result = replayed(|| {
+            self.recorded_call(Interaction::execute(self.language, self.source_hash()))
+        })
+        .execute_result();
+        let duration = span.finish(&result);
+        self.report(result, duration, None)
+    }
+
+    /// Serves the recorded result of the function call.
+    pub fn execute_function<I: IntoIterator<Item = Value>>(
+        &self,
+        function_name: &str,
+        arguments: I,
+    ) -> Result<Value, ScriptExecutionError> {
+        self.execute_function_with_report(function_name, arguments)
+            .into_result()
+    }
+
+    /// Serves the recorded result of the function call the same way as
+    /// [`Script::execute_function`] together with the replay duration.
+    pub fn execute_function_with_report<I: IntoIterator<Item = Value>>(
+        &self,
+        function_name: &str,
+        arguments: I,
+    ) -> ExecutionReport {
+        let arguments: Vec<Value> = arguments.into_iter().collect();
+        let span = CallSpan::execute_function(self, function_name, &arguments);
+        let result = replayed(|| {
+            self.recorded_call(Interaction::execute_function(
+                self.language,
+                self.source_hash(),
+                function_name,
+                &arguments,
+            ))
+        })
+        .execute_result();
+        let duration = span.finish(&result);
+        self.report(result, duration, Some(function_name))
+    }
+
+    fn report(
+        &self,
+        value: Result<Value, ScriptExecutionError>,
+        duration: Duration,
+        handler: Option<&str>,
+    ) -> ExecutionReport {
+        self.statistics.record(
+            handler,
+            duration,
+            value.as_ref().err().map(|error| error.kind()),
+        );
+        let mut warnings = Vec::new();
+        if !self.compiled {
+            warnings.push(String::from(
+                "script was not compiled using `Script::compile` before execution",
+            ));
+        }
+        let logs = match self.take_logs() {
+            Ok(logs) => logs,
+            Err(error) => {
+                warnings.push(format!("could not retrieve captured logs: {}", error));
+                Vec::new()
+            }
+        };
+        ExecutionReport {
+            value,
+            duration,
+            compile_time: self.compile_time,
+            handler: handler.map(|handler| handler.to_string()),
+            language: self.language,
+            logs,
+            warnings,
+        }
+    }
+
+    /// Adds the enabled helpers and declared globals to a call served from the cassette.
+    fn recorded_call(&self, interaction: Interaction) -> Interaction {
+        interaction.with_state(
+            [
+                ("js_exceptions", self.js_exceptions),
+                ("log_capture", self.log_capture),
+                ("batching", self.batching),
+                ("properties", self.properties),
+            ],
+            self.globals.values(),
+        )
+    }
+
+    /// Serves the recorded result of a generated helper handler.
+    fn replay_handler(
+        &self,
+        handler: &str,
+        arguments: Vec<Value>,
+    ) -> Result<Value, ScriptExecutionError> {
+        replayed(|| {
+            self.recorded_call(Interaction::execute_function(
+                self.language,
+                self.source_hash(),
+                handler,
+                &arguments,
+            ))
+        })
+        .execute_result()
+    }
+
+    fn take_logs(&self) -> Result<Vec<LogRecord>, ScriptExecutionError> {
+        if !self.log_capture {
+            return Ok(Vec::new());
+        }
+        Ok(parse_log_records(
+            self.language,
+            self.replay_handler(TAKE_LOGS_HANDLER, vec![])?,
+        ))
+    }
+
+    /// Serves the recorded values of the top-level properties declared in the source.
+    /// Always empty unless property access is enabled using [`Script::with_properties`].
+    pub fn properties(&self) -> Result<Map<String, Value>, ScriptExecutionError> {
+        if !self.properties || property_names(&self.source).is_empty() {
+            return Ok(Map::new());
+        }
+        parse_properties(self.replay_handler(GET_PROPERTIES_HANDLER, vec![])?)
+    }
+
+    /// Serves the recorded result of setting a top-level property declared in the source.
+    /// Requires property access to be enabled using [`Script::with_properties`].
+    pub fn set_property(&self, name: &str, value: Value) -> Result<(), ScriptExecutionError> {
+        if !self.properties || !property_names(&self.source).iter().any(|n| n == name) {
+            return Err(ScriptExecutionError::UnknownProperty(name.to_string()));
+        }
+        self.replay_handler(SET_PROPERTY_HANDLER, vec![Value::from(name), value])?;
+        Ok(())
+    }
+
+    /// Saves current property values (see [`Script::properties`]) to a JSON file.
+    pub fn save_properties<P: AsRef<Path>>(&self, path: P) -> Result<(), ScriptPropertiesError> {
+        let properties = Value::Object(self.properties()?);
+        std::fs::write(path, serde_json::to_vec_pretty(&properties)?)?;
+        Ok(())
+    }
+
+    /// Restores property values from a JSON file created by [`Script::save_properties`].
+    /// Values of properties which are no longer declared in the source are skipped.
+    pub fn load_properties<P: AsRef<Path>>(&self, path: P) -> Result<(), ScriptPropertiesError> {
+        let properties: Map<String, Value> = serde_json::from_slice(&std::fs::read(path)?)?;
+        let names = property_names(&self.source);
+        for (name, value) in properties {
+            if names.contains(&name) {
+                self.set_property(&name, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a copy of the aggregated execution statistics.
+    pub fn statistics(&self) -> ScriptStatistics {
+        self.statistics.snapshot()
+    }
+
+    /// Returns a thread-safe handle to the execution statistics, which can be read from other
+    /// threads, i.e. by a metrics exporter.
+    pub fn statistics_handle(&self) -> StatisticsHandle {
+        self.statistics.clone()
+    }
+
+    /// Serves the recorded results of the calls. When batching is enabled using
+    /// [`Script::with_batching`], the batch is served from a single recorded call, otherwise
+    /// calls are served one at a time the same way as [`Script::execute_function`].
+    pub fn execute_batch(
+        &self,
+        calls: &[(&str, Vec<Value>)],
+        mode: BatchMode,
+    ) -> Vec<Result<Value, ScriptExecutionError>> {
+        if self.batching {
+            let arguments = batch_arguments(calls, mode);
+            let span = CallSpan::execute_function(self, BATCH_HANDLER, &arguments);
+            let result = self.replay_handler(BATCH_HANDLER, arguments);
+            let duration = span.finish(&result);
+            return match self
+                .report(result, duration, Some(BATCH_HANDLER))
+                .into_result()
+                .and_then(|results| parse_batch_results(self.language, self.js_exceptions, results))
+            {
+                Ok(results) => results,
+                Err(error) => vec![Err(error)],
+            };
+        }
+        let mut results = Vec::with_capacity(calls.len());
+        for (function_name, arguments) in calls {
+            let result = self.execute_function(function_name, arguments.clone());
+            let failed = result.is_err();
+            results.push(result);
+            if failed && mode == BatchMode::StopOnFirstError {
+                break;
+            }
+        }
+        results
+    }
+
+    /// Serves the recorded results of the function call the same way as
+    /// [`Script::execute_function`], retrying according to the specified [`RetryPolicy`].
+    pub fn execute_function_with_retry<I: IntoIterator<Item = Value>>(
+        &self,
+        function_name: &str,
+        arguments: I,
+        policy: &RetryPolicy,
+    ) -> Result<Value, ScriptExecutionError> {
+        let arguments: Vec<Value> = arguments.into_iter().collect();
+        policy.run(|| self.execute_function(function_name, arguments.clone()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cassette::Cassette;
+    use serde_json::json;
+
+    const SOURCE: &str = "property counter : 0
+        on answer()
+            return 42
+        end answer";
+
+    #[test]
+    fn it_replays_reports_and_helper_calls() {
+        let path = std::env::temp_dir().join(format!(
+            "osakit_it_replays_reports_{}.json",
+            std::process::id()
+        ));
+        let hash = hash_source(SOURCE);
+        let options = [
+            ("log_capture", true),
+            ("batching", true),
+            ("properties", true),
+        ];
+        let declaration = global_declaration(Language::AppleScript, "name", &json!("x")).unwrap();
+        let call = |handler: &str, arguments: &[Value], result: serde_json::Value| {
+            Interaction::execute_function(Language::AppleScript, hash, handler, arguments)
+                .with_state(options, &[])
+                .with_execute_result(&Ok(result))
+        };
+        let batch = batch_arguments(&[("answer", vec![])], BatchMode::StopOnFirstError);
+        let interactions = vec![
+            Interaction::compile(Language::AppleScript, hash).with_state(options, &[]),
+            call("answer", &[], json!(42)),
+            call(TAKE_LOGS_HANDLER, &[], json!([[1700000000, "answered"]])),
+            call(GET_PROPERTIES_HANDLER, &[], json!({"counter": 1})),
+            call(
+                SET_PROPERTY_HANDLER,
+                &[json!("counter"), json!(2)],
+                json!(null),
+            ),
+            call(BATCH_HANDLER, &batch, json!([[true, 42]])),
+            call(TAKE_LOGS_HANDLER, &[], json!([])),
+            Interaction::compile(Language::AppleScript, hash).with_state(options, [&declaration]),
+            Interaction::execute(Language::AppleScript, hash)
+                .with_state(options, [&declaration])
+                .with_execute_result(&Ok(json!("x"))),
+            call(TAKE_LOGS_HANDLER, &[], json!([])).with_state(options, [&declaration]),
+        ];
+        std::fs::write(&path, json!({ "interactions": interactions }).to_string()).unwrap();
+        let cassette = Cassette::replay(&path).unwrap().insert();
+
+        let mut script = Script::new_from_source(Language::AppleScript, SOURCE)
+            .with_properties()
+            .with_log_capture()
+            .with_batching();
+        script.compile().unwrap();
+        let report = script.execute_function_with_report("answer", vec![]);
+        assert_eq!(report.value, Ok(Value::from(42)));
+        assert_eq!(report.handler.as_deref(), Some("answer"));
+        assert_eq!(report.logs.len(), 1);
+        assert_eq!(report.logs[0].message, "answered");
+        assert!(report.warnings.is_empty());
+        assert_eq!(script.statistics().calls, 1);
+        assert_eq!(script.handler_names(), vec!["answer"]);
+        assert_eq!(
+            script.properties().unwrap().get("counter"),
+            Some(&Value::from(1))
+        );
+        assert_eq!(script.set_property("counter", Value::from(2)), Ok(()));
+        assert_eq!(
+            script.set_property("missing", Value::Null),
+            Err(ScriptExecutionError::UnknownProperty(String::from(
+                "missing"
+            )))
+        );
+        assert_eq!(
+            script.execute_batch(&[("answer", vec![])], BatchMode::StopOnFirstError),
+            vec![Ok(Value::from(42))]
+        );
+        assert!(matches!(
+            script.write_to("script.scpt", StorageOptions::default()),
+            Err(ScriptStorageError::Unsupported)
+        ));
+        assert!(script.set_global("name", Value::from("x")).is_ok());
+        assert_eq!(script.execute(), Ok(Value::from("x")));
+
+        cassette.finish().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds of [`LatencyHistogram`] buckets in milliseconds.
+const LATENCY_BUCKETS_MS: [u64; 12] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// Histogram of execution latencies with fixed buckets from 1ms to 10s.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LatencyHistogram {
+    counts: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    sum: Duration,
+}
+
+impl LatencyHistogram {
+    /// Records a single observation.
+    pub fn observe(&mut self, duration: Duration) {
+        let index = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| duration <= Duration::from_millis(*bound))
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.counts[index] += 1;
+        self.sum += duration;
+    }
+
+    /// Cumulative counts of observations per bucket upper bound, `None` stands for infinity.
+    /// The format matches Prometheus histogram buckets.
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        let mut cumulative = 0;
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(index, count)| {
+                cumulative += count;
+                (
+                    LATENCY_BUCKETS_MS
+                        .get(index)
+                        .map(|bound| Duration::from_millis(*bound)),
+                    cumulative,
+                )
+            })
+            .collect()
+    }
+
+    /// Total number of observations.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Sum of all observed durations.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+}
+
+/// Aggregated execution statistics of a [`crate::Script`].
+/// Returned by [`crate::Script::statistics`] and [`StatisticsHandle::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ScriptStatistics {
+    /// Total number of executions.
+    pub calls: u64,
+    /// Number of executions per handler, [`crate::Script::execute`] calls are counted under `""`.
+    pub calls_by_handler: BTreeMap<String, u64>,
+    /// Total number of failed executions.
+    pub errors: u64,
+    /// Number of failed executions per [`crate::ScriptExecutionError::kind`].
+    pub errors_by_kind: BTreeMap<&'static str, u64>,
+    /// Execution latencies.
+    pub latency: LatencyHistogram,
+}
+
+impl ScriptStatistics {
+    /// Records a single execution.
+    pub(crate) fn record(
+        &mut self,
+        handler: Option<&str>,
+        duration: Duration,
+        error_kind: Option<&'static str>,
+    ) {
+        self.calls += 1;
+        *self
+            .calls_by_handler
+            .entry(handler.unwrap_or_default().to_string())
+            .or_default() += 1;
+        if let Some(kind) = error_kind {
+            self.errors += 1;
+            *self.errors_by_kind.entry(kind).or_default() += 1;
+        }
+        self.latency.observe(duration);
+    }
+}
+
+/// Thread-safe handle to the statistics of a [`crate::Script`].
+/// Allows reading statistics from other threads, i.e. by a metrics exporter.
+#[derive(Debug, Clone, Default)]
+pub struct StatisticsHandle(Arc<Mutex<ScriptStatistics>>);
+
+impl StatisticsHandle {
+    /// Returns a copy of the current statistics.
+    pub fn snapshot(&self) -> ScriptStatistics {
+        self.0
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .clone()
+    }
+
+    /// Resets statistics.
+    pub fn reset(&self) {
+        *self.0.lock().unwrap_or_else(|error| error.into_inner()) = ScriptStatistics::default();
+    }
+
+    pub(crate) fn record(
+        &self,
+        handler: Option<&str>,
+        duration: Duration,
+        error_kind: Option<&'static str>,
+    ) {
+        self.0
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .record(handler, duration, error_kind);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_records_latencies_in_buckets() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.observe(Duration::from_micros(500));
+        histogram.observe(Duration::from_millis(7));
+        histogram.observe(Duration::from_millis(10));
+        histogram.observe(Duration::from_secs(60));
+        let buckets = histogram.buckets();
+        assert_eq!(buckets.len(), LATENCY_BUCKETS_MS.len() + 1);
+        assert_eq!(buckets[0], (Some(Duration::from_millis(1)), 1));
+        assert_eq!(buckets[1], (Some(Duration::from_millis(5)), 1));
+        assert_eq!(buckets[2], (Some(Duration::from_millis(10)), 3));
+        assert_eq!(buckets[11], (Some(Duration::from_secs(10)), 3));
+        assert_eq!(buckets[12], (None, 4));
+        assert_eq!(histogram.count(), 4);
+        assert_eq!(
+            histogram.sum(),
+            Duration::from_micros(500) + Duration::from_millis(17) + Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn it_records_calls_and_errors() {
+        let handle = StatisticsHandle::default();
+        handle.record(None, Duration::from_millis(3), None);
+        handle.record(Some("concat"), Duration::from_millis(3), None);
+        handle.record(Some("concat"), Duration::from_millis(3), Some("runtime"));
+        let statistics = handle.snapshot();
+        assert_eq!(statistics.calls, 3);
+        assert_eq!(
+            statistics.calls_by_handler,
+            BTreeMap::from([(String::new(), 1), (String::from("concat"), 2)])
+        );
+        assert_eq!(statistics.errors, 1);
+        assert_eq!(statistics.errors_by_kind, BTreeMap::from([("runtime", 1)]));
+        assert_eq!(statistics.latency.count(), 3);
+    }
+
+    #[test]
+    fn it_resets_statistics() {
+        let handle = StatisticsHandle::default();
+        handle.record(None, Duration::from_millis(3), None);
+        handle.reset();
+        assert_eq!(handle.snapshot(), ScriptStatistics::default());
+    }
+}
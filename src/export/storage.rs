@@ -0,0 +1,367 @@
+use crate::script::Language;
+#[cfg(target_os = "macos")]
+use objc2::rc::Retained;
+#[cfg(target_os = "macos")]
+use objc2_foundation::{NSString, NSURL};
+#[cfg(target_os = "macos")]
+use objc2_osa_kit::{
+    OSAStorageApplicationBundleType, OSAStorageOptions, OSAStorageScriptBundleType,
+    OSAStorageScriptType,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Magic prefix of compiled `AppleScript` data.
+const APPLE_SCRIPT_MAGIC: &[u8] = b"FasdUAS ";
+
+/// Magic prefix of compiled `JavaScript` data.
+const JAVA_SCRIPT_MAGIC: &[u8] = b"JsOsaDAS";
+
+/// Location of the compiled script inside of script bundles and applications.
+const BUNDLE_MAIN_SCRIPT: &str = "Contents/Resources/Scripts/main.scpt";
+
+/// Format of a compiled script file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScriptFileFormat {
+    /// Single compiled script file, usually with `.scpt` extension.
+    #[default]
+    Script,
+    /// Script bundle directory, usually with `.scptd` extension.
+    ScriptBundle,
+    /// Application bundle, usually with `.app` extension.
+    Application,
+}
+
+/// Options used when writing a script using [`crate::Script::write_to`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StorageOptions {
+    /// File format.
+    pub format: ScriptFileFormat,
+    /// Saves the script without source, so it cannot be viewed or edited.
+    pub run_only: bool,
+    /// Keeps the application running after the script completes.
+    /// Only applies to [`ScriptFileFormat::Application`].
+    pub stay_open: bool,
+}
+
+#[cfg(target_os = "macos")]
+impl StorageOptions {
+    pub(crate) fn osa_storage_options(&self) -> OSAStorageOptions {
+        let mut options = OSAStorageOptions::Null;
+        if self.run_only {
+            options |= OSAStorageOptions::PreventGetSource;
+        }
+        if self.stay_open && self.format == ScriptFileFormat::Application {
+            options |= OSAStorageOptions::StayOpenApplet;
+        }
+        options
+    }
+
+    pub(crate) fn osa_storage_type(&self) -> &'static NSString {
+        unsafe {
+            match self.format {
+                ScriptFileFormat::Script => OSAStorageScriptType,
+                ScriptFileFormat::ScriptBundle => OSAStorageScriptBundleType,
+                ScriptFileFormat::Application => OSAStorageApplicationBundleType,
+            }
+        }
+    }
+}
+
+/// Error happening when writing or loading a compiled script.
+/// Returned by [`crate::Script::write_to`], [`crate::Script::new_from_compiled`] and
+/// [`read_script_file_header`].
+#[derive(Error, Debug)]
+pub enum ScriptStorageError {
+    #[error("unknown storage error")]
+    Unknown,
+    #[error("storage error: {message}")]
+    Failure {
+        message: String,
+        /// Error number reported by `OSAKit`, if any.
+        number: Option<i32>,
+    },
+    /// Happens when the path cannot be converted to a file URL.
+    #[error("invalid path: `{0}`")]
+    InvalidPath(PathBuf),
+    /// Happens when the loaded script uses a language not supported by [`Language`].
+    #[error("unsupported language: `{0}`")]
+    UnsupportedLanguage(String),
+    /// Happens when the file does not start with a known compiled script header.
+    #[error("unknown compiled script format")]
+    UnknownFormat,
+    /// Happens when writing a script which was not compiled using [`crate::Script::compile`].
+    #[error("script is not compiled")]
+    NotCompiled,
+    /// Happens when writing or loading a compiled script on platforms other than macOS.
+    #[error("compiled scripts can only be written and loaded on macOS")]
+    Unsupported,
+    #[error("script file error")]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn file_url(path: &Path) -> Result<Retained<NSURL>, ScriptStorageError> {
+    let path_string = path
+        .to_str()
+        .ok_or_else(|| ScriptStorageError::InvalidPath(path.to_path_buf()))?;
+    Ok(unsafe { NSURL::fileURLWithPath(&NSString::from_str(path_string)) })
+}
+
+/// Information about a compiled script read from the file header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptFileHeader {
+    /// Language of the compiled script.
+    pub language: Language,
+    /// Version of the compiled data format, i.e. `"1.101.10"`.
+    pub version: String,
+    /// Whether the script was saved without source, `None` on platforms other than macOS.
+    pub run_only: Option<bool>,
+}
+
+/// Reads the header of a compiled script file without executing the script.
+/// Script bundles and applications are supported by reading their main script.
+///
+/// The compiled data does not tell whether the script was saved as run-only, so on macOS
+/// the script is loaded to check whether its source is missing, the same way as
+/// [`crate::Script::is_run_only`] does.
+///
+/// ```no_run
+/// use osakit::{read_script_file_header, Language};
+///
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// let header = read_script_file_header("script.scpt")?;
+/// assert_eq!(header.language, Language::AppleScript);
+/// #
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_script_file_header<P: AsRef<Path>>(
+    path: P,
+) -> Result<ScriptFileHeader, ScriptStorageError> {
+    let path = path.as_ref();
+    let data = if path.is_dir() {
+        std::fs::read(path.join(BUNDLE_MAIN_SCRIPT))?
+    } else {
+        std::fs::read(path)?
+    };
+    let header = parse_script_file_header(&data)?;
+    #[cfg(target_os = "macos")]
+    let header = ScriptFileHeader {
+        run_only: Some(crate::Script::new_from_compiled(path)?.is_run_only()),
+        ..header
+    };
+    Ok(header)
+}
+
+fn parse_script_file_header(data: &[u8]) -> Result<ScriptFileHeader, ScriptStorageError> {
+    let (language, rest) = if let Some(rest) = data.strip_prefix(APPLE_SCRIPT_MAGIC) {
+        (Language::AppleScript, rest)
+    } else if let Some(rest) = data.strip_prefix(JAVA_SCRIPT_MAGIC) {
+        (Language::JavaScript, rest)
+    } else {
+        return Err(ScriptStorageError::UnknownFormat);
+    };
+    let version_length = rest
+        .iter()
+        .position(|byte| !byte.is_ascii_digit() && *byte != b'.')
+        .unwrap_or(rest.len());
+    // `JavaScript` version has a fixed length and can be directly followed by digits.
+    let version_length = match language {
+        Language::JavaScript => version_length.min(8),
+        Language::AppleScript => version_length,
+    };
+    if version_length == 0 {
+        return Err(ScriptStorageError::UnknownFormat);
+    }
+    Ok(ScriptFileHeader {
+        language,
+        version: String::from_utf8_lossy(&rest[..version_length]).into_owned(),
+        run_only: None,
+    })
+}
+
+/// Helpers enabled when the script was compiled. Stored as a comment at the start of the
+/// generated epilogue, so they can be restored when loading a compiled script.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) struct StoredHelpers {
+    pub(crate) js_exceptions: bool,
+    pub(crate) log_capture: bool,
+    pub(crate) batching: bool,
+    pub(crate) properties: bool,
+    pub(crate) globals: Vec<String>,
+}
+
+/// Comment marking the end of the generated prelude.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn prelude_end_marker(language: Language) -> &'static str {
+    match language {
+        // `AppleScript` keeps comments when decompiling, but may reformat the lines around them.
+        Language::AppleScript => "(*osakit:source*)\n",
+        // Kept on the same line, so line numbers in error messages stay intact.
+        Language::JavaScript => "/*osakit:source*/",
+    }
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn helpers_comment(language: Language) -> (&'static str, &'static str) {
+    match language {
+        Language::AppleScript => ("(*osakit:helpers ", "*)"),
+        Language::JavaScript => ("/*osakit:helpers ", "*/"),
+    }
+}
+
+impl StoredHelpers {
+    /// Comment starting the generated epilogue. Contains only booleans and identifiers, so it
+    /// cannot end the comment early.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn marker(&self, language: Language) -> String {
+        let (start, end) = helpers_comment(language);
+        format!(
+            "\n{}{}{}\n",
+            start,
+            serde_json::to_string(self).unwrap_or_default(),
+            end
+        )
+    }
+}
+
+/// Source of a loaded script, split into the generated prelude, the original source and the
+/// helpers stored in the epilogue marker.
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) struct LoadedSource<'a> {
+    pub(crate) prelude: &'a str,
+    pub(crate) source: &'a str,
+    pub(crate) helpers: StoredHelpers,
+}
+
+impl<'a> LoadedSource<'a> {
+    /// Splits the source of a loaded script, `None` if it was compiled without helpers.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn parse(language: Language, loaded: &'a str) -> Option<Self> {
+        let (start, end) = helpers_comment(language);
+        let marker_start = loaded.rfind(start)?;
+        let data_start = marker_start + start.len();
+        let data_end = data_start + loaded[data_start..].find(end)?;
+        let helpers = serde_json::from_str(&loaded[data_start..data_end]).ok()?;
+        let prelude_end = prelude_end_marker(language).trim_end();
+        let (prelude, source) = match loaded[..marker_start].find(prelude_end) {
+            Some(index) => loaded[..marker_start].split_at(index + prelude_end.len()),
+            None => ("", &loaded[..marker_start]),
+        };
+        // Line breaks following the prelude marker and preceding the epilogue marker.
+        let source = match prelude.is_empty() || language == Language::JavaScript {
+            true => source,
+            false => strip_line_break(source, str::strip_prefix),
+        };
+        Some(Self {
+            prelude,
+            source: strip_line_break(source, str::strip_suffix),
+            helpers,
+        })
+    }
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn strip_line_break<'a>(
+    text: &'a str,
+    strip: fn(&'a str, &'static str) -> Option<&'a str>,
+) -> &'a str {
+    ["\r\n", "\n", "\r"]
+        .into_iter()
+        .find_map(|line_break| strip(text, line_break))
+        .unwrap_or(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_reads_apple_script_header() {
+        let header = parse_script_file_header(b"FasdUAS 1.101.10\x0e\x00\x00\x00\x04").unwrap();
+        assert_eq!(header.language, Language::AppleScript);
+        assert_eq!(header.version, "1.101.10");
+    }
+
+    #[test]
+    fn it_reads_java_script_header() {
+        let header = parse_script_file_header(b"JsOsaDAS1.001.00function run() {}").unwrap();
+        assert_eq!(header.language, Language::JavaScript);
+        assert_eq!(header.version, "1.001.00");
+    }
+
+    #[test]
+    fn it_fails_on_unknown_format() {
+        assert!(matches!(
+            parse_script_file_header(b"on run\nend run"),
+            Err(ScriptStorageError::UnknownFormat)
+        ));
+        assert!(matches!(
+            parse_script_file_header(b"FasdUAS "),
+            Err(ScriptStorageError::UnknownFormat)
+        ));
+    }
+
+    #[test]
+    fn it_splits_loaded_sources() {
+        let helpers = StoredHelpers {
+            js_exceptions: true,
+            globals: vec![String::from("x")],
+            ..Default::default()
+        };
+        let loaded = format!(
+            "var x = 1;{}output = x;{}function __osakit_call() {{}}",
+            prelude_end_marker(Language::JavaScript),
+            helpers.marker(Language::JavaScript)
+        );
+        assert_eq!(
+            LoadedSource::parse(Language::JavaScript, &loaded),
+            Some(LoadedSource {
+                prelude: "var x = 1;/*osakit:source*/",
+                source: "output = x;",
+                helpers,
+            })
+        );
+    }
+
+    #[test]
+    fn it_splits_decompiled_apple_script_sources() {
+        let loaded = "property |x| : 1\r(*osakit:source*)\rreturn x\r\
+            (*osakit:helpers {\"js_exceptions\":false,\"log_capture\":true,\
+            \"batching\":false,\"properties\":false,\"globals\":[\"x\"]}*)\r\
+            on __osakit_take_logs()\rend __osakit_take_logs";
+        let source = LoadedSource::parse(Language::AppleScript, loaded).unwrap();
+        assert_eq!(source.prelude, "property |x| : 1\r(*osakit:source*)");
+        assert_eq!(source.source, "return x");
+        assert!(source.helpers.log_capture);
+        assert_eq!(LoadedSource::parse(Language::AppleScript, "return 1"), None);
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn it_converts_storage_options() {
+        let options = StorageOptions {
+            format: ScriptFileFormat::Application,
+            run_only: true,
+            stay_open: true,
+        };
+        assert_eq!(
+            options.osa_storage_options(),
+            OSAStorageOptions::PreventGetSource | OSAStorageOptions::StayOpenApplet
+        );
+        assert_eq!(
+            StorageOptions {
+                stay_open: true,
+                ..Default::default()
+            }
+            .osa_storage_options(),
+            OSAStorageOptions::Null
+        );
+    }
+}
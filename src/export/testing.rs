@@ -0,0 +1,698 @@
+use std::fmt::Debug;
+use std::io::{stdout, Write};
+use std::panic::catch_unwind;
+use std::process::ExitCode;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Registers a test to be run on the main thread by [`crate::harness!`].
+pub use osakit_macros::test;
+
+#[doc(hidden)]
+pub use ctor::ctor as __ctor;
+
+static TESTS: Mutex<Vec<TestDefinition>> = Mutex::new(Vec::new());
+
+#[doc(hidden)]
+pub fn __register(test: TestDefinition) {
+    TESTS.lock().unwrap().push(test);
+}
+
+/// Returns the tests registered using [`crate::test`] and clears the registry.
+pub fn take_registered() -> Vec<TestDefinition> {
+    std::mem::take(&mut *TESTS.lock().unwrap())
+}
+
+/// Whether a test is expected to panic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShouldPanic {
+    No,
+    Yes,
+    /// The panic message must contain the specified string.
+    WithMessage(&'static str),
+}
+
+/// Test run by [`run_tests`], usually constructed by [`crate::test`].
+#[derive(Debug, Clone)]
+pub struct TestDefinition {
+    /// Module path of the test function, the crate name is not included into the test name.
+    pub module_path: &'static str,
+    pub name: &'static str,
+    pub function: fn() -> Result<(), String>,
+    /// Contains the reason (possibly empty) if the test is ignored.
+    pub ignore: Option<&'static str>,
+    pub should_panic: ShouldPanic,
+}
+
+impl TestDefinition {
+    /// Test name used for filtering and reporting, i.e. `module::test_name`.
+    pub fn full_name(&self) -> String {
+        match self.module_path.split_once("::") {
+            Some((_, path)) => format!("{}::{}", path, self.name),
+            None => self.name.to_string(),
+        }
+    }
+}
+
+/// Result of a test function, either `()` or `Result<(), E>`.
+pub trait TestResult {
+    fn into_result(self) -> Result<(), String>;
+}
+
+impl TestResult for () {
+    fn into_result(self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl<E: Debug> TestResult for Result<(), E> {
+    fn into_result(self) -> Result<(), String> {
+        self.map_err(|error| format!("Error: {:?}", error))
+    }
+}
+
+/// Command line arguments of the test harness, a subset of the ones supported by libtest.
+///
+/// Tests are always run one by one on the calling thread, so `--test-threads` is ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Arguments {
+    /// Only tests containing one of the filters in their names are run.
+    pub filters: Vec<String>,
+    /// Tests containing one of these strings in their names are skipped.
+    pub skip: Vec<String>,
+    /// Filters have to match test names exactly.
+    pub exact: bool,
+    /// Lists tests instead of running them.
+    pub list: bool,
+    /// Runs only ignored tests.
+    pub ignored: bool,
+    /// Runs ignored tests together with the other ones.
+    pub include_ignored: bool,
+    /// Doesn't capture the output of tests.
+    pub nocapture: bool,
+    /// Shows the captured output of successful tests.
+    pub show_output: bool,
+    /// Displays one character per test instead of one line.
+    pub quiet: bool,
+    /// Displays help instead of running tests.
+    pub help: bool,
+}
+
+const HELP: &str = "Usage: [OPTIONS] [FILTERS...]
+
+Options:
+        --include-ignored
+                        Run ignored and not ignored tests
+        --ignored       Run only ignored tests
+        --exact         Exactly match filters rather than by substring
+        --skip FILTER   Skip tests whose names contain FILTER (this flag can
+                        be used multiple times)
+        --list          List all tests
+        --nocapture     Don't capture stdout/stderr of each test
+        --show-output   Show captured stdout of successful tests
+    -q, --quiet         Display one character per test instead of one line
+    -h, --help          Display this message
+
+Tests are run one by one on the main thread, `--test-threads` is ignored.
+";
+
+impl Arguments {
+    /// Parses arguments of the current process.
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    /// Parses arguments, not including the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut result = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') {
+                result.filters.push(arg);
+                continue;
+            }
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for `{}`", name))
+            };
+            match name.as_str() {
+                "--exact" => result.exact = true,
+                "--list" => result.list = true,
+                "--ignored" => result.ignored = true,
+                "--include-ignored" => result.include_ignored = true,
+                "--nocapture" | "--no-capture" => result.nocapture = true,
+                "--show-output" => result.show_output = true,
+                "-q" | "--quiet" => result.quiet = true,
+                "-h" | "--help" => result.help = true,
+                "--skip" => result.skip.push(value()?),
+                "--format" => match value()?.as_str() {
+                    "pretty" => result.quiet = false,
+                    "terse" => result.quiet = true,
+                    format => return Err(format!("unsupported format `{}`", format)),
+                },
+                "--test-threads" => {
+                    let threads = value()?;
+                    threads
+                        .parse::<usize>()
+                        .map_err(|_| format!("invalid number of threads `{}`", threads))?;
+                }
+                "--color" | "-Z" => {
+                    value()?;
+                }
+                "--test" => {}
+                _ => return Err(format!("unknown option `{}`", name)),
+            }
+        }
+        Ok(result)
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        let matches = |filter: &String| {
+            if self.exact {
+                name == filter
+            } else {
+                name.contains(filter.as_str())
+            }
+        };
+        (self.filters.is_empty() || self.filters.iter().any(matches))
+            && !self.skip.iter().any(matches)
+    }
+}
+
+/// Summary of a test run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Conclusion {
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+    pub filtered_out: usize,
+}
+
+impl Conclusion {
+    pub fn has_failed(&self) -> bool {
+        self.failed > 0
+    }
+
+    /// Exit code used by libtest: `101` if any of the tests failed.
+    pub fn exit_code(&self) -> ExitCode {
+        if self.has_failed() {
+            ExitCode::from(101)
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// Runs the tests registered using [`crate::test`] according to the command line arguments.
+/// Used by [`crate::harness!`].
+pub fn run() -> ExitCode {
+    match Arguments::from_env() {
+        Ok(args) if args.help => {
+            print!("{}", HELP);
+            ExitCode::SUCCESS
+        }
+        Ok(args) => run_tests(&args, take_registered()).exit_code(),
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::from(101)
+        }
+    }
+}
+
+/// Runs the tests one by one on the calling thread, reporting to the standard output.
+pub fn run_tests(args: &Arguments, tests: Vec<TestDefinition>) -> Conclusion {
+    run_tests_with_output(args, tests, &mut stdout())
+}
+
+enum Outcome {
+    Passed,
+    Failed(Option<String>),
+    Ignored,
+}
+
+fn run_tests_with_output<W: Write>(
+    args: &Arguments,
+    tests: Vec<TestDefinition>,
+    out: &mut W,
+) -> Conclusion {
+    let mut tests: Vec<(String, TestDefinition)> = tests
+        .into_iter()
+        .map(|test| (test.full_name(), test))
+        .collect();
+    tests.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let total = tests.len();
+    let tests: Vec<(String, TestDefinition)> = tests
+        .into_iter()
+        .filter(|(name, test)| args.matches(name) && (!args.ignored || test.ignore.is_some()))
+        .collect();
+    let mut conclusion = Conclusion {
+        filtered_out: total - tests.len(),
+        ..Conclusion::default()
+    };
+
+    if args.list {
+        for (name, _) in &tests {
+            let _ = writeln!(out, "{}: test", name);
+        }
+        if !args.quiet {
+            let _ = writeln!(out, "\n{} tests, 0 benchmarks", tests.len());
+        }
+        return conclusion;
+    }
+
+    let _ = writeln!(
+        out,
+        "\nrunning {} test{}",
+        tests.len(),
+        if tests.len() == 1 { "" } else { "s" }
+    );
+    let started = Instant::now();
+    let mut failures: Vec<(String, String)> = Vec::new();
+    let mut successes: Vec<(String, String)> = Vec::new();
+    for (name, test) in tests {
+        if !args.quiet {
+            let _ = write!(out, "test {} ... ", name);
+        }
+        let _ = out.flush();
+        let (outcome, output) = match test.ignore {
+            Some(_) if !args.ignored && !args.include_ignored => (Outcome::Ignored, String::new()),
+            _ if args.nocapture => (run_test(&test), String::new()),
+            _ => {
+                let capture = capture::Capture::start();
+                let outcome = run_test(&test);
+                let output = capture.map(|c| c.finish()).unwrap_or_default();
+                (outcome, output)
+            }
+        };
+        match outcome {
+            Outcome::Passed => {
+                conclusion.passed += 1;
+                let _ = write!(out, "{}", if args.quiet { "." } else { "ok\n" });
+                successes.push((name, output));
+            }
+            Outcome::Ignored => {
+                conclusion.ignored += 1;
+                let _ = match test.ignore {
+                    _ if args.quiet => write!(out, "i"),
+                    Some(reason) if !reason.is_empty() => writeln!(out, "ignored, {}", reason),
+                    _ => writeln!(out, "ignored"),
+                };
+            }
+            Outcome::Failed(message) => {
+                conclusion.failed += 1;
+                let _ = write!(out, "{}", if args.quiet { "F" } else { "FAILED\n" });
+                let output = match message {
+                    Some(message) => format!("{}{}\n", output, message),
+                    None => output,
+                };
+                failures.push((name, output));
+            }
+        }
+    }
+    if args.quiet {
+        let _ = writeln!(out);
+    }
+
+    if args.show_output {
+        write_section(out, "successes", &successes);
+    }
+    write_section(out, "failures", &failures);
+    let _ = writeln!(
+        out,
+        "\ntest result: {}. {} passed; {} failed; {} ignored; 0 measured; {} filtered out; finished in {:.2}s\n",
+        if conclusion.has_failed() { "FAILED" } else { "ok" },
+        conclusion.passed,
+        conclusion.failed,
+        conclusion.ignored,
+        conclusion.filtered_out,
+        started.elapsed().as_secs_f64()
+    );
+    conclusion
+}
+
+fn write_section<W: Write>(out: &mut W, title: &str, tests: &[(String, String)]) {
+    if tests.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "\n{}:\n", title);
+    for (name, output) in tests {
+        if !output.is_empty() {
+            let _ = writeln!(out, "---- {} stdout ----\n{}", name, output);
+        }
+    }
+    let _ = writeln!(out, "\n{}:", title);
+    for (name, _) in tests {
+        let _ = writeln!(out, "    {}", name);
+    }
+}
+
+fn run_test(test: &TestDefinition) -> Outcome {
+    match (catch_unwind(test.function), test.should_panic) {
+        (Ok(Ok(())), ShouldPanic::No) => Outcome::Passed,
+        (Ok(Err(message)), _) => Outcome::Failed(Some(message)),
+        (Ok(Ok(())), _) => Outcome::Failed(Some("note: test did not panic as expected".into())),
+        (Err(_), ShouldPanic::No) => Outcome::Failed(None),
+        (Err(_), ShouldPanic::Yes) => Outcome::Passed,
+        (Err(payload), ShouldPanic::WithMessage(expected)) => {
+            let message = payload
+                .downcast_ref::<String>()
+                .map(String::as_str)
+                .or_else(|| payload.downcast_ref::<&str>().copied());
+            match message {
+                Some(message) if message.contains(expected) => Outcome::Passed,
+                Some(message) => Outcome::Failed(Some(format!(
+                    "note: panic did not contain expected string\n      panic message: {:?},\n expected substring: {:?}",
+                    message, expected
+                ))),
+                None => Outcome::Failed(Some(format!(
+                    "note: expected panic with string value,\n found non-string value\n     expected substring: {:?}",
+                    expected
+                ))),
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+mod capture {
+    use std::fs::{remove_file, File, OpenOptions};
+    use std::io::{stderr, stdout, Read, Seek, SeekFrom, Write};
+    use std::os::fd::AsRawFd;
+    use std::os::raw::c_int;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    extern "C" {
+        fn dup(fd: c_int) -> c_int;
+        fn dup2(src: c_int, dst: c_int) -> c_int;
+        fn close(fd: c_int) -> c_int;
+    }
+
+    const STDOUT: c_int = 1;
+    const STDERR: c_int = 2;
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    /// Redirects the standard output and error of the process into a temporary file, so that
+    /// the output of scripts and foreign code is captured as well.
+    pub(super) struct Capture {
+        file: File,
+        saved: Option<(c_int, c_int)>,
+    }
+
+    impl Capture {
+        pub(super) fn start() -> Option<Self> {
+            let path = std::env::temp_dir().join(format!(
+                "osakit-test-output-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .ok()?;
+            let _ = remove_file(&path);
+            let _ = stdout().flush();
+            let _ = stderr().flush();
+            // SAFETY: only duplicates the standard descriptors and the descriptor owned by `file`.
+            unsafe {
+                let saved_stdout = dup(STDOUT);
+                let saved_stderr = dup(STDERR);
+                if saved_stdout < 0 || saved_stderr < 0 {
+                    close(saved_stdout);
+                    close(saved_stderr);
+                    return None;
+                }
+                dup2(file.as_raw_fd(), STDOUT);
+                dup2(file.as_raw_fd(), STDERR);
+                Some(Self {
+                    file,
+                    saved: Some((saved_stdout, saved_stderr)),
+                })
+            }
+        }
+
+        pub(super) fn finish(mut self) -> String {
+            self.restore();
+            let mut output = Vec::new();
+            let _ = self.file.seek(SeekFrom::Start(0));
+            let _ = self.file.read_to_end(&mut output);
+            String::from_utf8_lossy(&output).into_owned()
+        }
+
+        fn restore(&mut self) {
+            if let Some((saved_stdout, saved_stderr)) = self.saved.take() {
+                let _ = stdout().flush();
+                let _ = stderr().flush();
+                // SAFETY: restores the descriptors saved in `start` and closes the copies.
+                unsafe {
+                    dup2(saved_stdout, STDOUT);
+                    dup2(saved_stderr, STDERR);
+                    close(saved_stdout);
+                    close(saved_stderr);
+                }
+            }
+        }
+    }
+
+    impl Drop for Capture {
+        fn drop(&mut self) {
+            self.restore();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod capture {
+    /// Output capturing is only supported on Unix, the output is passed through otherwise.
+    pub(super) struct Capture;
+
+    impl Capture {
+        pub(super) fn start() -> Option<Self> {
+            None
+        }
+
+        pub(super) fn finish(self) -> String {
+            String::new()
+        }
+    }
+}
+
+/// Generates `main` running the tests registered using [`crate::test`] on the main thread.
+///
+/// The test target must use `harness = false` in `Cargo.toml`:
+///
+/// ```toml
+/// [[test]]
+/// name = "scripts"
+/// harness = false
+/// ```
+///
+/// ## Example
+///
+/// ```no_run
+/// #[osakit::test]
+/// fn it_runs_scripts() {
+///     // OSAKit calls are made from the main thread.
+/// }
+///
+/// osakit::harness!();
+/// ```
+#[macro_export]
+macro_rules! harness {
+    () => {
+        fn main() -> ::std::process::ExitCode {
+            $crate::testing::run()
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::{run_tests_with_output, Arguments, Conclusion, ShouldPanic, TestDefinition};
+
+    fn definition(name: &'static str, function: fn() -> Result<(), String>) -> TestDefinition {
+        TestDefinition {
+            module_path: "crate::module",
+            name,
+            function,
+            ignore: None,
+            should_panic: ShouldPanic::No,
+        }
+    }
+
+    fn tests() -> Vec<TestDefinition> {
+        vec![
+            definition("it_passes", || {
+                println!("passing output");
+                Ok(())
+            }),
+            definition("it_fails", || {
+                println!("failing output");
+                Err("Error: \"failure\"".into())
+            }),
+            TestDefinition {
+                ignore: Some("slow"),
+                ..definition("it_is_ignored", || Ok(()))
+            },
+            TestDefinition {
+                should_panic: ShouldPanic::WithMessage("expected"),
+                ..definition("it_panics", || panic!("expected panic"))
+            },
+        ]
+    }
+
+    fn run(args: &[&str]) -> (Conclusion, String) {
+        let args = Arguments::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+        let mut output = Vec::new();
+        let conclusion = run_tests_with_output(&args, tests(), &mut output);
+        (conclusion, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn it_parses_arguments() {
+        let args = Arguments::parse(
+            [
+                "module",
+                "--exact",
+                "--skip=slow",
+                "--skip",
+                "other",
+                "--test-threads",
+                "1",
+                "--include-ignored",
+                "--nocapture",
+            ]
+            .map(String::from),
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            Arguments {
+                filters: vec!["module".into()],
+                skip: vec!["slow".into(), "other".into()],
+                exact: true,
+                include_ignored: true,
+                nocapture: true,
+                ..Arguments::default()
+            }
+        );
+        assert_eq!(
+            Arguments::parse(["--unknown".to_string()]),
+            Err("unknown option `--unknown`".into())
+        );
+        assert_eq!(
+            Arguments::parse(["--skip".to_string()]),
+            Err("missing value for `--skip`".into())
+        );
+    }
+
+    #[test]
+    fn it_names_tests_without_crate_name() {
+        assert_eq!(
+            definition("it_passes", || Ok(())).full_name(),
+            "module::it_passes"
+        );
+        assert_eq!(
+            TestDefinition {
+                module_path: "scripts",
+                ..definition("it_passes", || Ok(()))
+            }
+            .full_name(),
+            "it_passes"
+        );
+    }
+
+    #[test]
+    fn it_runs_tests_capturing_output() {
+        let (conclusion, output) = run(&[]);
+        assert_eq!(
+            conclusion,
+            Conclusion {
+                passed: 2,
+                failed: 1,
+                ignored: 1,
+                filtered_out: 0
+            }
+        );
+        assert!(output.contains("\nrunning 4 tests\n"));
+        assert!(output.contains("test module::it_passes ... ok\n"));
+        assert!(output.contains("test module::it_panics ... ok\n"));
+        assert!(output.contains("test module::it_is_ignored ... ignored, slow\n"));
+        assert!(output.contains("test module::it_fails ... FAILED\n"));
+        assert!(output.contains("\nfailures:\n    module::it_fails\n"));
+        assert!(!output.contains("passing output"));
+        if cfg!(unix) {
+            assert!(output.contains(
+                "---- module::it_fails stdout ----\nfailing output\nError: \"failure\"\n"
+            ));
+        }
+        assert!(output.contains(
+            "test result: FAILED. 2 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out;"
+        ));
+    }
+
+    #[test]
+    fn it_filters_tests() {
+        let (conclusion, output) = run(&["it_pa", "--skip", "panics"]);
+        assert_eq!(
+            conclusion,
+            Conclusion {
+                passed: 1,
+                failed: 0,
+                ignored: 0,
+                filtered_out: 3
+            }
+        );
+        assert!(output.contains("\nrunning 1 test\n"));
+        assert!(output.contains("test result: ok. 1 passed;"));
+
+        let (conclusion, _) = run(&["--exact", "it_passes"]);
+        assert_eq!(conclusion.filtered_out, 4);
+    }
+
+    #[test]
+    fn it_runs_ignored_tests() {
+        let (conclusion, output) = run(&["--ignored"]);
+        assert_eq!(conclusion.passed, 1);
+        assert_eq!(conclusion.filtered_out, 3);
+        assert!(output.contains("test module::it_is_ignored ... ok\n"));
+
+        let (conclusion, _) = run(&["--include-ignored"]);
+        assert_eq!(conclusion.passed, 3);
+        assert_eq!(conclusion.ignored, 0);
+    }
+
+    #[test]
+    fn it_lists_tests() {
+        let (conclusion, output) = run(&["--list", "module"]);
+        assert_eq!(conclusion, Conclusion::default());
+        assert_eq!(
+            output,
+            "module::it_fails: test\n\
+             module::it_is_ignored: test\n\
+             module::it_panics: test\n\
+             module::it_passes: test\n\
+             \n4 tests, 0 benchmarks\n"
+        );
+        let (_, output) = run(&["--list", "--format", "terse", "--ignored"]);
+        assert_eq!(output, "module::it_is_ignored: test\n");
+    }
+
+    #[test]
+    fn it_shows_output_of_successful_tests() {
+        let (_, output) = run(&["--show-output", "it_passes"]);
+        if cfg!(unix) {
+            assert!(output
+                .contains("\nsuccesses:\n\n---- module::it_passes stdout ----\npassing output\n"));
+        }
+        assert!(output.contains("\nsuccesses:\n    module::it_passes\n"));
+    }
+}
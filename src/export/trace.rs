@@ -0,0 +1,265 @@
+use crate::script::{Script, ScriptCompilationError, ScriptExecutionError};
+#[cfg(feature = "tracing")]
+use crate::value::Map;
+use crate::value::Value;
+#[cfg(feature = "tracing")]
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// Controls whether argument and result values are recorded in `tracing` spans.
+/// Values may contain sensitive data, so recording is disabled by default.
+///
+/// ## Example
+///
+/// ```
+/// use osakit::{redact_strings, set_value_recording, ValueRecording};
+///
+/// set_value_recording(ValueRecording::Redacted(redact_strings));
+/// ```
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, Default)]
+pub enum ValueRecording {
+    /// Values are not recorded.
+    #[default]
+    Disabled,
+    /// Values are recorded after being passed through the redaction function.
+    Redacted(fn(&Value) -> Value),
+    /// Values are recorded as is.
+    Full,
+}
+
+#[cfg(feature = "tracing")]
+static VALUE_RECORDING: RwLock<ValueRecording> = RwLock::new(ValueRecording::Disabled);
+
+/// Sets how argument and result values are recorded in `tracing` spans.
+#[cfg(feature = "tracing")]
+pub fn set_value_recording(recording: ValueRecording) {
+    *VALUE_RECORDING
+        .write()
+        .unwrap_or_else(|error| error.into_inner()) = recording;
+}
+
+#[cfg(feature = "tracing")]
+fn value_recording() -> ValueRecording {
+    *VALUE_RECORDING
+        .read()
+        .unwrap_or_else(|error| error.into_inner())
+}
+
+/// Redaction function replacing all strings with `"<redacted>"` and keeping the structure,
+/// numbers, booleans and object keys intact. To be used with [`ValueRecording::Redacted`].
+#[cfg(feature = "tracing")]
+pub fn redact_strings(value: &Value) -> Value {
+    match value {
+        Value::String(_) => Value::String(String::from("<redacted>")),
+        Value::Array(items) => Value::Array(items.iter().map(redact_strings).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), redact_strings(value)))
+                .collect::<Map<String, Value>>(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Stable 64-bit FNV-1a hash of the script source.
+pub(crate) fn hash_source(source: &str) -> u64 {
+    source
+        .as_bytes()
+        .iter()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Span covering a single compile/execute call. Does nothing without the `tracing` feature.
+pub(crate) struct CallSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+    started: Instant,
+}
+
+impl CallSpan {
+    pub(crate) fn compile(script: &Script) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = script;
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                target: "osakit",
+                "compile",
+                language = script.language().name(),
+                source_hash = script.source_hash(),
+                duration_us = tracing::field::Empty,
+                error = tracing::field::Empty,
+                error_code = tracing::field::Empty,
+            )
+            .entered(),
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn execute(script: &Script) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = script;
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                target: "osakit",
+                "execute",
+                language = script.language().name(),
+                source_hash = script.source_hash(),
+                duration_us = tracing::field::Empty,
+                result = tracing::field::Empty,
+                error = tracing::field::Empty,
+                error_code = tracing::field::Empty,
+            )
+            .entered(),
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn execute_function(script: &Script, handler: &str, arguments: &[Value]) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = (script, handler, arguments);
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            target: "osakit",
+            "execute_function",
+            language = script.language().name(),
+            handler,
+            argument_count = arguments.len(),
+            source_hash = script.source_hash(),
+            duration_us = tracing::field::Empty,
+            arguments = tracing::field::Empty,
+            result = tracing::field::Empty,
+            error = tracing::field::Empty,
+            error_code = tracing::field::Empty,
+        );
+        #[cfg(feature = "tracing")]
+        if let Some(arguments) = recorded_arguments(arguments) {
+            span.record("arguments", arguments);
+        }
+        Self {
+            #[cfg(feature = "tracing")]
+            span: span.entered(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Records duration and outcome of a compilation, returns the duration.
+    pub(crate) fn finish_compile(self, result: &Result<(), ScriptCompilationError>) -> Duration {
+        let duration = self.started.elapsed();
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("duration_us", duration.as_micros() as u64);
+            if let Err(error) = result {
+                self.span.record(
+                    "error",
+                    match error {
+                        ScriptCompilationError::Unknown => "unknown",
+                        ScriptCompilationError::Failure { .. } => "failure",
+                        ScriptCompilationError::Function { .. } => "function",
+                        ScriptCompilationError::MainThread => "main_thread",
+                    },
+                );
+                if let Some(code) = error.code() {
+                    self.span.record("error_code", code.number());
+                }
+                tracing::debug!(target: "osakit", %error, "script compilation failed");
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = result;
+        duration
+    }
+
+    /// Records duration and outcome of an execution, returns the duration.
+    pub(crate) fn finish(self, result: &Result<Value, ScriptExecutionError>) -> Duration {
+        let duration = self.started.elapsed();
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("duration_us", duration.as_micros() as u64);
+            match result {
+                Ok(value) => {
+                    if let Some(value) = recorded_value(value) {
+                        self.span.record("result", value);
+                    }
+                }
+                Err(error) => {
+                    self.span.record("error", error.kind());
+                    if let Some(code) = error.code() {
+                        self.span.record("error_code", code.number());
+                    }
+                    tracing::debug!(target: "osakit", %error, "script execution failed");
+                }
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = result;
+        duration
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn recorded_value(value: &Value) -> Option<String> {
+    match value_recording() {
+        ValueRecording::Disabled => None,
+        ValueRecording::Redacted(redact) => Some(redact(value).to_string()),
+        ValueRecording::Full => Some(value.to_string()),
+    }
+}
+
+/// Same as [`recorded_value`], but the arguments are only copied when redacting.
+#[cfg(feature = "tracing")]
+fn recorded_arguments(arguments: &[Value]) -> Option<String> {
+    match value_recording() {
+        ValueRecording::Disabled => None,
+        ValueRecording::Redacted(redact) => {
+            Some(Value::Array(arguments.iter().map(redact).collect()).to_string())
+        }
+        ValueRecording::Full => serde_json::to_string(arguments).ok(),
+    }
+}
+
+/// Emits an event about a failed value conversion.
+#[inline]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn conversion_failed<E: std::fmt::Display>(direction: &'static str, error: &E) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(target: "osakit", direction, %error, "value conversion failed");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (direction, error);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "tracing")]
+    use serde_json::json;
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn it_redacts_strings() {
+        assert_eq!(
+            redact_strings(&json!({
+                "name": "secret",
+                "id": 21,
+                "tags": ["a", true, null],
+            })),
+            json!({
+                "name": "<redacted>",
+                "id": 21,
+                "tags": ["<redacted>", true, null],
+            })
+        );
+    }
+
+    #[test]
+    fn it_hashes_source() {
+        assert_eq!(hash_source(""), 0xcbf29ce484222325);
+        assert_eq!(hash_source("a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(hash_source("return 1"), hash_source("return 2"));
+    }
+}
@@ -1,14 +1,8 @@
-use crate::Value;
+use super::{ScriptInputConversionError, Value};
+use crate::trace::conversion_failed;
 use objc2::{rc::Retained, AllocAnyThread};
 use objc2_foundation::{NSArray, NSDictionary, NSNull, NSNumber, NSObject, NSString};
 use std::ops::Deref;
-use thiserror::Error;
-
-#[derive(Error, Debug, PartialEq, Eq)]
-pub enum ScriptInputConversionError {
-    #[error("number conversion error: `{0}`")]
-    NumberConversionError(String),
-}
 
 fn value_to_nsobject(value: Value) -> Result<Retained<NSObject>, ScriptInputConversionError> {
     Ok(unsafe {
@@ -37,7 +31,7 @@ fn value_to_nsobject(value: Value) -> Result<Retained<NSObject>, ScriptInputConv
                     })?
             }),
             Value::Null => Retained::cast_unchecked(NSNull::null()),
-            Value::Array(vec) => Retained::cast_unchecked(values_vec_to_ns_array(vec)?),
+            Value::Array(vec) => Retained::cast_unchecked(values_to_ns_array(vec)?),
             Value::Object(obj) => {
                 let mut keys: Vec<Retained<NSString>> = Vec::new();
                 let mut values: Vec<Retained<NSObject>> = Vec::new();
@@ -54,6 +48,12 @@ fn value_to_nsobject(value: Value) -> Result<Retained<NSObject>, ScriptInputConv
 
 pub(crate) fn values_vec_to_ns_array<I: IntoIterator<Item = Value>>(
     values: I,
+) -> Result<Retained<NSArray>, ScriptInputConversionError> {
+    values_to_ns_array(values).inspect_err(|error| conversion_failed("input", error))
+}
+
+fn values_to_ns_array<I: IntoIterator<Item = Value>>(
+    values: I,
 ) -> Result<Retained<NSArray>, ScriptInputConversionError> {
     let mut vec: Vec<Retained<NSObject>> = Vec::new();
 
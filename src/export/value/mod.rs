@@ -1,4 +1,9 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[cfg(target_os = "macos")]
 pub(crate) mod input;
+#[cfg(target_os = "macos")]
 pub(crate) mod output;
 
 /// [`serde_json::Value`] from [`serde_json`].
@@ -11,3 +16,31 @@ pub type Map<K, V> = serde_json::Map<K, V>;
 pub use serde_json::from_value;
 /// [`serde_json::to_value`] from [`serde_json`].
 pub use serde_json::to_value;
+
+/// Error happening when converting execution result to [`Value`].
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScriptOutputConversionError {
+    #[error("string expected, but none found")]
+    StringExpectedButNoneFound,
+    #[error("date expected, but none found")]
+    DateExpectedButNoneFound,
+    #[error("string expected, but {0} found")]
+    StringExpectedButValueFound(String),
+    #[error("unexpected typed value: `{0}`")]
+    UnpexpectedTypedValue(String),
+    #[error("unkndown descriptor type: `{0}`")]
+    UnknownDescriptorType(String),
+    #[error("descriptor not found at index: `{0}`")]
+    DescriptorNotFoundAtIndex(isize),
+    #[error("infinite float cannot be converted: `{0}`")]
+    InfiniteFloat(String),
+    #[error("url expected, but none found")]
+    UrlExpectedButNoneFound,
+}
+
+/// Error happening when converting arguments to the format compatible with `OSAScript`.
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScriptInputConversionError {
+    #[error("number conversion error: `{0}`")]
+    NumberConversionError(String),
+}
@@ -1,28 +1,8 @@
-use super::{Map, Value};
+use super::{Map, ScriptOutputConversionError, Value};
+use crate::trace::conversion_failed;
 use objc2::{msg_send, rc::Retained};
 use objc2_foundation::{NSAppleEventDescriptor, NSInteger};
 use serde_json::Number;
-use thiserror::Error;
-
-#[derive(Error, Debug, PartialEq, Eq)]
-pub enum ScriptOutputConversionError {
-    #[error("string expected, but none found")]
-    StringExpectedButNoneFound,
-    #[error("date expected, but none found")]
-    DateExpectedButNoneFound,
-    #[error("string expected, but {0} found")]
-    StringExpectedButValueFound(String),
-    #[error("unexpected typed value: `{0}`")]
-    UnpexpectedTypedValue(String),
-    #[error("unkndown descriptor type: `{0}`")]
-    UnknownDescriptorType(String),
-    #[error("descriptor not found at index: `{0}`")]
-    DescriptorNotFoundAtIndex(isize),
-    #[error("infinite float cannot be converted: `{0}`")]
-    InfiniteFloat(String),
-    #[error("url expected, but none found")]
-    UrlExpectedButNoneFound,
-}
 
 type FourCharCode = u32;
 
@@ -47,10 +27,7 @@ fn add_special_key_to_map_if_defined(
     key: &str,
 ) -> Result<(), ScriptOutputConversionError> {
     if let Some(val_descriptor) = get_descriptor_for_keyword(descriptor, keyword) {
-        map.insert(
-            key.into(),
-            get_value_from_ns_apple_event_descriptor(val_descriptor)?,
-        );
+        map.insert(key.into(), descriptor_to_value(val_descriptor)?);
     }
     Ok(())
 }
@@ -100,6 +77,12 @@ fn four_char_code_to_string(t: FourCharCode) -> String {
 
 pub(crate) fn get_value_from_ns_apple_event_descriptor(
     descriptor: Retained<NSAppleEventDescriptor>,
+) -> Result<Value, ScriptOutputConversionError> {
+    descriptor_to_value(descriptor).inspect_err(|error| conversion_failed("output", error))
+}
+
+fn descriptor_to_value(
+    descriptor: Retained<NSAppleEventDescriptor>,
 ) -> Result<Value, ScriptOutputConversionError> {
     Ok(match get_descriptor_type(&descriptor) {
         DESC_TYPE_STRING => Value::String(
@@ -195,7 +178,7 @@ fn get_nested_ns_apple_event_descriptor_value(
     descriptor: &Retained<NSAppleEventDescriptor>,
     index: NSInteger,
 ) -> Result<Value, ScriptOutputConversionError> {
-    get_value_from_ns_apple_event_descriptor(unsafe { descriptor.descriptorAtIndex(index) }.ok_or(
+    descriptor_to_value(unsafe { descriptor.descriptorAtIndex(index) }.ok_or(
         ScriptOutputConversionError::DescriptorNotFoundAtIndex(index),
     )?)
 }
@@ -203,7 +186,6 @@ fn get_nested_ns_apple_event_descriptor_value(
 #[cfg(test)]
 mod test {
     use super::super::super::script::{Language, Script};
-    use super::super::super::value::output::ScriptOutputConversionError;
     use super::*;
     use objc2::AllocAnyThread;
     use objc2_foundation::NSAppleEventDescriptor;
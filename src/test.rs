@@ -4,8 +4,6 @@ mod export;
 pub use export::*;
 
 #[macro_use]
-extern crate libtest_mimic_collect;
+extern crate osakit;
 
-pub fn main() {
-    libtest_mimic_collect::TestCollection::run();
-}
+osakit::harness!();